@@ -0,0 +1,473 @@
+//! Typed client for the pool server's REST endpoints.
+//!
+//! The pool reports most lookups that come up empty as a 200 with a `Failed to get ...`
+//! body rather than an error status, so each method turns those into an `ApiError`
+//! variant the commands can match on.
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use reqwest::StatusCode;
+use solana_sdk::{
+    hash::Hash,
+    pubkey::Pubkey,
+    signer::{Signer, SignerError},
+    transaction::Transaction,
+};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::claim_stake_rewards::StakeAccount;
+use crate::http::{self, EndpointClass, HttpError, HttpRequest};
+use crate::signer::Wallet;
+
+/// Seconds between claims of mining rewards
+const CLAIM_INTERVAL: u64 = 1800;
+
+#[derive(Debug)]
+pub enum ApiError {
+    /// The pool has no miner account for this wallet
+    NotSignedUp,
+    /// The wallet has no stake account for the requested token
+    NoStakeAccount,
+    RateLimited {
+        retry_after: Option<Duration>,
+    },
+    /// A 5xx from the pool, after retrying where allowed
+    Server {
+        status: StatusCode,
+        message: String,
+    },
+    /// The pool answered a claim or transaction with something other than success
+    Rejected(String),
+    /// A body the client can't make sense of
+    InvalidResponse {
+        endpoint: &'static str,
+        body: String,
+    },
+    /// The request didn't get an answer: timeouts, connection errors, an open breaker
+    Http(HttpError),
+    /// The wallet couldn't sign the request, or its remote signer refused to
+    Signer(SignerError),
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::NotSignedUp => write!(f, "this wallet is not signed up with the pool"),
+            ApiError::NoStakeAccount => write!(f, "no stake account found"),
+            ApiError::RateLimited {
+                retry_after: Some(after),
+            } => write!(
+                f,
+                "rate limited by the pool, try again in {}s",
+                after.as_secs().max(1)
+            ),
+            ApiError::RateLimited { retry_after: None } => {
+                write!(f, "rate limited by the pool, try again shortly")
+            }
+            ApiError::Server { status, message } if message.is_empty() => {
+                write!(f, "pool server error ({})", status)
+            }
+            ApiError::Server { status, message } => {
+                write!(f, "pool server error ({}): {}", status, message)
+            }
+            ApiError::Rejected(message) => write!(f, "the pool rejected the request: {}", message),
+            ApiError::InvalidResponse { endpoint, body } => {
+                write!(f, "unexpected response from {}: {:?}", endpoint, body)
+            }
+            ApiError::Http(e) => write!(f, "{}", e),
+            ApiError::Signer(e) => write!(f, "failed to sign: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<SignerError> for ApiError {
+    fn from(e: SignerError) -> Self {
+        ApiError::Signer(e)
+    }
+}
+
+impl From<HttpError> for ApiError {
+    fn from(e: HttpError) -> Self {
+        match e {
+            HttpError::RateLimited { retry_after, .. } => ApiError::RateLimited { retry_after },
+            HttpError::Status { status, body, .. } if status.is_server_error() => {
+                ApiError::Server {
+                    status,
+                    message: body,
+                }
+            }
+            e => ApiError::Http(e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignupStatus {
+    SignedUp,
+    AlreadySignedUp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClaimStatus {
+    Queued,
+    AlreadyQueued,
+    /// Mining rewards can only be claimed once per `CLAIM_INTERVAL`
+    TooSoon {
+        wait: Duration,
+    },
+}
+
+/// One pool server, addressed by `host[:port]` as given to `--url`.
+pub struct PoolApi {
+    base: String,
+}
+
+impl PoolApi {
+    pub fn new(url: &str, unsecure: bool) -> Self {
+        let scheme = if unsecure { "http" } else { "https" };
+        PoolApi {
+            base: format!("{}://{}", scheme, url),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base, path)
+    }
+
+    async fn get(&self, path: &str, class: EndpointClass) -> Result<String, ApiError> {
+        let url = self.url(path);
+        let res = http::client().get(url.as_str()).class(class).send().await?;
+        Ok(res.text(&url)?)
+    }
+
+    async fn post(&self, request: HttpRequest, url: &str) -> Result<String, ApiError> {
+        Ok(request.send().await?.text(url)?)
+    }
+
+    /// Posts a partially signed transaction for the pool to sign and send.
+    async fn post_transaction(&self, path: String, tx: &Transaction) -> Result<(), ApiError> {
+        let url = self.url(&path);
+        let body = bincode::serialize(tx).expect("transactions always serialize");
+        let request = http::client()
+            .post(url.as_str())
+            .body(BASE64_STANDARD.encode(body));
+        match self.post(request, &url).await?.as_str() {
+            "SUCCESS" => Ok(()),
+            other => Err(ApiError::Rejected(other.to_string())),
+        }
+    }
+
+    pub async fn timestamp(&self) -> Result<u64, ApiError> {
+        let body = self.get("/timestamp", EndpointClass::Auth).await?;
+        body.parse().map_err(|_| invalid("/timestamp", body))
+    }
+
+    pub async fn latest_blockhash(&self) -> Result<Hash, ApiError> {
+        let body = self.get("/latest-blockhash", EndpointClass::Auth).await?;
+        BASE64_STANDARD
+            .decode(&body)
+            .ok()
+            .and_then(|bytes| bincode::deserialize(&bytes).ok())
+            .ok_or_else(|| invalid("/latest-blockhash", body))
+    }
+
+    pub async fn pool_authority(&self) -> Result<Pubkey, ApiError> {
+        let body = self
+            .get("/pool/authority/pubkey", EndpointClass::Read)
+            .await?;
+        Pubkey::from_str(&body).map_err(|_| invalid("/pool/authority/pubkey", body))
+    }
+
+    pub async fn fee_payer(&self) -> Result<Pubkey, ApiError> {
+        let body = self
+            .get("/pool/fee_payer/pubkey", EndpointClass::Read)
+            .await?;
+        Pubkey::from_str(&body).map_err(|_| invalid("/pool/fee_payer/pubkey", body))
+    }
+
+    /// ORE in the wallet, or None if it has no ORE token account yet.
+    pub async fn wallet_balance(&self, pubkey: &Pubkey) -> Result<Option<f64>, ApiError> {
+        let path = format!("/miner/balance?pubkey={}", pubkey);
+        let body = self.get(&path, EndpointClass::Read).await?;
+        optional_amount("/miner/balance", body)
+    }
+
+    /// Balance of `mint` in the wallet, or None if it has no token account for it.
+    pub async fn token_balance(
+        &self,
+        pubkey: &Pubkey,
+        mint: &Pubkey,
+    ) -> Result<Option<f64>, ApiError> {
+        let path = format!("/v2/miner/balance?pubkey={}&mint={}", pubkey, mint);
+        let body = self.get(&path, EndpointClass::Read).await?;
+        optional_amount("/v2/miner/balance", body)
+    }
+
+    /// Unclaimed mining rewards.
+    pub async fn rewards(&self, pubkey: &Pubkey) -> Result<f64, ApiError> {
+        let path = format!("/miner/rewards?pubkey={}", pubkey);
+        let body = self.get(&path, EndpointClass::Read).await?;
+        amount("/miner/rewards", body, ApiError::NotSignedUp)
+    }
+
+    /// ORE delegated to the pool with the legacy stake account.
+    pub async fn stake(&self, pubkey: &Pubkey) -> Result<f64, ApiError> {
+        let path = format!("/miner/stake?pubkey={}", pubkey);
+        let body = self.get(&path, EndpointClass::Read).await?;
+        amount("/miner/stake", body, ApiError::NoStakeAccount)
+    }
+
+    /// Tokens staked in a v1 boost account.
+    pub async fn boost_stake(&self, pubkey: &Pubkey, mint: &Pubkey) -> Result<f64, ApiError> {
+        let path = format!("/miner/boost/stake?pubkey={}&mint={}", pubkey, mint);
+        let body = self.get(&path, EndpointClass::Read).await?;
+        amount("/miner/boost/stake", body, ApiError::NoStakeAccount)
+    }
+
+    /// Tokens staked in a v2 boost account.
+    pub async fn boost_stake_v2(&self, pubkey: &Pubkey, mint: &Pubkey) -> Result<f64, ApiError> {
+        let path = format!("/v2/miner/boost/stake?pubkey={}&mint={}", pubkey, mint);
+        let body = self.get(&path, EndpointClass::Read).await?;
+        amount("/v2/miner/boost/stake", body, ApiError::NoStakeAccount)
+    }
+
+    pub async fn stake_accounts(&self, pubkey: &Pubkey) -> Result<Vec<StakeAccount>, ApiError> {
+        let path = format!("/v2/miner/boost/stake-accounts?pubkey={}", pubkey);
+        let body = self.get(&path, EndpointClass::Read).await?;
+        if body.starts_with(LOOKUP_FAILED) {
+            return Err(ApiError::NoStakeAccount);
+        }
+        serde_json::from_str(&body).map_err(|_| invalid("/v2/miner/boost/stake-accounts", body))
+    }
+
+    pub async fn signup(&self, miner: &Pubkey) -> Result<SignupStatus, ApiError> {
+        let url = self.url(&format!("/v2/signup?miner={}", miner));
+        let request = http::client().post(url.as_str()).body("BLANK");
+        match self.post(request, &url).await?.as_str() {
+            "SUCCESS" => Ok(SignupStatus::SignedUp),
+            "EXISTS" => Ok(SignupStatus::AlreadySignedUp),
+            other => Err(ApiError::Rejected(other.to_string())),
+        }
+    }
+
+    /// Queues a claim of `amount` grains of mining rewards to `receiver`, signed by `key`
+    /// over a fresh server timestamp.
+    pub async fn claim(
+        &self,
+        key: &Wallet,
+        receiver: &Pubkey,
+        amount: u64,
+    ) -> Result<ClaimStatus, ApiError> {
+        let timestamp = self.timestamp().await?;
+        let mut signed_msg = vec![];
+        signed_msg.extend(timestamp.to_le_bytes());
+        signed_msg.extend(receiver.to_bytes());
+        signed_msg.extend(amount.to_le_bytes());
+
+        let url = self.url(&format!(
+            "/v2/claim?timestamp={}&receiver_pubkey={}&amount={}",
+            timestamp, receiver, amount
+        ));
+        let request = http::client()
+            .post(url.as_str())
+            .header("Authorization", basic_auth(key, &signed_msg).await?);
+        match self.post(request, &url).await?.as_str() {
+            "SUCCESS" => Ok(ClaimStatus::Queued),
+            "QUEUED" => Ok(ClaimStatus::AlreadyQueued),
+            other => match other.parse::<u64>() {
+                Ok(elapsed) => Ok(ClaimStatus::TooSoon {
+                    wait: Duration::from_secs(CLAIM_INTERVAL.saturating_sub(elapsed)),
+                }),
+                Err(_) => Err(ApiError::Rejected(other.to_string())),
+            },
+        }
+    }
+
+    /// Queues a claim of `amount` grains of staking rewards from the `mint` boost.
+    pub async fn claim_stake_rewards(
+        &self,
+        key: &Wallet,
+        mint: &Pubkey,
+        receiver: &Pubkey,
+        amount: u64,
+    ) -> Result<ClaimStatus, ApiError> {
+        let timestamp = self.timestamp().await?;
+        let mut signed_msg = vec![];
+        signed_msg.extend(timestamp.to_le_bytes());
+        signed_msg.extend(mint.to_bytes());
+        signed_msg.extend(receiver.to_bytes());
+        signed_msg.extend(amount.to_le_bytes());
+
+        let url = self.url(&format!(
+            "/v2/claim-stake-rewards?timestamp={}&mint={}&receiver_pubkey={}&amount={}",
+            timestamp, mint, receiver, amount
+        ));
+        let request = http::client()
+            .post(url.as_str())
+            .header("Authorization", basic_auth(key, &signed_msg).await?);
+        match self.post(request, &url).await?.as_str() {
+            "SUCCESS" => Ok(ClaimStatus::Queued),
+            "QUEUED" => Ok(ClaimStatus::AlreadyQueued),
+            other => Err(ApiError::Rejected(other.to_string())),
+        }
+    }
+
+    pub async fn stake_boost(
+        &self,
+        pubkey: &Pubkey,
+        mint: &Pubkey,
+        amount: u64,
+        tx: &Transaction,
+    ) -> Result<(), ApiError> {
+        let path = format!(
+            "/v2/stake-boost?pubkey={}&mint={}&amount={}",
+            pubkey, mint, amount
+        );
+        self.post_transaction(path, tx).await
+    }
+
+    pub async fn unstake_boost(
+        &self,
+        pubkey: &Pubkey,
+        mint: &Pubkey,
+        amount: u64,
+        tx: &Transaction,
+    ) -> Result<(), ApiError> {
+        let path = format!(
+            "/v2/unstake-boost?pubkey={}&mint={}&amount={}",
+            pubkey, mint, amount
+        );
+        self.post_transaction(path, tx).await
+    }
+
+    /// `init` says the transaction also creates the v2 boost account.
+    pub async fn migrate_boost(
+        &self,
+        pubkey: &Pubkey,
+        mint: &Pubkey,
+        init: bool,
+        tx: &Transaction,
+    ) -> Result<(), ApiError> {
+        let path = format!(
+            "/v2/migrate-boost?pubkey={}&mint={}&init={}",
+            pubkey, mint, init
+        );
+        self.post_transaction(path, tx).await
+    }
+
+    /// Withdraws from the legacy stake account.
+    pub async fn unstake(
+        &self,
+        pubkey: &Pubkey,
+        amount: u64,
+        tx: &Transaction,
+    ) -> Result<(), ApiError> {
+        let path = format!("/unstake?pubkey={}&amount={}", pubkey, amount);
+        self.post_transaction(path, tx).await
+    }
+}
+
+/// How the pool starts the body of a lookup that found nothing
+const LOOKUP_FAILED: &str = "Failed to g";
+
+fn invalid(endpoint: &'static str, body: String) -> ApiError {
+    ApiError::InvalidResponse { endpoint, body }
+}
+
+fn amount(endpoint: &'static str, body: String, missing: ApiError) -> Result<f64, ApiError> {
+    if body.starts_with(LOOKUP_FAILED) {
+        return Err(missing);
+    }
+    body.parse().map_err(|_| invalid(endpoint, body))
+}
+
+fn optional_amount(endpoint: &'static str, body: String) -> Result<Option<f64>, ApiError> {
+    match amount(endpoint, body, ApiError::NoStakeAccount) {
+        Ok(amount) => Ok(Some(amount)),
+        Err(ApiError::NoStakeAccount) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+async fn basic_auth(key: &Wallet, msg: &[u8]) -> Result<String, SignerError> {
+    let sig = key.sign(msg).await?;
+    Ok(format!(
+        "Basic {}",
+        BASE64_STANDARD.encode(format!("{}:{}", key.pubkey(), sig))
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_pool::MockPool;
+    use solana_sdk::signature::Keypair;
+
+    #[tokio::test]
+    async fn maps_pool_failures_to_error_variants() {
+        let pool = MockPool::start().await;
+        let api = PoolApi::new(&pool.url(), true);
+        let pubkey = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+
+        pool.respond("/miner/rewards", 200, "Failed to get miner account");
+        pool.respond("/miner/stake", 200, "Failed to get staker account");
+        pool.respond("/miner/balance", 200, "Failed to get token account balance");
+        pool.respond("/v2/miner/boost/stake", 200, "12.5");
+        pool.respond("/v2/signup", 200, "EXISTS");
+        pool.respond("/pool/authority/pubkey", 200, "not a pubkey");
+        pool.fail("/v2/stake-boost", 1);
+
+        assert!(matches!(
+            api.rewards(&pubkey).await,
+            Err(ApiError::NotSignedUp)
+        ));
+        assert!(matches!(
+            api.stake(&pubkey).await,
+            Err(ApiError::NoStakeAccount)
+        ));
+        assert_eq!(api.wallet_balance(&pubkey).await.unwrap(), None);
+        assert_eq!(api.boost_stake_v2(&pubkey, &mint).await.unwrap(), 12.5);
+        assert_eq!(
+            api.signup(&pubkey).await.unwrap(),
+            SignupStatus::AlreadySignedUp
+        );
+
+        let tx = Transaction::default();
+        assert!(matches!(
+            api.stake_boost(&pubkey, &mint, 1, &tx).await,
+            Err(ApiError::Server {
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                ..
+            })
+        ));
+        assert!(matches!(
+            api.pool_authority().await,
+            Err(ApiError::InvalidResponse {
+                endpoint: "/pool/authority/pubkey",
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn reports_time_left_until_the_next_claim() {
+        let pool = MockPool::start().await;
+        pool.respond("/v2/claim", 200, "1200");
+        let api = PoolApi::new(&pool.url(), true);
+
+        let status = api
+            .claim(&Keypair::new().into(), &Pubkey::new_unique(), 1)
+            .await
+            .unwrap();
+        assert_eq!(
+            status,
+            ClaimStatus::TooSoon {
+                wait: Duration::from_secs(600)
+            }
+        );
+    }
+}
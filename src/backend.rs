@@ -0,0 +1,570 @@
+use chrono::prelude::*;
+use clap::ValueEnum;
+use drillx_2::equix;
+use log::warn;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::{
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+use crate::session::ThreadSubmission;
+
+// Constants for tuning the rayon backend
+const MIN_CHUNK_SIZE: u64 = 3_000_000;
+const MAX_CHUNK_SIZE: u64 = 30_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// Core-pinned drillx_2::get_hashes_with_memory loop
+    Drillx,
+    /// Core-pinned equix loop using the compiled runtime where available
+    Equix,
+    /// Chunked nonce ranges on a rayon thread pool
+    Rayon,
+}
+
+impl BackendKind {
+    pub fn build(self) -> Box<dyn HashBackend> {
+        match self {
+            BackendKind::Drillx => Box::new(DrillxBackend),
+            BackendKind::Equix => Box::<EquixBackend>::default(),
+            BackendKind::Rayon => Box::<RayonBackend>::default(),
+        }
+    }
+}
+
+/// Receives improving solutions from the hashing threads.
+pub trait SolutionSink: Sync {
+    /// Called with each improved solution. Returning false stops the job.
+    fn submit(&self, submission: ThreadSubmission) -> bool;
+
+    /// Polled by the hashing threads between nonces.
+    fn should_stop(&self) -> bool {
+        false
+    }
+}
+
+pub struct HashJob<'a> {
+    pub challenge: [u8; 32],
+    pub nonce_range: Range<u64>,
+    pub deadline: Instant,
+    pub threads: u32,
+    pub core_offset: u32,
+    pub sink: &'a dyn SolutionSink,
+}
+
+/// Difficulties at or above the last bucket are counted in it
+pub const HISTOGRAM_BUCKETS: usize = 32;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HashStats {
+    pub hashes: u64,
+    pub best: ThreadSubmission,
+    pub elapsed: Duration,
+    /// The sink asked the job to stop before the deadline
+    pub stopped: bool,
+    /// Number of hashes found at each difficulty
+    pub histogram: [u64; HISTOGRAM_BUCKETS],
+    /// Equix instances built with the compiled and interpreted runtimes, where the backend
+    /// builds them itself
+    pub equix_compiled: u64,
+    pub equix_interpreted: u64,
+}
+
+impl HashStats {
+    fn record(&mut self, difficulty: u32) {
+        self.hashes += 1;
+        self.histogram[(difficulty as usize).min(HISTOGRAM_BUCKETS - 1)] += 1;
+    }
+
+    fn merge(&mut self, other: HashStats) {
+        self.hashes += other.hashes;
+        if other.best.difficulty > self.best.difficulty {
+            self.best = other.best;
+        }
+        self.stopped |= other.stopped;
+        for (bucket, count) in self.histogram.iter_mut().zip(other.histogram) {
+            *bucket += count;
+        }
+        self.equix_compiled += other.equix_compiled;
+        self.equix_interpreted += other.equix_interpreted;
+    }
+}
+
+pub trait HashBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Hashes `job.nonce_range` until the deadline, streaming improvements to `job.sink`.
+    fn hash(&self, job: &HashJob) -> HashStats;
+}
+
+fn core_ids(threads: u32, core_offset: u32) -> Vec<Option<core_affinity::CoreId>> {
+    let cores = core_affinity::get_core_ids().unwrap_or_default();
+    (0..threads)
+        .map(|i| cores.get((i + core_offset) as usize).copied())
+        .collect()
+}
+
+pub struct DrillxBackend;
+
+impl HashBackend for DrillxBackend {
+    fn name(&self) -> &'static str {
+        "drillx"
+    }
+
+    fn hash(&self, job: &HashJob) -> HashStats {
+        let hash_timer = Instant::now();
+        let stop = AtomicBool::new(false);
+        let nonces_per_thread = 10_000;
+
+        let mut stats = std::thread::scope(|s| {
+            let handles = core_ids(job.threads.max(1), job.core_offset)
+                .into_iter()
+                .enumerate()
+                .map(|(i, core_id)| {
+                    let stop = &stop;
+                    s.spawn(move || {
+                        let mut memory = equix::SolverMemory::new();
+                        if let Some(core_id) = core_id {
+                            let _ = core_affinity::set_for_current(core_id);
+                        }
+
+                        let mut nonce = job.nonce_range.start + (nonces_per_thread * i as u64);
+                        let mut stats = HashStats::default();
+
+                        loop {
+                            if stop.load(Ordering::Relaxed) || job.sink.should_stop() {
+                                stats.stopped = true;
+                                break;
+                            }
+
+                            // Create hash
+                            for hx in drillx_2::get_hashes_with_memory(
+                                &mut memory,
+                                &job.challenge,
+                                &nonce.to_le_bytes(),
+                            ) {
+                                let difficulty = hx.difficulty();
+                                stats.record(difficulty);
+                                if difficulty.gt(&7) && difficulty.gt(&stats.best.difficulty) {
+                                    stats.best = ThreadSubmission {
+                                        nonce,
+                                        difficulty,
+                                        d: hx.d,
+                                    };
+                                    if !job.sink.submit(stats.best) {
+                                        stop.store(true, Ordering::Relaxed);
+                                    }
+                                }
+                            }
+
+                            // Exit if processed nonce range
+                            if nonce >= job.nonce_range.end {
+                                break;
+                            }
+
+                            if nonce % 100 == 0
+                                && Instant::now() >= job.deadline
+                                && stats.best.difficulty.ge(&8)
+                            {
+                                break;
+                            }
+
+                            // Increment nonce
+                            nonce += 1;
+                        }
+
+                        stats
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let mut stats = HashStats::default();
+            for h in handles {
+                if let Ok(thread_stats) = h.join() {
+                    stats.merge(thread_stats);
+                }
+            }
+            stats
+        });
+
+        stats.elapsed = hash_timer.elapsed();
+        stats
+    }
+}
+
+pub struct EquixBackend {
+    /// Nonces hashed before the first timing check, used to estimate the hash rate
+    pub nonce_init_interval: u64,
+}
+
+impl Default for EquixBackend {
+    fn default() -> Self {
+        EquixBackend {
+            nonce_init_interval: 100,
+        }
+    }
+}
+
+impl HashBackend for EquixBackend {
+    fn name(&self) -> &'static str {
+        "equix"
+    }
+
+    fn hash(&self, job: &HashJob) -> HashStats {
+        let hash_timer = Instant::now();
+        let threads = job.threads.max(1);
+        let nonce_range = job.nonce_range.clone();
+        let cutoff_timestamp_us: i128 = Local::now().timestamp_micros() as i128
+            + job
+                .deadline
+                .saturating_duration_since(hash_timer)
+                .as_micros() as i128;
+        let nonces_per_thread = (nonce_range.end - nonce_range.start)
+            .saturating_div(2)
+            .saturating_div(threads as u64);
+        let nonce_init_interval = self.nonce_init_interval;
+
+        // Best difficulty found by any thread so far, only improvements on it are submitted
+        let best_difficulty = AtomicU32::new(0);
+
+        let mut stats = std::thread::scope(|s| {
+            let handles = core_ids(threads, job.core_offset)
+                .into_iter()
+                .enumerate()
+                .map(|(thread_number, core_id)| {
+                    let best_difficulty = &best_difficulty;
+                    let nonce_range = nonce_range.clone();
+                    std::thread::Builder::new()
+                        .name(format!(
+                            "ore_hq_cl_{}",
+                            thread_number as u32 + job.core_offset
+                        ))
+                        .stack_size(256 * 1024) // Attempt to reduce memory requirements for each thread
+                        .spawn_scoped(s, move || {
+                            let mut memory = equix::SolverMemory::new();
+                            if let Some(core_id) = core_id {
+                                let _ = core_affinity::set_for_current(core_id);
+                            }
+                            let first_nonce =
+                                nonce_range.start + (nonces_per_thread * thread_number as u64);
+                            let mut nonce = first_nonce;
+                            let mut nonces_current_interval = nonce_init_interval * 2;
+                            let mut cutoff_nonce = nonce + nonces_current_interval;
+                            let mut current_nonces_per_us: f64;
+                            let loop_start_us = Local::now().timestamp_micros();
+                            let mut current_timestamp_us: i64;
+
+                            let left = (cutoff_timestamp_us - loop_start_us as i128) / 1_000_000;
+                            let mut more_than_5_secs_left = 1;
+                            if left < 5 {
+                                more_than_5_secs_left = 0;
+                            }
+
+                            let mut stats = HashStats::default();
+                            let mut this_threads_difficulty = 6;
+                            let mut difficulty: u32;
+                            let mut seed = [0_u8; 40];
+                            let mut equix_builder = equix::EquiXBuilder::new();
+                            let equix_rt = equix_builder.runtime(equix::RuntimeOption::TryCompile);
+                            let mut nonce_le_bytes: [u8; 8];
+                            seed[00..32].copy_from_slice(&job.challenge);
+                            loop {
+                                nonce_le_bytes = nonce.to_le_bytes();
+                                seed[32..40].copy_from_slice(&nonce_le_bytes);
+                                if let Ok(equix) = equix_rt.build(&seed) {
+                                    if equix.runtime() == equix::Runtime::Compiled {
+                                        stats.equix_compiled += 1;
+                                    } else {
+                                        stats.equix_interpreted += 1;
+                                    }
+                                    let solutions = equix.solve_with_memory(&mut memory);
+                                    for solution in solutions {
+                                        let digest = solution.to_bytes();
+                                        let hash = drillx_2::hashv(&digest, &nonce_le_bytes);
+
+                                        // Determine the number of leading zeroes
+                                        difficulty = 0;
+                                        for byte in hash {
+                                            if byte == 0 {
+                                                difficulty = difficulty.wrapping_add(8);
+                                            } else {
+                                                difficulty =
+                                                    difficulty.wrapping_add(byte.leading_zeros());
+                                                break;
+                                            }
+                                        }
+
+                                        stats.record(difficulty);
+
+                                        if difficulty > this_threads_difficulty {
+                                            this_threads_difficulty = difficulty;
+                                            stats.best = ThreadSubmission {
+                                                nonce,
+                                                difficulty,
+                                                d: digest,
+                                            };
+                                            // A higher difficulty has been found since the last difficulty was sent to server
+                                            if best_difficulty
+                                                .fetch_max(difficulty, Ordering::Relaxed)
+                                                < difficulty
+                                                && !job.sink.submit(stats.best)
+                                            {
+                                                stats.stopped = true;
+                                            }
+                                        }
+                                    }
+                                }
+
+                                // Increment nonce & process only when we reach the cutoff_nonce
+                                nonce = nonce.wrapping_add(1);
+                                // Checked every nonce, the timing checkpoints below can be most of a pass apart
+                                if stats.stopped || job.sink.should_stop() {
+                                    stats.stopped = true;
+                                    break;
+                                }
+                                if nonce >= cutoff_nonce {
+                                    current_timestamp_us = Local::now().timestamp_micros();
+
+                                    // Determine current nonces per µs for the duration so far
+                                    current_nonces_per_us = (nonce - first_nonce) as f64
+                                        / (current_timestamp_us as i128 - loop_start_us as i128)
+                                            as f64;
+
+                                    if more_than_5_secs_left > 0 {
+                                        // called before the end of the mining pass - to target 5s before cutoff timestamp to ensure accurate finishing time
+                                        nonces_current_interval = ((cutoff_timestamp_us
+                                            - current_timestamp_us as i128
+                                            - 5_000_000)
+                                            as f64
+                                            * current_nonces_per_us)
+                                            as u64;
+                                    } else {
+                                        // called at 5s before the end of the mining pass - to target 2.5ms before cutoff timestamp
+                                        nonces_current_interval = ((cutoff_timestamp_us
+                                            - current_timestamp_us as i128
+                                            - 2_500)
+                                            as f64
+                                            * current_nonces_per_us)
+                                            as u64;
+                                    }
+                                    more_than_5_secs_left -= 1;
+
+                                    // Set the number of the cutoff nonce where the next check for completion will take place
+                                    cutoff_nonce = nonce.wrapping_add(nonces_current_interval);
+
+                                    // Exit loop if <1 nonce to get to cutoff
+                                    if nonces_current_interval < 1 {
+                                        break;
+                                    }
+                                    // Exit if processed nonce range
+                                    if nonce >= nonce_range.end {
+                                        break;
+                                    }
+                                }
+                            }
+
+                            stats
+                        })
+                        .expect("Failed to spawn mining thread")
+                })
+                .collect::<Vec<_>>();
+
+            let mut stats = HashStats::default();
+            for h in handles {
+                if let Ok(thread_stats) = h.join() {
+                    stats.merge(thread_stats);
+                }
+            }
+            stats
+        });
+
+        stats.elapsed = hash_timer.elapsed();
+        stats
+    }
+}
+
+#[derive(Default)]
+pub struct RayonBackend {
+    /// Kept between jobs with the thread count it was built for
+    pool: Mutex<Option<(usize, Arc<rayon::ThreadPool>)>>,
+}
+
+impl RayonBackend {
+    /// The pool for `threads`, rebuilt only when the thread count changes. `None` if it
+    /// can't be built, in which case rayon's global pool does the work.
+    fn pool(&self, threads: usize) -> Option<Arc<rayon::ThreadPool>> {
+        let mut cached = self.pool.lock().unwrap();
+        if let Some((built_for, pool)) = cached.as_ref() {
+            if *built_for == threads {
+                return Some(pool.clone());
+            }
+        }
+        match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+            Ok(pool) => {
+                let pool = Arc::new(pool);
+                *cached = Some((threads, pool.clone()));
+                Some(pool)
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to build a pool of {} threads, using the global pool: {}",
+                    threads, e
+                );
+                None
+            }
+        }
+    }
+}
+
+fn calculate_dynamic_chunk_size(nonce_range: &Range<u64>, threads: usize) -> u64 {
+    let range_size = nonce_range.end - nonce_range.start;
+    let chunks_per_thread = 5;
+    let ideal_chunk_size = range_size / (threads * chunks_per_thread) as u64;
+
+    ideal_chunk_size.clamp(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE)
+}
+
+impl HashBackend for RayonBackend {
+    fn name(&self) -> &'static str {
+        "rayon"
+    }
+
+    fn hash(&self, job: &HashJob) -> HashStats {
+        let threads = job.threads.max(1) as usize;
+        let nonce_range = &job.nonce_range;
+        let stop_signal = AtomicBool::new(false);
+
+        let chunk_size = calculate_dynamic_chunk_size(nonce_range, threads);
+        let start_time = Instant::now();
+
+        let work = || -> Vec<HashStats> {
+            (0..threads)
+                .into_par_iter()
+                .map(|core_id| {
+                    let mut memory = equix::SolverMemory::new();
+                    let core_range_size = (nonce_range.end - nonce_range.start) / threads as u64;
+                    let core_start = nonce_range.start + core_id as u64 * core_range_size;
+                    let core_end = if core_id == threads - 1 {
+                        nonce_range.end
+                    } else {
+                        core_start + core_range_size
+                    };
+
+                    let mut core_best = HashStats::default();
+
+                    'outer: for chunk_start in (core_start..core_end).step_by(chunk_size as usize) {
+                        let chunk_end = (chunk_start + chunk_size).min(core_end);
+                        for nonce in chunk_start..chunk_end {
+                            if Instant::now() >= job.deadline {
+                                break 'outer;
+                            }
+
+                            if stop_signal.load(Ordering::Relaxed) || job.sink.should_stop() {
+                                core_best.stopped = true;
+                                break 'outer;
+                            }
+
+                            for hx in drillx_2::get_hashes_with_memory(
+                                &mut memory,
+                                &job.challenge,
+                                &nonce.to_le_bytes(),
+                            ) {
+                                let difficulty = hx.difficulty();
+                                core_best.record(difficulty);
+
+                                if difficulty > core_best.best.difficulty {
+                                    core_best.best = ThreadSubmission {
+                                        nonce,
+                                        difficulty,
+                                        d: hx.d,
+                                    };
+                                    if !job.sink.submit(core_best.best) {
+                                        stop_signal.store(true, Ordering::Relaxed);
+                                    }
+                                }
+                            }
+
+                            if nonce % 100 == 0
+                                && Instant::now() >= job.deadline
+                                && core_best.best.difficulty >= 8
+                            {
+                                break 'outer;
+                            }
+                        }
+                    }
+
+                    core_best
+                })
+                .collect()
+        };
+        let results = match self.pool(threads) {
+            Some(pool) => pool.install(work),
+            None => work(),
+        };
+
+        let mut stats = HashStats::default();
+        for result in results {
+            stats.merge(result);
+        }
+        stats.elapsed = start_time.elapsed();
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    struct StopLater {
+        stop: AtomicBool,
+    }
+
+    impl SolutionSink for StopLater {
+        fn submit(&self, _submission: ThreadSubmission) -> bool {
+            true
+        }
+
+        fn should_stop(&self) -> bool {
+            self.stop.load(Ordering::Relaxed)
+        }
+    }
+
+    #[test]
+    fn equix_stops_mid_pass_well_before_the_deadline() {
+        let sink = StopLater {
+            stop: AtomicBool::new(false),
+        };
+        let job = HashJob {
+            challenge: [3; 32],
+            nonce_range: 0..u64::MAX / 2,
+            deadline: Instant::now() + Duration::from_secs(60),
+            threads: 2,
+            core_offset: 0,
+            sink: &sink,
+        };
+
+        let started = Instant::now();
+        let stats = std::thread::scope(|s| {
+            s.spawn(|| {
+                std::thread::sleep(Duration::from_millis(500));
+                sink.stop.store(true, Ordering::Relaxed);
+            });
+            EquixBackend::default().hash(&job)
+        });
+
+        assert!(stats.stopped);
+        assert!(stats.hashes > 0);
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "{:?}",
+            started.elapsed()
+        );
+    }
+}
@@ -1,215 +1,148 @@
-use solana_sdk::{signature::Keypair, signer::Signer};
-
-use crate::claim_stake_rewards::StakeAccount;
+use serde::Serialize;
+use solana_sdk::{pubkey::Pubkey, signer::Signer};
+use std::str::FromStr;
+
+use crate::api::{ApiError, PoolApi};
+use crate::error::Error;
+use crate::output;
+use crate::signer::Wallet;
+use crate::textln;
 // use std::collections::HashMap;
 // use tokio::time::{sleep, Duration};
 
-pub async fn balance(key: &Keypair, url: String, unsecure: bool) {
-    let base_url = url;
-    let client = reqwest::Client::new();
+#[derive(Debug, Serialize)]
+struct BalanceReport {
+    wallet: String,
+    signed_up: bool,
+    rewards: f64,
+    /// None when the wallet has no stake account
+    staked: Option<f64>,
+    stake_accounts: Vec<StakeRewards>,
+    tokens: Vec<TokenBalance>,
+}
 
-    let url_prefix = if unsecure {
-        "http".to_string()
-    } else {
-        "https".to_string()
-    };
+#[derive(Debug, Serialize)]
+struct StakeRewards {
+    mint: String,
+    rewards: f64,
+}
 
-    println!("Wallet: {}", key.pubkey().to_string());
+#[derive(Debug, Serialize)]
+struct TokenBalance {
+    mint: String,
+    label: &'static str,
+    wallet: f64,
+    boosted: f64,
+}
 
-    // Fetch Wallet (Stakeable) Balance
-    let balance_response = client
-        .get(format!(
-            "{}://{}/miner/balance?pubkey={}",
-            url_prefix,
-            base_url,
-            key.pubkey().to_string()
-        ))
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
+pub async fn balance(key: &Wallet, url: String, unsecure: bool) -> Result<(), Error> {
+    let api = PoolApi::new(&url, unsecure);
+    let pubkey = key.pubkey();
 
-    let _balance = match balance_response.parse::<f64>() {
-        Ok(b) => b,
-        Err(_) => 0.0,
-    };
+    textln!("Wallet: {}", pubkey);
 
     // Fetch Unclaimed Rewards
-    let rewards_response = client
-        .get(format!(
-            "{}://{}/miner/rewards?pubkey={}",
-            url_prefix,
-            base_url,
-            key.pubkey().to_string()
-        ))
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-
-    let rewards = match rewards_response.parse::<f64>() {
-        Ok(r) => r,
-        Err(_) => 0.0,
+    let (signed_up, rewards) = match api.rewards(&pubkey).await {
+        Ok(rewards) => (true, rewards),
+        Err(ApiError::NotSignedUp) => {
+            textln!("  This wallet is not signed up with the pool.");
+            (false, 0.0)
+        }
+        Err(e) => return Err(Error::api("Failed to fetch mining rewards", e)),
     };
 
     // Fetch Staked Balance
-    let stake_response = client
-        .get(format!(
-            "{}://{}/miner/stake?pubkey={}",
-            url_prefix,
-            base_url,
-            key.pubkey().to_string()
-        ))
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-
-    let staked_balance = if stake_response.contains("Failed to g") {
-        println!("  Delegated stake balance: No staked account");
-        0.0
-    } else {
-        stake_response.parse::<f64>().unwrap_or(0.0)
+    let staked = match api.stake(&pubkey).await {
+        Ok(staked) => Some(staked),
+        Err(ApiError::NoStakeAccount) => {
+            textln!("  Delegated stake balance: No staked account");
+            None
+        }
+        Err(e) => return Err(Error::api("Failed to fetch staked balance", e)),
     };
 
     // Fetch Unclaimed Stake Rewards
-    let staker_rewards_response = client
-        .get(format!(
-            "{}://{}/v2/miner/boost/stake-accounts?pubkey={}",
-            url_prefix,
-            base_url,
-            key.pubkey().to_string()
-        ))
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-    let stake_accounts: Vec<StakeAccount> = match serde_json::from_str(&staker_rewards_response) {
-        Ok(sa) => {
-            sa
-        },
-        Err(_) => {
-            println!("Failed to parse server stake accounts.");
-            return;
+    let stake_accounts = match api.stake_accounts(&pubkey).await {
+        Ok(sa) => sa,
+        Err(ApiError::NoStakeAccount) => vec![],
+        Err(e) => {
+            return Err(Error::api("Failed to fetch server stake accounts", e))
         }
     };
 
-    println!();
-    println!("Staker Rewards:");
+    textln!();
+    textln!("Staker Rewards:");
     let mut total_staker_rewards = 0.0f64;
+    let mut stake_rewards = vec![];
     for stake_account in stake_accounts {
         let claimable_rewards = stake_account.rewards_balance as f64 / 10f64.powf(ore_api::consts::TOKEN_DECIMALS as f64);
-        println!("  {} - {:.11} ORE", stake_account.mint_pubkey,  claimable_rewards);
+        textln!("  {} - {:.11} ORE", stake_account.mint_pubkey,  claimable_rewards);
         total_staker_rewards += claimable_rewards;
+        stake_rewards.push(StakeRewards {
+            mint: stake_account.mint_pubkey,
+            rewards: claimable_rewards,
+        });
     }
 
-    println!();
-    println!("  Unclaimed Mining Rewards: {:.11} ORE", rewards);
-    println!("  Unclaimed Staker Rewards: {:.11} ORE", total_staker_rewards);
-    println!("  Staked Balance:    {:.11} ORE", staked_balance);
-    println!();
+    textln!();
+    textln!("  Unclaimed Mining Rewards: {:.11} ORE", rewards);
+    textln!("  Unclaimed Staker Rewards: {:.11} ORE", total_staker_rewards);
+    textln!("  Staked Balance:    {:.11} ORE", staked.unwrap_or(0.0));
+    textln!();
 
-    let token_mints = vec![
+    let token_mints = [
         ("oreoU2P8bN6jkk3jbaiVxYnG1dCXcYxwhwyK9jSybcp", "ORE Token"),
         ("DrSS5RM7zUd9qjUEdDaf31vnDUSbCrMto6mjqTrHFifN", "ORE-SOL LP"),
         ("meUwDp23AaxhiNKaQCyJ2EAF2T4oe1gSkEkGXSRVdZb", "ORE-ISC LP"),
-    ];
+    ]
+    .map(|(mint, label)| (Pubkey::from_str(mint).unwrap(), label));
 
-    print!("In Wallet (Stakeable):\n");
+    let mut tokens = vec![];
+    textln!("In Wallet (Stakeable):");
     for (mint, label) in token_mints.iter() {
-        let token_balance =
-            get_token_balance(key, base_url.clone(), unsecure, mint.to_string()).await;
-        println!("  {}: {}", label, token_balance);
+        match api.token_balance(&pubkey, mint).await {
+            Ok(token_balance) => {
+                let token_balance = token_balance.unwrap_or(0.0);
+                textln!("  {}: {}", label, token_balance);
+                tokens.push(TokenBalance {
+                    mint: mint.to_string(),
+                    label,
+                    wallet: token_balance,
+                    boosted: 0.0,
+                });
+            }
+            Err(e) => {
+                let context = format!("Failed to fetch {} wallet balance", label);
+                return Err(Error::api(context, e));
+            }
+        }
     }
-    println!();
-    println!("Boosted:");
-    for (mint, label) in token_mints.iter() {
-        let boosted_token_balance =
-            get_boosted_stake_balance_v2(key, base_url.clone(), unsecure, mint.to_string()).await;
-        println!("  {}: {}", label, boosted_token_balance.max(0.0));
+    textln!();
+    textln!("Boosted:");
+    for ((mint, label), token) in token_mints.iter().zip(tokens.iter_mut()) {
+        match api.boost_stake_v2(&pubkey, mint).await {
+            Ok(boosted) => {
+                textln!("  {}: {}", label, boosted);
+                token.boosted = boosted;
+            }
+            Err(ApiError::NoStakeAccount) => textln!("  {}: 0", label),
+            Err(e) => {
+                let context = format!("Failed to fetch {} boost", label);
+                return Err(Error::api(context, e));
+            }
+        }
     }
-}
-
-pub async fn get_token_balance(key: &Keypair, url: String, unsecure: bool, mint: String) -> f64 {
-    let client = reqwest::Client::new();
-    let url_prefix = if unsecure { "http" } else { "https" };
-
-    let balance_response = client
-        .get(format!(
-            "{}://{}/v2/miner/balance?pubkey={}&mint={}",
-            url_prefix,
-            url,
-            key.pubkey().to_string(),
-            mint
-        ))
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-
-    balance_response.parse::<f64>().unwrap_or(0.0)
-}
-
-pub async fn get_boosted_stake_balance(
-    key: &Keypair,
-    url: String,
-    unsecure: bool,
-    mint: String,
-) -> f64 {
-    let client = reqwest::Client::new();
-    let url_prefix = if unsecure { "http" } else { "https" };
 
-    let balance_response = client
-        .get(format!(
-            "{}://{}/miner/boost/stake?pubkey={}&mint={}",
-            url_prefix,
-            url,
-            key.pubkey().to_string(),
-            mint
-        ))
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-
-    balance_response.parse::<f64>().unwrap_or(-1.0)
-}
-
-pub async fn get_boosted_stake_balance_v2(
-    key: &Keypair,
-    url: String,
-    unsecure: bool,
-    mint: String,
-) -> f64 {
-    let client = reqwest::Client::new();
-    let url_prefix = if unsecure { "http" } else { "https" };
-
-    let balance_response = client
-        .get(format!(
-            "{}://{}/v2/miner/boost/stake?pubkey={}&mint={}",
-            url_prefix,
-            url,
-            key.pubkey().to_string(),
-            mint
-        ))
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-
-    balance_response.parse::<f64>().unwrap_or(-1.0)
+    output::emit(
+        "balance",
+        &BalanceReport {
+            wallet: pubkey.to_string(),
+            signed_up,
+            rewards,
+            staked,
+            stake_accounts: stake_rewards,
+            tokens,
+        },
+    );
+    Ok(())
 }
@@ -0,0 +1,140 @@
+use clap::{arg, Parser};
+use colored::*;
+use solana_sdk::keccak::hashv;
+use std::time::{Duration, Instant};
+
+use crate::backend::{BackendKind, HashJob, HashStats, SolutionSink};
+use crate::session::ThreadSubmission;
+
+#[derive(Debug, Parser)]
+pub struct BenchmarkArgs {
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value = "10",
+        help = "How long to hash for each backend and thread count"
+    )]
+    pub duration: u64,
+    #[arg(
+        long,
+        value_name = "THREADS",
+        value_delimiter = ',',
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Comma separated thread counts to test [default: 1, 2, 4, ... up to all cores]"
+    )]
+    pub threads: Vec<u32>,
+    #[arg(
+        long,
+        value_name = "BACKEND",
+        help = "Only benchmark this backend [default: all]"
+    )]
+    pub backend: Option<BackendKind>,
+}
+
+// Accepts every solution, nothing is sent anywhere
+struct NullSink;
+
+impl SolutionSink for NullSink {
+    fn submit(&self, _submission: ThreadSubmission) -> bool {
+        true
+    }
+}
+
+/// Fixed challenge for benchmark run `n`, so runs are comparable across machines.
+fn synthetic_challenge(n: u64) -> [u8; 32] {
+    hashv(&[b"ore-hq-client benchmark", &n.to_le_bytes()]).to_bytes()
+}
+
+fn default_thread_counts() -> Vec<u32> {
+    let cores = core_affinity::get_core_ids().map_or(1, |c| c.len()) as u32;
+    let mut counts = vec![];
+    let mut threads = 1;
+    while threads < cores {
+        counts.push(threads);
+        threads *= 2;
+    }
+    counts.push(cores);
+    counts
+}
+
+pub fn benchmark(args: &BenchmarkArgs) {
+    let backends = match args.backend {
+        Some(backend) => vec![backend],
+        None => vec![BackendKind::Drillx, BackendKind::Equix, BackendKind::Rayon],
+    };
+    let thread_counts = if args.threads.is_empty() {
+        default_thread_counts()
+    } else {
+        args.threads.clone()
+    };
+    let duration = Duration::from_secs(args.duration);
+
+    println!(
+        "Benchmarking {} backend(s) at {:?} threads for {}s each\n",
+        backends.len(),
+        thread_counts,
+        args.duration
+    );
+
+    let mut run = 0;
+    for kind in backends {
+        let backend = kind.build();
+        for &threads in &thread_counts {
+            let stats = backend.hash(&HashJob {
+                challenge: synthetic_challenge(run),
+                nonce_range: 0..u64::MAX / 2,
+                deadline: Instant::now() + duration,
+                threads,
+                core_offset: 0,
+                sink: &NullSink,
+            });
+            run += 1;
+            print_result(backend.name(), threads, &stats);
+        }
+    }
+}
+
+fn print_result(name: &str, threads: u32, stats: &HashStats) {
+    let secs = stats.elapsed.as_secs_f64();
+    let hashrate = if secs > 0.0 {
+        stats.hashes as f64 / secs
+    } else {
+        0.0
+    };
+    println!(
+        "{} {:>3} threads: {} H/s  ({} hashes in {:.2}s)  best diff: {}",
+        format!("{:<6}", name).blue(),
+        threads,
+        format!("{:>10.1}", hashrate).green(),
+        stats.hashes,
+        secs,
+        stats.best.difficulty,
+    );
+
+    let histogram = stats
+        .histogram
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count > 0)
+        .map(|(difficulty, count)| format!("{}:{}", difficulty, count))
+        .collect::<Vec<_>>()
+        .join(" ");
+    println!(
+        "{}",
+        format!("    difficulty histogram: {}", histogram).dimmed()
+    );
+
+    let builds = stats.equix_compiled + stats.equix_interpreted;
+    if builds > 0 {
+        println!(
+            "{}",
+            format!(
+                "    equix runtime: {} compiled, {} interpreted ({:.2}% fallback)",
+                stats.equix_compiled,
+                stats.equix_interpreted,
+                stats.equix_interpreted as f64 * 100.0 / builds as f64
+            )
+            .dimmed()
+        );
+    }
+}
@@ -1,10 +1,18 @@
-use base64::{prelude::BASE64_STANDARD, Engine};
 use clap::Parser;
 use colored::*;
 use inquire::{InquireError, Text};
-use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+use serde::Serialize;
+use solana_sdk::{pubkey::Pubkey, signer::Signer};
 use spl_token::amount_to_ui_amount;
-use std::{str::FromStr, time::Duration};
+use std::str::FromStr;
+
+use crate::api::{ApiError, ClaimStatus, PoolApi};
+use crate::error::Error;
+use crate::output;
+use crate::signer::Wallet;
+use crate::textln;
+
+const CMD: &str = "claim";
 
 #[derive(Debug, Parser)]
 pub struct ClaimArgs {
@@ -25,72 +33,58 @@ pub struct ClaimArgs {
     pub y: bool,
 }
 
-pub async fn claim(args: ClaimArgs, key: Keypair, url: String, unsecure: bool) {
-    let client = reqwest::Client::new();
-    let url_prefix = if unsecure {
-        "http".to_string()
-    } else {
-        "https".to_string()
-    };
+/// The outcome of a claim, shared with `claim-stake-rewards`.
+#[derive(Debug, Serialize)]
+pub struct ClaimReport {
+    pub receiver: String,
+    pub mint: String,
+    pub amount: f64,
+    /// `queued`, `already_queued` or `cooldown`
+    pub status: &'static str,
+    /// Seconds until the next claim is allowed, when cooling down
+    pub cooldown_seconds: Option<u64>,
+}
+
+pub async fn claim(args: ClaimArgs, key: Wallet, url: String, unsecure: bool) -> Result<(), Error> {
+    let api = PoolApi::new(&url, unsecure);
 
     let receiver_pubkey = match args.receiver_pubkey {
         Some(rpk) => match Pubkey::from_str(&rpk) {
             Ok(pk) => pk,
             Err(_) => {
-                println!("Failed to parse provided receiver pubkey.\nDouble check the provided public key is valid and try again.");
-                return;
+                return Err(Error::Usage("Failed to parse provided receiver pubkey.\nDouble check the provided public key is valid and try again.".to_string()));
             }
         },
         None => key.pubkey(),
     };
 
-    let balance_response = client
-        .get(format!(
-            "{}://{}/miner/balance?pubkey={}",
-            url_prefix,
-            url,
-            receiver_pubkey.to_string()
-        ))
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-
-    let balance = if let Ok(parsed_balance) = balance_response.parse::<f64>() {
-        parsed_balance
-    } else {
-        // If the wallet balance failed to parse
-        println!("\n  Note: A 0.004 ORE fee will be deducted from your claim amount to cover the cost\n  of Token Account Creation. This is a one time fee used to create the ORE Token Account.");
-        0.0
+    let balance = match api.wallet_balance(&receiver_pubkey).await {
+        Ok(Some(balance)) => balance,
+        Ok(None) => {
+            // The receiver has no ORE token account yet
+            textln!("\n  Note: A 0.004 ORE fee will be deducted from your claim amount to cover the cost\n  of Token Account Creation. This is a one time fee used to create the ORE Token Account.");
+            0.0
+        }
+        Err(e) => {
+            return Err(Error::api("Failed to fetch the receiving wallet balance", e))
+        }
     };
 
-    let rewards_response = client
-        .get(format!(
-            "{}://{}/miner/rewards?pubkey={}",
-            url_prefix,
-            url,
-            key.pubkey().to_string()
-        ))
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-
-    let rewards = rewards_response.parse::<f64>().unwrap_or(0.0);
+    let rewards = match api.rewards(&key.pubkey()).await {
+        Ok(rewards) => rewards,
+        Err(e @ ApiError::NotSignedUp) => {
+            return Err(Error::api("There are no rewards to claim", e));
+        }
+        Err(e) => return Err(Error::api("Failed to fetch unclaimed rewards", e)),
+    };
 
-    println!("  Miner Unclaimed Rewards:      {:.11} ORE", rewards);
-    println!("  Receiving Wallet Ore Balance: {:.11} ORE", balance);
+    textln!("  Miner Unclaimed Rewards:      {:.11} ORE", rewards);
+    textln!("  Receiving Wallet Ore Balance: {:.11} ORE", balance);
 
     let minimum_claim_amount = 0.005;
     if rewards < minimum_claim_amount {
-        println!();
-        println!("  You have not reached the required claim limit of 0.005 ORE.");
-        println!("  Keep mining to accumulate more rewards before you can withdraw.");
-        return;
+        textln!();
+        return Err(Error::BelowMinimumClaim);
     }
 
     // Convert balance to grains
@@ -98,18 +92,21 @@ pub async fn claim(args: ClaimArgs, key: Keypair, url: String, unsecure: bool) {
 
     // If balance is zero, inform the user and return to keypair selection
     if balance_grains == 0 {
-        println!("\n  There is no balance to claim.");
-        return;
+        textln!();
+        return Err(Error::NothingToClaim);
     }
 
     let mut claim_amount = args.amount.unwrap_or(rewards);
 
     // Prompt the user for an amount if it's not provided or less than 0.005
+    if output::json() && claim_amount < minimum_claim_amount {
+        return Err(Error::Usage("--amount must be at least 0.005 ORE".to_string()));
+    }
     loop {
         if claim_amount < minimum_claim_amount {
             if claim_amount != 0.0 {
                 // Only show the message if they previously entered an invalid value
-                println!("  Please enter a number above 0.005.");
+                textln!("  Please enter a number above 0.005.");
             }
 
             match Text::new("\n  Enter the amount to claim (minimum 0.005 ORE or 'esc' to cancel):")
@@ -117,24 +114,24 @@ pub async fn claim(args: ClaimArgs, key: Keypair, url: String, unsecure: bool) {
             {
                 Ok(input) => {
                     if input.trim().eq_ignore_ascii_case("esc") {
-                        println!("  Claim operation canceled.");
-                        return;
+                        textln!("  Claim operation canceled.");
+                        return Ok(());
                     }
 
                     claim_amount = match input.trim().parse::<f64>() {
                         Ok(val) if val >= 0.005 => val,
                         _ => {
-                            println!("  Please enter a valid number above 0.005.");
+                            textln!("  Please enter a valid number above 0.005.");
                             continue;
                         }
                     };
                 }
                 Err(InquireError::OperationCanceled) => {
-                    println!("  Claim operation canceled.");
-                    return;
+                    textln!("  Claim operation canceled.");
+                    return Ok(());
                 }
                 Err(_) => {
-                    println!("  Invalid input. Please try again.");
+                    textln!("  Invalid input. Please try again.");
                     continue;
                 }
             }
@@ -149,12 +146,12 @@ pub async fn claim(args: ClaimArgs, key: Keypair, url: String, unsecure: bool) {
 
     // Auto-adjust the claim amount if it exceeds the available balance
     if claim_amount_grains > balance_grains {
-        println!(
+        textln!(
             "  You do not have enough rewards to claim {} ORE.",
             amount_to_ui_amount(claim_amount_grains, ore_api::consts::TOKEN_DECIMALS)
         );
         claim_amount_grains = balance_grains;
-        println!(
+        textln!(
             "  Adjusting claim amount to the maximum available: {} ORE.",
             amount_to_ui_amount(claim_amount_grains, ore_api::consts::TOKEN_DECIMALS)
         );
@@ -162,6 +159,9 @@ pub async fn claim(args: ClaimArgs, key: Keypair, url: String, unsecure: bool) {
 
     // RED TEXT
     if !args.y {
+        if output::json() {
+            return Err(Error::ConfirmationRequired);
+        }
         match Text::new(
             &format!(
                 "  Are you sure you want to claim {} ORE? (Y/n or 'esc' to cancel)",
@@ -174,98 +174,129 @@ pub async fn claim(args: ClaimArgs, key: Keypair, url: String, unsecure: bool) {
         {
             Ok(confirm) => {
                 if confirm.trim().eq_ignore_ascii_case("esc") {
-                    println!("  Claim canceled.");
-                    return;
+                    textln!("  Claim canceled.");
+                    return Ok(());
                 } else if confirm.trim().is_empty() || confirm.trim().to_lowercase() == "y" {
                 } else {
-                    println!("  Claim canceled.");
-                    return;
+                    textln!("  Claim canceled.");
+                    return Ok(());
                 }
             }
             Err(InquireError::OperationCanceled) => {
-                println!("  Claim operation canceled.");
-                return;
+                textln!("  Claim operation canceled.");
+                return Ok(());
             }
             Err(_) => {
-                println!("  Invalid input. Claim canceled.");
-                return;
+                textln!("  Invalid input. Claim canceled.");
+                return Ok(());
             }
         }
     }
 
-    let timestamp = if let Ok(response) = client
-        .get(format!("{}://{}/timestamp", url_prefix, url))
-        .send()
-        .await
-    {
-        if let Ok(ts) = response.text().await {
-            if let Ok(ts) = ts.parse::<u64>() {
-                ts
-            } else {
-                println!("Failed to get timestamp from server, please try again.");
-                return;
-            }
-        } else {
-            println!("Failed to get timestamp from server, please try again.");
-            return;
-        }
-    } else {
-        println!("Failed to get timestamp from server, please try again.");
-        return;
-    };
-
-    println!(
+    textln!(
         "  Sending claim request for {} ORE...",
         amount_to_ui_amount(claim_amount_grains, ore_api::consts::TOKEN_DECIMALS)
     );
 
-    let mut signed_msg = vec![];
-    signed_msg.extend(timestamp.to_le_bytes());
-    signed_msg.extend(receiver_pubkey.to_bytes());
-    signed_msg.extend(claim_amount_grains.to_le_bytes());
+    let result = api.claim(&key, &receiver_pubkey, claim_amount_grains).await;
+    report_claim(
+        CMD,
+        &receiver_pubkey,
+        &ore_api::consts::MINT_ADDRESS,
+        claim_amount_grains,
+        result,
+    )
+}
 
-    let sig = key.sign_message(&signed_msg);
-    let auth = BASE64_STANDARD.encode(format!("{}:{}", key.pubkey(), sig));
+/// Prints what happened to a claim request and emits its `ClaimReport`. A claim that is
+/// still cooling down is reported too, then returned as `Error::ClaimCooldown`.
+pub fn report_claim(
+    command: &str,
+    receiver: &Pubkey,
+    mint: &Pubkey,
+    grains: u64,
+    result: Result<ClaimStatus, ApiError>,
+) -> Result<(), Error> {
+    let mut report = ClaimReport {
+        receiver: receiver.to_string(),
+        mint: mint.to_string(),
+        amount: amount_to_ui_amount(grains, ore_api::consts::TOKEN_DECIMALS),
+        status: "queued",
+        cooldown_seconds: None,
+    };
+    match result {
+        Ok(ClaimStatus::Queued) => {
+            textln!("  Successfully queued claim request!");
+            output::emit(command, &report);
+        }
+        Ok(ClaimStatus::AlreadyQueued) => {
+            textln!("  Claim is already queued for processing.");
+            report.status = "already_queued";
+            output::emit(command, &report);
+        }
+        Ok(ClaimStatus::TooSoon { wait }) => {
+            report.status = "cooldown";
+            report.cooldown_seconds = Some(wait.as_secs());
+            output::emit_unsuccessful(command, &report);
+            return Err(Error::ClaimCooldown { wait });
+        }
+        Err(e) => return Err(Error::api("Claim failed, please wait and try again", e)),
+    }
+    Ok(())
+}
 
-    let resp = client
-        .post(format!(
-            "{}://{}/v2/claim?timestamp={}&receiver_pubkey={}&amount={}",
-            url_prefix,
-            url,
-            timestamp,
-            receiver_pubkey.to_string(),
-            claim_amount_grains
-        ))
-        .header("Authorization", format!("Basic {}", auth))
-        .send()
-        .await;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_pool::MockPool;
+    use solana_sdk::signature::Keypair;
 
-    match resp {
-        Ok(res) => match res.text().await.unwrap().as_str() {
-            "SUCCESS" => {
-                println!("  Successfully queued claim request!");
-            }
-            "QUEUED" => {
-                println!("  Claim is already queued for processing.");
-            }
-            other => {
-                if let Ok(time) = other.parse::<u64>() {
-                    let time_left = 1800 - time;
-                    let secs = time_left % 60;
-                    let mins = (time_left / 60) % 60;
-                    println!(
-                        "  You cannot claim until the time is up. Time left until next claim available: {}m {}s",
-                        mins, secs
-                    );
-                } else {
-                    println!("  Unexpected response: {}", other);
-                }
-            }
-        },
-        Err(e) => {
-            println!("  ERROR: {}", e);
-            println!("  Retrying in 5 seconds...");
-            tokio::time::sleep(Duration::from_secs(5)).await;
-        }
+    #[tokio::test]
+    async fn claim_sends_signed_request() {
+        let pool = MockPool::start().await;
+        pool.respond("/miner/balance", 200, "1.5");
+        pool.respond("/miner/rewards", 200, "0.25");
+        pool.respond("/v2/claim", 200, "SUCCESS");
+        let key = Keypair::new();
+        let pubkey = key.pubkey();
+
+        let args = ClaimArgs {
+            receiver_pubkey: None,
+            amount: Some(0.1),
+            y: true,
+        };
+        claim(args, key.into(), pool.url(), true).await.unwrap();
+
+        let state = pool.state();
+        let claims = state.requests_to("/v2/claim");
+        assert_eq!(claims.len(), 1);
+        let timestamp: u64 = claims[0].query["timestamp"].parse().unwrap();
+        let amount: u64 = claims[0].query["amount"].parse().unwrap();
+        assert_eq!(amount, 10_000_000_000);
+
+        let (signer, signature) = claims[0].basic_auth().unwrap();
+        let mut signed_msg = vec![];
+        signed_msg.extend(timestamp.to_le_bytes());
+        signed_msg.extend(pubkey.to_bytes());
+        signed_msg.extend(amount.to_le_bytes());
+        assert_eq!(signer, pubkey);
+        assert!(signature.verify(&pubkey.to_bytes(), &signed_msg));
+    }
+
+    #[tokio::test]
+    async fn claim_below_minimum_is_not_sent() {
+        let pool = MockPool::start().await;
+        pool.respond("/miner/balance", 200, "1.5");
+        pool.respond("/miner/rewards", 200, "0.001");
+
+        let args = ClaimArgs {
+            receiver_pubkey: None,
+            amount: None,
+            y: true,
+        };
+        let result = claim(args, Keypair::new().into(), pool.url(), true).await;
+
+        assert!(matches!(result, Err(Error::BelowMinimumClaim)));
+        assert!(pool.state().requests_to("/v2/claim").is_empty());
     }
 }
@@ -1,11 +1,19 @@
-use base64::{prelude::BASE64_STANDARD, Engine};
 use clap::Parser;
 use colored::*;
 use inquire::{InquireError, Text};
 use serde::{Deserialize, Serialize};
-use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+use solana_sdk::{pubkey::Pubkey, signer::Signer};
 use spl_token::amount_to_ui_amount;
-use std::{str::FromStr, time::Duration};
+use std::str::FromStr;
+
+use crate::api::{ApiError, PoolApi};
+use crate::claim::report_claim;
+use crate::error::Error;
+use crate::output;
+use crate::signer::Wallet;
+use crate::textln;
+
+const CMD: &str = "claim-stake-rewards";
 
 #[derive(Debug, Parser)]
 pub struct ClaimStakeRewardsArgs {
@@ -45,20 +53,19 @@ pub struct StakeAccount {
 }
 
 
-pub async fn claim_stake_rewards(args: ClaimStakeRewardsArgs, key: Keypair, url: String, unsecure: bool) {
-    let client = reqwest::Client::new();
-    let url_prefix = if unsecure {
-        "http".to_string()
-    } else {
-        "https".to_string()
-    };
+pub async fn claim_stake_rewards(
+    args: ClaimStakeRewardsArgs,
+    key: Wallet,
+    url: String,
+    unsecure: bool,
+) -> Result<(), Error> {
+    let api = PoolApi::new(&url, unsecure);
 
     let receiver_pubkey = match args.receiver_pubkey {
         Some(rpk) => match Pubkey::from_str(&rpk) {
             Ok(pk) => pk,
             Err(_) => {
-                println!("Failed to parse provided receiver pubkey.\nDouble check the provided public key is valid and try again.");
-                return;
+                return Err(Error::Usage("Failed to parse provided receiver pubkey.\nDouble check the provided public key is valid and try again.".to_string()));
             }
         },
         None => key.pubkey(),
@@ -69,90 +76,59 @@ pub async fn claim_stake_rewards(args: ClaimStakeRewardsArgs, key: Keypair, url:
             pk
         },
         Err(_) => {
-            println!("Failed to parse provided mint pubkey.\nDouble check the provided public key is valid and try again.");
-            return;
+            return Err(Error::Usage("Failed to parse provided mint pubkey.\nDouble check the provided public key is valid and try again.".to_string()));
         }
     };
 
-    let balance_response = client
-        .get(format!(
-            "{}://{}/miner/balance?pubkey={}",
-            url_prefix,
-            url,
-            receiver_pubkey.to_string()
-        ))
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-
     let mut has_deduction = false;
-    let balance = if let Ok(parsed_balance) = balance_response.parse::<f64>() {
-        parsed_balance
-    } else {
-        // If the wallet balance failed to parse
-        has_deduction = true;
-        println!("\n  Note: A 0.004 ORE fee will be deducted from your claim amount to cover the cost\n  of Token Account Creation. This is a one time fee used to create the ORE Token Account.");
-        0.0
+    let balance = match api.wallet_balance(&receiver_pubkey).await {
+        Ok(Some(balance)) => balance,
+        Ok(None) => {
+            // The receiver has no ORE token account yet
+            has_deduction = true;
+            textln!("\n  Note: A 0.004 ORE fee will be deducted from your claim amount to cover the cost\n  of Token Account Creation. This is a one time fee used to create the ORE Token Account.");
+            0.0
+        }
+        Err(e) => {
+            return Err(Error::api("Failed to fetch the receiving wallet balance", e))
+        }
     };
 
-    let stake_accounts = client
-        .get(format!(
-            "{}://{}/v2/miner/boost/stake-accounts?pubkey={}",
-            url_prefix,
-            url,
-            receiver_pubkey.to_string()
-        ))
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-
-    let stake_accounts: Vec<StakeAccount> = match serde_json::from_str(&stake_accounts) {
-        Ok(sa) => {
-            sa
-        },
-        Err(_) => {
-            println!("Failed to parse server stake accounts.");
-            return;
+    let stake_accounts = match api.stake_accounts(&receiver_pubkey).await {
+        Ok(sa) => sa,
+        Err(ApiError::NoStakeAccount) => {
+            return Err(Error::NoStakeAccount {
+                mint: args.mint_pubkey,
+            })
+        }
+        Err(e) => {
+            return Err(Error::api("Failed to fetch server stake accounts", e))
         }
     };
 
-    let mut stake_account = stake_accounts[0].clone();
-    let mut found_stake = false;
-
-    for sa in stake_accounts {
-        if sa.mint_pubkey == args.mint_pubkey {
-            stake_account = sa.clone();
-            found_stake = true;
-            break;
+    let stake_account = match stake_accounts
+        .into_iter()
+        .find(|sa| sa.mint_pubkey == args.mint_pubkey)
+    {
+        Some(sa) => sa,
+        None => {
+            return Err(Error::NoStakeAccount {
+                mint: args.mint_pubkey,
+            })
         }
-    }
-
-    if !found_stake {
-        println!("Failed to find stake account for mint: {}", args.mint_pubkey);
-        return;
-    }
+    };
 
 
     let rewards = stake_account.rewards_balance as f64 / 10f64.powf(ore_api::consts::TOKEN_DECIMALS as f64);
 
-    println!("  Stake Mint:      {}", args.mint_pubkey);
-    println!("  Unclaimed Stake Rewards:      {:.11} ORE", rewards);
-    println!("  Receiving Wallet Ore Balance: {:.11} ORE", balance);
+    textln!("  Stake Mint:      {}", args.mint_pubkey);
+    textln!("  Unclaimed Stake Rewards:      {:.11} ORE", rewards);
+    textln!("  Receiving Wallet Ore Balance: {:.11} ORE", balance);
 
     let minimum_claim_amount = 0.005;
-    if has_deduction {
-        if rewards < minimum_claim_amount {
-            println!();
-            println!("  You have not reached the required claim limit of 0.005 ORE.");
-            println!("  Keep accumulating more rewards before you can withdraw.");
-            return;
-        }
+    if has_deduction && rewards < minimum_claim_amount {
+        textln!();
+        return Err(Error::BelowMinimumClaim);
     }
 
     // Convert balance to grains
@@ -160,18 +136,21 @@ pub async fn claim_stake_rewards(args: ClaimStakeRewardsArgs, key: Keypair, url:
 
     // If balance is zero, inform the user and return to keypair selection
     if balance_grains == 0 {
-        println!("\n  There is no rewards to claim.");
-        return;
+        textln!();
+        return Err(Error::NothingToClaim);
     }
 
     let mut claim_amount = args.amount.unwrap_or(rewards);
 
     // Prompt the user for an amount if it's not provided or less than 0.005
+    if output::json() && has_deduction && claim_amount < minimum_claim_amount {
+        return Err(Error::Usage("--amount must be at least 0.005 ORE".to_string()));
+    }
     loop {
         if has_deduction && claim_amount < minimum_claim_amount {
             if claim_amount != 0.0 {
                 // Only show the message if they previously entered an invalid value
-                println!("  Please enter a number above 0.005.");
+                textln!("  Please enter a number above 0.005.");
             }
 
             match Text::new("\n  Enter the amount to claim (minimum 0.005 ORE or 'esc' to cancel):")
@@ -179,24 +158,24 @@ pub async fn claim_stake_rewards(args: ClaimStakeRewardsArgs, key: Keypair, url:
             {
                 Ok(input) => {
                     if input.trim().eq_ignore_ascii_case("esc") {
-                        println!("  Claim operation canceled.");
-                        return;
+                        textln!("  Claim operation canceled.");
+                        return Ok(());
                     }
 
                     claim_amount = match input.trim().parse::<f64>() {
                         Ok(val) if val >= 0.005 => val,
                         _ => {
-                            println!("  Please enter a valid number above 0.005.");
+                            textln!("  Please enter a valid number above 0.005.");
                             continue;
                         }
                     };
                 }
                 Err(InquireError::OperationCanceled) => {
-                    println!("  Claim operation canceled.");
-                    return;
+                    textln!("  Claim operation canceled.");
+                    return Ok(());
                 }
                 Err(_) => {
-                    println!("  Invalid input. Please try again.");
+                    textln!("  Invalid input. Please try again.");
                     continue;
                 }
             }
@@ -211,12 +190,12 @@ pub async fn claim_stake_rewards(args: ClaimStakeRewardsArgs, key: Keypair, url:
 
     // Auto-adjust the claim amount if it exceeds the available balance
     if claim_amount_grains > balance_grains {
-        println!(
+        textln!(
             "  You do not have enough rewards to claim {} ORE.",
             amount_to_ui_amount(claim_amount_grains, ore_api::consts::TOKEN_DECIMALS)
         );
         claim_amount_grains = balance_grains;
-        println!(
+        textln!(
             "  Adjusting claim amount to the maximum available: {} ORE.",
             amount_to_ui_amount(claim_amount_grains, ore_api::consts::TOKEN_DECIMALS)
         );
@@ -224,6 +203,9 @@ pub async fn claim_stake_rewards(args: ClaimStakeRewardsArgs, key: Keypair, url:
 
     // RED TEXT
     if !args.y {
+        if output::json() {
+            return Err(Error::ConfirmationRequired);
+        }
         match Text::new(
             &format!(
                 "  Are you sure you want to claim {} ORE? (Y/n or 'esc' to cancel)",
@@ -236,100 +218,32 @@ pub async fn claim_stake_rewards(args: ClaimStakeRewardsArgs, key: Keypair, url:
         {
             Ok(confirm) => {
                 if confirm.trim().eq_ignore_ascii_case("esc") {
-                    println!("  Claim canceled.");
-                    return;
+                    textln!("  Claim canceled.");
+                    return Ok(());
                 } else if confirm.trim().is_empty() || confirm.trim().to_lowercase() == "y" {
                 } else {
-                    println!("  Claim canceled.");
-                    return;
+                    textln!("  Claim canceled.");
+                    return Ok(());
                 }
             }
             Err(InquireError::OperationCanceled) => {
-                println!("  Claim operation canceled.");
-                return;
+                textln!("  Claim operation canceled.");
+                return Ok(());
             }
             Err(_) => {
-                println!("  Invalid input. Claim canceled.");
-                return;
+                textln!("  Invalid input. Claim canceled.");
+                return Ok(());
             }
         }
     }
 
-    let timestamp = if let Ok(response) = client
-        .get(format!("{}://{}/timestamp", url_prefix, url))
-        .send()
-        .await
-    {
-        if let Ok(ts) = response.text().await {
-            if let Ok(ts) = ts.parse::<u64>() {
-                ts
-            } else {
-                println!("Failed to get timestamp from server, please try again.");
-                return;
-            }
-        } else {
-            println!("Failed to get timestamp from server, please try again.");
-            return;
-        }
-    } else {
-        println!("Failed to get timestamp from server, please try again.");
-        return;
-    };
-
-    println!(
+    textln!(
         "  Sending claim request for {} ORE...",
         amount_to_ui_amount(claim_amount_grains, ore_api::consts::TOKEN_DECIMALS)
     );
 
-    let mut signed_msg = vec![];
-    signed_msg.extend(timestamp.to_le_bytes());
-    signed_msg.extend(mint_pubkey.to_bytes());
-    signed_msg.extend(receiver_pubkey.to_bytes());
-    signed_msg.extend(claim_amount_grains.to_le_bytes());
-
-    let sig = key.sign_message(&signed_msg);
-    let auth = BASE64_STANDARD.encode(format!("{}:{}", key.pubkey(), sig));
-
-    let resp = client
-        .post(format!(
-            "{}://{}/v2/claim-stake-rewards?timestamp={}&mint={}&receiver_pubkey={}&amount={}",
-            url_prefix,
-            url,
-            timestamp,
-            mint_pubkey.to_string(),
-            receiver_pubkey.to_string(),
-            claim_amount_grains
-        ))
-        .header("Authorization", format!("Basic {}", auth))
-        .send()
+    let result = api
+        .claim_stake_rewards(&key, &mint_pubkey, &receiver_pubkey, claim_amount_grains)
         .await;
-
-    match resp {
-        Ok(res) => match res.text().await.unwrap().as_str() {
-            "SUCCESS" => {
-                println!("  Successfully queued claim request!");
-            }
-            "QUEUED" => {
-                println!("  Claim is already queued for processing.");
-            }
-            other => {
-                println!("  Unexpected response: {}", other);
-                // if let Ok(time) = other.parse::<u64>() {
-                //     let time_left = 1800 - time;
-                //     let secs = time_left % 60;
-                //     let mins = (time_left / 60) % 60;
-                //     println!(
-                //         "  You cannot claim until the time is up. Time left until next claim available: {}m {}s",
-                //         mins, secs
-                //     );
-                // } else {
-                // }
-            }
-        },
-        Err(e) => {
-            println!("  ERROR: {}", e);
-            println!("  Retrying in 5 seconds...");
-            tokio::time::sleep(Duration::from_secs(5)).await;
-        }
-    }
+    report_claim(CMD, &receiver_pubkey, &mint_pubkey, claim_amount_grains, result)
 }
@@ -0,0 +1,1078 @@
+//! Settings file, `~/.config/ore-hq-client/config.toml` by default.
+//!
+//! Every setting resolves as command line > environment > file > built-in default.
+//! Top-level keys are the base settings and a `[profiles.<name>]` table is laid over
+//! them when that profile is active, so one versionable file can describe every rig.
+//! `[pools.<name>]` tables name pool servers with their own defaults; the selected
+//! pool is laid over the file settings but still gives way to the environment.
+
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Parser, Subcommand, ValueEnum};
+use colored::*;
+use dirs::home_dir;
+use log::info;
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use crate::backend::BackendKind;
+use crate::error::Error;
+use crate::mine::MineArgs;
+use crate::signer::SignerAddress;
+use crate::{Args, Commands};
+
+pub const CONFIG_ENV: &str = "ORE_HQ_CONFIG";
+pub const PROFILE_ENV: &str = "ORE_HQ_PROFILE";
+/// The keypair list older clients kept in the working directory
+const LEGACY_KEYPAIR_LIST: &str = "keypair_list";
+
+pub struct Key {
+    pub name: &'static str,
+    pub env: &'static str,
+    pub default: &'static str,
+    pub help: &'static str,
+}
+
+/// Every setting the file accepts. The mine-pmc tunables keep their old env names.
+#[rustfmt::skip]
+pub const KEYS: &[Key] = &[
+    Key { name: "pool", env: "ORE_HQ_POOL", default: "", help: "Named pool from [pools] to connect to" },
+    Key { name: "url", env: "ORE_HQ_URL", default: "ec1ipse.me", help: "Pool server" },
+    Key { name: "failover", env: "ORE_HQ_FAILOVER", default: "", help: "Pools to fall back to in order, as hosts or [pools] names" },
+    Key { name: "keypair", env: "ORE_HQ_KEYPAIR", default: "~/.config/solana/id.json", help: "Keypair file" },
+    Key { name: "passphrase_file", env: "ORE_HQ_PASSPHRASE_FILE", default: "", help: "File holding the passphrase of an encrypted keypair, for unattended rigs" },
+    Key { name: "signer", env: "ORE_HQ_SIGNER", default: "", help: "Sign with `signer serve` at unix:PATH or tcp:HOST:PORT instead of the keypair file" },
+    Key { name: "use_http", env: "ORE_HQ_USE_HTTP", default: "false", help: "Connect without TLS" },
+    Key { name: "threads", env: "ORE_HQ_THREADS", default: "4", help: "Mining threads (protomine defaults to 1)" },
+    Key { name: "buffer", env: "ORE_HQ_BUFFER", default: "0", help: "Seconds to submit before the cutoff" },
+    Key { name: "backend", env: "ORE_HQ_BACKEND", default: "", help: "Hashing backend: drillx, equix or rayon (default depends on the command)" },
+    Key { name: "overmine_by_ms", env: "OVERMINE_BY_MS", default: "2000", help: "mine-pmc: keep mining this long past the cutoff" },
+    Key { name: "nonce_init_interval", env: "NONCE_INIT_INTERVAL", default: "100", help: "mine-pmc: nonces between timing checks" },
+    Key { name: "core_offset", env: "CORE_OFFSET", default: "0", help: "mine-pmc: first core to pin threads to" },
+    Key { name: "miner_name", env: "MINER_NAME", default: "", help: "mine-pmc: name shown at the start of each pass" },
+];
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failover: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keypair: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub passphrase_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub use_http: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threads: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buffer: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend: Option<BackendKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overmine_by_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce_init_interval: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub core_offset: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub miner_name: Option<String>,
+}
+
+impl Settings {
+    /// `over` wins wherever it has a value.
+    pub fn merge(&self, over: &Settings) -> Settings {
+        Settings {
+            pool: over.pool.clone().or_else(|| self.pool.clone()),
+            url: over.url.clone().or_else(|| self.url.clone()),
+            failover: over.failover.clone().or_else(|| self.failover.clone()),
+            keypair: over.keypair.clone().or_else(|| self.keypair.clone()),
+            passphrase_file: over
+                .passphrase_file
+                .clone()
+                .or_else(|| self.passphrase_file.clone()),
+            signer: over.signer.clone().or_else(|| self.signer.clone()),
+            use_http: over.use_http.or(self.use_http),
+            threads: over.threads.or(self.threads),
+            buffer: over.buffer.or(self.buffer),
+            backend: over.backend.or(self.backend),
+            overmine_by_ms: over.overmine_by_ms.or(self.overmine_by_ms),
+            nonce_init_interval: over.nonce_init_interval.or(self.nonce_init_interval),
+            core_offset: over.core_offset.or(self.core_offset),
+            miner_name: over.miner_name.clone().or_else(|| self.miner_name.clone()),
+        }
+    }
+
+    /// Sets `key` from its string form, as given on the environment or `config set`.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<Option<T>, ConfigError>
+        where
+            T::Err: fmt::Display,
+        {
+            value
+                .trim()
+                .parse()
+                .map(Some)
+                .map_err(|e: T::Err| ConfigError::InvalidValue {
+                    key: key.to_string(),
+                    value: value.to_string(),
+                    reason: e.to_string(),
+                })
+        }
+        match key {
+            "pool" => self.pool = Some(value.to_string()),
+            "url" => self.url = Some(value.to_string()),
+            "failover" => {
+                self.failover = Some(
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|url| !url.is_empty())
+                        .map(str::to_string)
+                        .collect(),
+                )
+            }
+            "keypair" => self.keypair = Some(value.to_string()),
+            "passphrase_file" => self.passphrase_file = Some(value.to_string()),
+            "signer" => {
+                self.signer = parse::<SignerAddress>(key, value)?.map(|_| value.trim().to_string())
+            }
+            "use_http" => self.use_http = parse(key, value)?,
+            "threads" => self.threads = parse(key, value)?,
+            "buffer" => self.buffer = parse(key, value)?,
+            "backend" => {
+                self.backend =
+                    Some(BackendKind::from_str(value.trim(), true).map_err(|reason| {
+                        ConfigError::InvalidValue {
+                            key: key.to_string(),
+                            value: value.to_string(),
+                            reason,
+                        }
+                    })?)
+            }
+            "overmine_by_ms" => self.overmine_by_ms = parse(key, value)?,
+            "nonce_init_interval" => self.nonce_init_interval = parse(key, value)?,
+            "core_offset" => self.core_offset = parse(key, value)?,
+            "miner_name" => self.miner_name = Some(value.to_string()),
+            _ => return Err(ConfigError::UnknownKey(key.to_string())),
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        match key {
+            "pool" => self.pool.clone(),
+            "url" => self.url.clone(),
+            "failover" => self.failover.as_ref().map(|urls| urls.join(",")),
+            "keypair" => self.keypair.clone(),
+            "passphrase_file" => self.passphrase_file.clone(),
+            "signer" => self.signer.clone(),
+            "use_http" => self.use_http.map(|v| v.to_string()),
+            "threads" => self.threads.map(|v| v.to_string()),
+            "buffer" => self.buffer.map(|v| v.to_string()),
+            "backend" => self
+                .backend
+                .and_then(|b| b.to_possible_value())
+                .map(|v| v.get_name().to_string()),
+            "overmine_by_ms" => self.overmine_by_ms.map(|v| v.to_string()),
+            "nonce_init_interval" => self.nonce_init_interval.map(|v| v.to_string()),
+            "core_offset" => self.core_offset.map(|v| v.to_string()),
+            "miner_name" => self.miner_name.clone(),
+            _ => None,
+        }
+    }
+
+    /// Settings given as environment variables, read through `var` so tests can fake it.
+    pub fn from_env(var: impl Fn(&str) -> Option<String>) -> Result<Settings, ConfigError> {
+        let mut settings = Settings::default();
+        for key in KEYS {
+            if let Some(value) = var(key.env) {
+                settings.set(key.name, &value).map_err(|e| match e {
+                    ConfigError::InvalidValue { value, reason, .. } => ConfigError::InvalidValue {
+                        key: key.env.to_string(),
+                        value,
+                        reason,
+                    },
+                    e => e,
+                })?;
+            }
+        }
+        Ok(settings)
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Parse(String),
+    UnknownKey(String),
+    UnknownProfile(String),
+    UnknownPool(String),
+    InvalidValue {
+        key: String,
+        value: String,
+        reason: String,
+    },
+    AlreadyExists(PathBuf),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ConfigError::Parse(e) => write!(f, "invalid config file: {}", e),
+            ConfigError::UnknownKey(key) => write!(f, "unknown setting '{}'", key),
+            ConfigError::UnknownProfile(name) => {
+                write!(f, "no profile named '{}' in the config file", name)
+            }
+            ConfigError::UnknownPool(name) => {
+                write!(f, "no pool named '{}' in the config file", name)
+            }
+            ConfigError::InvalidValue { key, value, reason } => {
+                write!(f, "invalid value '{}' for {}: {}", value, key, reason)
+            }
+            ConfigError::AlreadyExists(path) => {
+                write!(
+                    f,
+                    "{} already exists, pass --force to overwrite it",
+                    path.display()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// A pool server and the defaults to mine it with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pool {
+    pub host: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub use_http: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend: Option<BackendKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threads: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buffer: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keypair: Option<String>,
+}
+
+impl Pool {
+    pub fn settings(&self) -> Settings {
+        Settings {
+            url: Some(self.host.clone()),
+            use_http: self.use_http,
+            backend: self.backend,
+            threads: self.threads,
+            buffer: self.buffer,
+            keypair: self.keypair.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ConfigFile {
+    /// Profile used when none is given with `--profile` or `ORE_HQ_PROFILE`
+    pub profile: Option<String>,
+    /// Keypairs offered by the interactive menu
+    pub keypairs: Vec<String>,
+    /// Names usable as `--keypair`, each for the path of a keypair
+    pub labels: BTreeMap<String, String>,
+    pub settings: Settings,
+    pub profiles: BTreeMap<String, Settings>,
+    pub pools: BTreeMap<String, Pool>,
+}
+
+impl ConfigFile {
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let mut table: toml::value::Table =
+            toml::from_str(text).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        let parse_err = |e: toml::de::Error| ConfigError::Parse(e.to_string());
+
+        let profile = match table.remove("profile") {
+            Some(value) => Some(value.try_into().map_err(parse_err)?),
+            None => None,
+        };
+        let keypairs = match table.remove("keypairs") {
+            Some(value) => value.try_into().map_err(parse_err)?,
+            None => vec![],
+        };
+        let labels = match table.remove("labels") {
+            Some(value) => value.try_into().map_err(parse_err)?,
+            None => BTreeMap::new(),
+        };
+        let profiles = match table.remove("profiles") {
+            Some(value) => value.try_into().map_err(parse_err)?,
+            None => BTreeMap::new(),
+        };
+        let pools = match table.remove("pools") {
+            Some(value) => value.try_into().map_err(parse_err)?,
+            None => BTreeMap::new(),
+        };
+        let settings = toml::Value::Table(table).try_into().map_err(parse_err)?;
+        Ok(ConfigFile {
+            profile,
+            keypairs,
+            labels,
+            settings,
+            profiles,
+            pools,
+        })
+    }
+
+    /// A missing file is an empty config.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(ConfigError::Io {
+                path: path.to_path_buf(),
+                error,
+            }),
+        }
+    }
+
+    /// The profile in effect: the one asked for, else the file's own choice.
+    pub fn active_profile<'a>(&'a self, requested: Option<&'a str>) -> Option<&'a str> {
+        requested.or(self.profile.as_deref())
+    }
+
+    /// File settings with the active profile laid over the base ones.
+    pub fn resolve(&self, requested: Option<&str>) -> Result<Settings, ConfigError> {
+        match self.active_profile(requested) {
+            Some(name) => self
+                .profiles
+                .get(name)
+                .map(|profile| self.settings.merge(profile))
+                .ok_or_else(|| ConfigError::UnknownProfile(name.to_string())),
+            None => Ok(self.settings.clone()),
+        }
+    }
+
+    pub fn pool(&self, name: &str) -> Result<&Pool, ConfigError> {
+        self.pools
+            .get(name)
+            .ok_or_else(|| ConfigError::UnknownPool(name.to_string()))
+    }
+}
+
+pub fn default_path() -> PathBuf {
+    home_dir()
+        .unwrap_or_default()
+        .join(".config")
+        .join("ore-hq-client")
+        .join("config.toml")
+}
+
+static PATH: OnceCell<PathBuf> = OnceCell::new();
+/// The file and profile `load` read, kept so the menu can switch pools
+static LOADED: OnceCell<(ConfigFile, Option<String>)> = OnceCell::new();
+static SETTINGS: Lazy<RwLock<Settings>> = Lazy::new(Default::default);
+
+/// The config file in use, `--config` or `ORE_HQ_CONFIG` if given.
+pub fn path() -> &'static Path {
+    PATH.get_or_init(|| {
+        std::env::var_os(CONFIG_ENV)
+            .map(PathBuf::from)
+            .unwrap_or_else(default_path)
+    })
+}
+
+/// Points the config file at `path` for a test, the one file every test in the process uses.
+#[cfg(test)]
+pub fn use_path(path: &Path) {
+    let in_use = PATH.get_or_init(|| path.to_path_buf());
+    assert_eq!(
+        in_use,
+        path,
+        "the config file is already {}",
+        in_use.display()
+    );
+}
+
+/// Environment and file settings resolved by `load`, for commands built by the menu.
+pub fn settings() -> Settings {
+    SETTINGS.read().unwrap().clone()
+}
+
+fn requested_profile(profile: Option<&str>) -> Option<String> {
+    profile
+        .map(str::to_string)
+        .or_else(|| std::env::var(PROFILE_ENV).ok())
+}
+
+/// Reads the config file and environment once at startup.
+pub fn load(
+    config: Option<&Path>,
+    profile: Option<&str>,
+    pool: Option<&str>,
+) -> Result<Settings, ConfigError> {
+    if let Some(config) = config {
+        let _ = PATH.set(config.to_path_buf());
+    }
+    let file = ConfigFile::load(path())?;
+    let profile = requested_profile(profile);
+    let settings = resolve(&file, profile.as_deref(), pool, |name| {
+        std::env::var(name).ok()
+    })?;
+    *SETTINGS.write().unwrap() = settings.clone();
+    let _ = LOADED.set((file, profile));
+    Ok(settings)
+}
+
+/// Environment over the selected pool over the file's settings for the active profile.
+/// The pool is `pool` if given, else the one the environment or file settings name.
+pub fn resolve(
+    file: &ConfigFile,
+    profile: Option<&str>,
+    pool: Option<&str>,
+    var: impl Fn(&str) -> Option<String>,
+) -> Result<Settings, ConfigError> {
+    let env = Settings::from_env(var)?;
+    let mut settings = file.resolve(profile)?;
+    let pool = pool
+        .map(str::to_string)
+        .or_else(|| env.pool.clone())
+        .or_else(|| settings.pool.clone());
+    if let Some(name) = pool {
+        settings = settings.merge(&file.pool(&name)?.settings());
+        settings.pool = Some(name);
+    }
+    Ok(settings.merge(&env))
+}
+
+/// Fills every argument not given on the command line from `settings`.
+pub fn apply(args: &mut Args, matches: &ArgMatches, settings: &Settings) {
+    if unset(matches, "url") {
+        if let Some(url) = &settings.url {
+            args.url = url.clone();
+        }
+    }
+    if unset(matches, "keypair") {
+        if let Some(keypair) = &settings.keypair {
+            args.keypair = keypair.clone();
+        }
+    }
+    if unset(matches, "passphrase_file") && args.passphrase_file.is_none() {
+        args.passphrase_file = settings.passphrase_file.clone();
+    }
+    if unset(matches, "signer") && args.signer.is_none() {
+        args.signer = settings.signer.clone();
+    }
+    if unset(matches, "use_http") {
+        if let Some(use_http) = settings.use_http {
+            args.use_http = use_http;
+        }
+    }
+
+    let Some((_, sub)) = matches.subcommand() else {
+        return;
+    };
+    match &mut args.command {
+        Some(Commands::Mine(mine)) => apply_mine(mine, sub, settings),
+        Some(Commands::MinePmc { mine, pmc }) => {
+            apply_mine(mine, sub, settings);
+            pmc.overmine_by_ms = pmc.overmine_by_ms.or(settings.overmine_by_ms);
+            pmc.nonce_init_interval = pmc.nonce_init_interval.or(settings.nonce_init_interval);
+            pmc.core_offset = pmc.core_offset.or(settings.core_offset);
+            if pmc.miner_name.is_none() {
+                pmc.miner_name = settings.miner_name.clone();
+            }
+        }
+        Some(Commands::Protomine(protomine)) => {
+            if unset(sub, "threads") {
+                if let Some(threads) = settings.threads {
+                    protomine.threads = threads as usize;
+                }
+            }
+            protomine.backend = protomine.backend.or(settings.backend);
+            if protomine.failover.is_empty() {
+                protomine.failover = settings.failover.clone().unwrap_or_default();
+            }
+            protomine.failover = pool_hosts(&protomine.failover);
+        }
+        _ => {}
+    }
+}
+
+/// True when `id` wasn't given on the command line.
+/// Whether `--url`, or a pool or url in the environment or file, says where to mine.
+/// When nothing does the menu offers the configured pools.
+pub fn pool_chosen(matches: &ArgMatches, settings: &Settings) -> bool {
+    !unset(matches, "url") || settings.pool.is_some() || settings.url.is_some()
+}
+
+fn unset(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) != Some(ValueSource::CommandLine)
+}
+
+/// Fills the options every miner shares from `settings`, where the command line left them.
+fn apply_mine(mine: &mut MineArgs, sub: &ArgMatches, settings: &Settings) {
+    if unset(sub, "threads") {
+        if let Some(threads) = settings.threads {
+            mine.threads = threads;
+        }
+    }
+    if unset(sub, "buffer") {
+        if let Some(buffer) = settings.buffer {
+            mine.buffer = buffer;
+        }
+    }
+    mine.backend = mine.backend.or(settings.backend);
+    if mine.failover.is_empty() {
+        mine.failover = settings.failover.clone().unwrap_or_default();
+    }
+    mine.failover = pool_hosts(&mine.failover);
+}
+
+/// Swaps names of configured pools for their hosts.
+fn pool_hosts(urls: &[String]) -> Vec<String> {
+    let pools = pools();
+    urls.iter()
+        .map(|url| {
+            pools
+                .get(url)
+                .map_or_else(|| url.clone(), |p| p.host.clone())
+        })
+        .collect()
+}
+
+/// The failover hosts for a session built from `settings`.
+pub fn failover_urls(settings: &Settings) -> Vec<String> {
+    pool_hosts(settings.failover.as_deref().unwrap_or_default())
+}
+
+/// Named pools, for the menu to offer.
+pub fn pools() -> BTreeMap<String, Pool> {
+    LOADED
+        .get()
+        .map(|(file, _)| file.pools.clone())
+        .unwrap_or_default()
+}
+
+/// Switches to the pool `name`, as `--pool` would have, and returns the new settings.
+pub fn select_pool(name: &str) -> Result<Settings, ConfigError> {
+    let (file, profile) = LOADED
+        .get()
+        .ok_or_else(|| ConfigError::UnknownPool(name.to_string()))?;
+    let settings = resolve(file, profile.as_deref(), Some(name), |key| {
+        std::env::var(key).ok()
+    })?;
+    *SETTINGS.write().unwrap() = settings.clone();
+    Ok(settings)
+}
+
+/// Keypairs saved for the menu.
+pub fn keypairs() -> Vec<String> {
+    ConfigFile::load(path())
+        .map(|file| file.keypairs)
+        .unwrap_or_default()
+}
+
+pub fn save_keypairs(keypairs: &[String]) -> Result<(), ConfigError> {
+    update(path(), |table| {
+        let list = keypairs.iter().cloned().map(toml::Value::String).collect();
+        table.insert("keypairs".to_string(), toml::Value::Array(list));
+        Ok(())
+    })
+}
+
+/// Keypair labels, each with the path it stands for.
+pub fn labels() -> BTreeMap<String, String> {
+    ConfigFile::load(path())
+        .map(|file| file.labels)
+        .unwrap_or_default()
+}
+
+pub fn save_labels(labels: &BTreeMap<String, String>) -> Result<(), ConfigError> {
+    update(path(), |table| {
+        let labels = labels
+            .iter()
+            .map(|(label, path)| (label.clone(), toml::Value::String(path.clone())))
+            .collect();
+        table.insert("labels".to_string(), toml::Value::Table(labels));
+        Ok(())
+    })
+}
+
+/// Makes `keypair`, a path or label, the keypair of the base settings.
+pub fn set_keypair(keypair: &str) -> Result<(), ConfigError> {
+    set_value(path(), None, "keypair", keypair)
+}
+
+/// Moves the entries of an old `keypair_list` file into the config file.
+pub fn import_legacy_keypairs() -> Result<(), ConfigError> {
+    let legacy = PathBuf::from(LEGACY_KEYPAIR_LIST);
+    let Ok(text) = fs::read_to_string(&legacy) else {
+        return Ok(());
+    };
+    let mut keypairs = keypairs();
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if !keypairs.iter().any(|k| k == line) {
+            keypairs.push(line.to_string());
+        }
+    }
+    save_keypairs(&keypairs)?;
+
+    let mut imported = legacy.into_os_string();
+    imported.push(".imported");
+    fs::rename(LEGACY_KEYPAIR_LIST, &imported).map_err(|error| ConfigError::Io {
+        path: PathBuf::from(LEGACY_KEYPAIR_LIST),
+        error,
+    })?;
+    info!(
+        "Moved the keypairs in {} to {}",
+        LEGACY_KEYPAIR_LIST,
+        path().display()
+    );
+    Ok(())
+}
+
+/// Edits the raw TOML so keys this client doesn't model are kept.
+fn update(
+    path: &Path,
+    edit: impl FnOnce(&mut toml::value::Table) -> Result<(), ConfigError>,
+) -> Result<(), ConfigError> {
+    let io_err = |error| ConfigError::Io {
+        path: path.to_path_buf(),
+        error,
+    };
+    let mut table: toml::value::Table = match fs::read_to_string(path) {
+        Ok(text) => toml::from_str(&text).map_err(|e| ConfigError::Parse(e.to_string()))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Default::default(),
+        Err(e) => return Err(io_err(e)),
+    };
+    edit(&mut table)?;
+
+    let text = toml::to_string(&toml::Value::Table(table))
+        .map_err(|e| ConfigError::Parse(e.to_string()))?;
+    // Never write out a file the next run would reject
+    ConfigFile::parse(&text)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(io_err)?;
+    }
+    fs::write(path, text).map_err(io_err)
+}
+
+/// Sets `key` in the base settings, or in `profile` when given.
+fn set_value(
+    path: &Path,
+    profile: Option<&str>,
+    key: &str,
+    value: &str,
+) -> Result<(), ConfigError> {
+    if key == "profile" {
+        if profile.is_some() {
+            return Err(ConfigError::UnknownKey(
+                "profiles.<name>.profile".to_string(),
+            ));
+        }
+        let file = ConfigFile::load(path)?;
+        if !file.profiles.contains_key(value) {
+            return Err(ConfigError::UnknownProfile(value.to_string()));
+        }
+        return update(path, |table| {
+            table.insert(
+                "profile".to_string(),
+                toml::Value::String(value.to_string()),
+            );
+            Ok(())
+        });
+    }
+
+    let mut parsed = Settings::default();
+    parsed.set(key, value)?;
+    let toml::Value::Table(mut parsed) =
+        toml::Value::try_from(&parsed).map_err(|e| ConfigError::Parse(e.to_string()))?
+    else {
+        unreachable!("settings serialize to a table");
+    };
+    let value = parsed.remove(key).expect("the key was just set");
+
+    update(path, |table| {
+        let target = match profile {
+            Some(name) => table
+                .entry("profiles")
+                .or_insert_with(|| toml::Value::Table(Default::default()))
+                .as_table_mut()
+                .ok_or_else(|| ConfigError::Parse("profiles must be a table".to_string()))?
+                .entry(name)
+                .or_insert_with(|| toml::Value::Table(Default::default()))
+                .as_table_mut()
+                .ok_or_else(|| ConfigError::Parse(format!("profiles.{} must be a table", name)))?,
+            None => table,
+        };
+        target.insert(key.to_string(), value);
+        Ok(())
+    })
+}
+
+fn template(keypairs: &[String], labels: &BTreeMap<String, String>) -> String {
+    let mut out = String::from(
+        "# ore-hq-client settings. Command line flags and environment variables override these.\n\
+         # A [profiles.<name>] table overrides the keys above it when selected with --profile,\n\
+         # ORE_HQ_PROFILE or the profile key.\n\n\
+         # profile = \"rig1\"\n\n",
+    );
+    for key in KEYS {
+        let value = match key.name {
+            "pool" => "\"production\"".to_string(),
+            "failover" => "[\"backup.example.com\", \"staging\"]".to_string(),
+            "backend" => "\"drillx\"".to_string(),
+            "passphrase_file" => "\"~/.config/ore-hq-client/passphrase\"".to_string(),
+            "signer" => "\"unix:/run/ore-hq-client/signer.sock\"".to_string(),
+            "url" | "keypair" | "miner_name" => format!("{:?}", key.default),
+            _ => key.default.to_string(),
+        };
+        out.push_str(&format!("# {}\n# {} = {}\n", key.help, key.name, value));
+    }
+    let list = toml::Value::Array(keypairs.iter().cloned().map(toml::Value::String).collect());
+    out.push_str(&format!(
+        "\n# Keypairs offered by the interactive menu\nkeypairs = {}\n\n\
+         # Names to pass as --keypair, managed with the keys command\n",
+        list
+    ));
+    if labels.is_empty() {
+        out.push_str("# [labels]\n# rig1 = \"~/.config/solana/rig1.json\"\n\n");
+    } else {
+        out.push_str("[labels]\n");
+        for (label, path) in labels {
+            out.push_str(&format!("{} = {:?}\n", label, path));
+        }
+        out.push('\n');
+    }
+    out.push_str(
+        "# [profiles.rig1]\n# threads = 16\n# core_offset = 16\n\n\
+         # Pools to pick with --pool <name> or from the menu\n\
+         # [pools.production]\n# host = \"ec1ipse.me\"\n\n\
+         # [pools.staging]\n# host = \"staging.example.com\"\n# use_http = true\n\
+         # backend = \"rayon\"\n# threads = 2\n# buffer = 1\n\
+         # keypair = \"~/.config/solana/staging.json\"\n",
+    );
+    out
+}
+
+#[derive(Debug, Parser)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub command: ConfigCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    #[command(about = "Show each setting, its value and where it came from.")]
+    Show,
+    #[command(about = "Set a setting in the config file, in the --profile one if given.")]
+    Set {
+        #[arg(
+            value_name = "KEY",
+            help = "Setting name, or profile to choose the default profile"
+        )]
+        key: String,
+        #[arg(value_name = "VALUE")]
+        value: String,
+    },
+    #[command(about = "Write a commented config file to start from.")]
+    Init {
+        #[arg(long, help = "Overwrite an existing config file")]
+        force: bool,
+    },
+}
+
+pub fn config(
+    args: &ConfigArgs,
+    config: Option<&Path>,
+    profile: Option<&str>,
+    pool: Option<&str>,
+) -> Result<(), Error> {
+    if let Some(config) = config {
+        let _ = PATH.set(config.to_path_buf());
+    }
+    let profile = requested_profile(profile);
+    match &args.command {
+        ConfigCommand::Show => show(profile.as_deref(), pool)?,
+        ConfigCommand::Set { key, value } => {
+            set_value(path(), profile.as_deref(), key, value)?;
+            println!("Set {} = {} in {}", key, value, path().display());
+        }
+        ConfigCommand::Init { force } => init(*force)?,
+    }
+    Ok(())
+}
+
+fn show(profile: Option<&str>, pool: Option<&str>) -> Result<(), ConfigError> {
+    let file = ConfigFile::load(path())?;
+    let active = file.active_profile(profile);
+    let overlay = match active {
+        Some(name) => Some(
+            file.profiles
+                .get(name)
+                .ok_or_else(|| ConfigError::UnknownProfile(name.to_string()))?,
+        ),
+        None => None,
+    };
+    let env = Settings::from_env(|name| std::env::var(name).ok())?;
+    let pool_name = pool
+        .map(str::to_string)
+        .or_else(|| env.pool.clone())
+        .or_else(|| file.resolve(profile).ok().and_then(|s| s.pool));
+    let pool_settings = match &pool_name {
+        Some(name) => Some(file.pool(name)?.settings()),
+        None => None,
+    };
+
+    println!("Config file: {}", path().display());
+    println!("Profile: {}", active.unwrap_or("(none)"));
+    println!("Pool: {}", pool_name.as_deref().unwrap_or("(none)"));
+    println!();
+    for key in KEYS {
+        let (value, source) = if key.name == "pool" && pool.is_some() {
+            (pool.unwrap_or_default().to_string(), "--pool".to_string())
+        } else if let Some(v) = env.get(key.name) {
+            (v, format!("env {}", key.env))
+        } else if let Some(v) = pool_settings.as_ref().and_then(|p| p.get(key.name)) {
+            (
+                v,
+                format!("pool {}", pool_name.as_deref().unwrap_or_default()),
+            )
+        } else if let Some(v) = overlay.and_then(|p| p.get(key.name)) {
+            (v, format!("profile {}", active.unwrap_or_default()))
+        } else if let Some(v) = file.settings.get(key.name) {
+            (v, "file".to_string())
+        } else {
+            (key.default.to_string(), "default".to_string())
+        };
+        println!("{:<20} {:<28} {}", key.name, value, source.dimmed());
+    }
+    if !file.profiles.is_empty() {
+        println!();
+        println!(
+            "Profiles: {}",
+            file.profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+        );
+    }
+    for (name, pool) in &file.pools {
+        println!(
+            "Pool {}: {}://{}",
+            name,
+            if pool.use_http.unwrap_or(false) {
+                "http"
+            } else {
+                "https"
+            },
+            pool.host
+        );
+    }
+    Ok(())
+}
+
+fn init(force: bool) -> Result<(), ConfigError> {
+    let path = path();
+    if path.exists() && !force {
+        return Err(ConfigError::AlreadyExists(path.to_path_buf()));
+    }
+    let io_err = |error| ConfigError::Io {
+        path: path.to_path_buf(),
+        error,
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(io_err)?;
+    }
+    fs::write(path, template(&keypairs(), &labels())).map_err(io_err)?;
+    println!("{}", format!("Wrote {}", path.display()).green());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{CommandFactory, FromArgMatches};
+
+    const FILE: &str = r#"
+        profile = "rig"
+        url = "file.example"
+        threads = 8
+        buffer = 1
+        overmine_by_ms = 1500
+
+        [profiles.rig]
+        threads = 12
+        miner_name = "rig"
+
+        [profiles.spare]
+        threads = 2
+
+        [profiles.staging]
+        pool = "staging"
+        threads = 1
+
+        [pools.staging]
+        host = "staging.example.com"
+        use_http = true
+        backend = "rayon"
+        threads = 3
+        keypair = "~/staging.json"
+    "#;
+
+    fn parse_args(argv: &[&str]) -> (Args, ArgMatches) {
+        let matches = Args::command().try_get_matches_from(argv).unwrap();
+        (Args::from_arg_matches(&matches).unwrap(), matches)
+    }
+
+    #[test]
+    fn command_line_beats_env_beats_file_beats_defaults() {
+        let file = ConfigFile::parse(FILE).unwrap();
+        let env = |name: &str| match name {
+            "ORE_HQ_BUFFER" => Some("3".to_string()),
+            "OVERMINE_BY_MS" => Some("2500".to_string()),
+            _ => None,
+        };
+        let settings = resolve(&file, None, None, env).unwrap();
+        let (mut args, matches) = parse_args(&[
+            "ore-hq-client",
+            "mine-pmc",
+            "--threads",
+            "2",
+            "--overmine-by-ms",
+            "900",
+        ]);
+
+        apply(&mut args, &matches, &settings);
+
+        assert_eq!(args.url, "file.example");
+        assert_eq!(args.keypair, "~/.config/solana/id.json");
+        let Some(Commands::MinePmc { mine, pmc }) = args.command else {
+            panic!("expected mine-pmc");
+        };
+        assert_eq!(mine.threads, 2);
+        assert_eq!(mine.buffer, 3);
+        assert_eq!(pmc.overmine_by_ms, Some(900));
+        assert_eq!(pmc.miner_name.as_deref(), Some("rig"));
+        assert_eq!(pmc.core_offset, None);
+
+        let spare = resolve(&file, Some("spare"), None, |_| None).unwrap();
+        assert_eq!(spare.threads, Some(2));
+        assert_eq!(spare.miner_name, None);
+        assert!(matches!(
+            resolve(&file, Some("missing"), None, |_| None),
+            Err(ConfigError::UnknownProfile(_))
+        ));
+        assert!(matches!(
+            Settings::from_env(|n| (n == "CORE_OFFSET").then(|| "x".to_string())),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn pools_override_the_file_but_not_env_or_flags() {
+        let file = ConfigFile::parse(FILE).unwrap();
+        let env = |name: &str| (name == "ORE_HQ_BUFFER").then(|| "4".to_string());
+
+        let settings = resolve(&file, Some("staging"), None, env).unwrap();
+        assert_eq!(settings.pool.as_deref(), Some("staging"));
+        assert_eq!(settings.url.as_deref(), Some("staging.example.com"));
+        assert_eq!(settings.threads, Some(3));
+        assert_eq!(settings.buffer, Some(4));
+        assert_eq!(settings.overmine_by_ms, Some(1500));
+
+        let (mut args, matches) = parse_args(&[
+            "ore-hq-client",
+            "--pool",
+            "staging",
+            "protomine",
+            "--threads",
+            "6",
+        ]);
+        let settings = resolve(&file, None, args.pool.as_deref(), |_| None).unwrap();
+        apply(&mut args, &matches, &settings);
+        assert_eq!(args.url, "staging.example.com");
+        assert!(args.use_http);
+        assert_eq!(args.keypair, "~/staging.json");
+        let Some(Commands::Protomine(protomine)) = args.command else {
+            panic!("expected protomine");
+        };
+        assert_eq!(protomine.threads, 6);
+        assert_eq!(protomine.backend, Some(BackendKind::Rayon));
+
+        assert!(matches!(
+            resolve(&file, None, Some("prod"), |_| None),
+            Err(ConfigError::UnknownPool(_))
+        ));
+    }
+
+    #[test]
+    fn the_menu_asks_for_a_pool_only_when_nothing_chose_one() {
+        let empty = ConfigFile::parse("").unwrap();
+        let none = resolve(&empty, None, None, |_| None).unwrap();
+        let (_, matches) = parse_args(&["ore-hq-client"]);
+        assert!(!pool_chosen(&matches, &none));
+
+        // Even the default url counts when it's given
+        let (_, matches) = parse_args(&["ore-hq-client", "--url", "ec1ipse.me"]);
+        assert!(pool_chosen(&matches, &none));
+
+        let (_, matches) = parse_args(&["ore-hq-client"]);
+        let env = |name: &str| (name == "ORE_HQ_URL").then(|| "ec1ipse.me".to_string());
+        assert!(pool_chosen(
+            &matches,
+            &resolve(&empty, None, None, env).unwrap()
+        ));
+        let file = ConfigFile::parse(FILE).unwrap();
+        assert!(pool_chosen(
+            &matches,
+            &resolve(&file, None, Some("staging"), |_| None).unwrap()
+        ));
+    }
+
+    #[test]
+    fn set_keeps_the_rest_of_the_file() {
+        let path =
+            std::env::temp_dir().join(format!("ore-hq-client-config-{}.toml", std::process::id()));
+        fs::write(&path, FILE).unwrap();
+
+        set_value(&path, None, "threads", "16").unwrap();
+        set_value(&path, Some("new"), "use_http", "true").unwrap();
+        set_value(&path, None, "profile", "spare").unwrap();
+        assert!(set_value(&path, None, "threads", "many").is_err());
+        assert!(set_value(&path, None, "thread", "1").is_err());
+        assert!(set_value(&path, None, "profile", "missing").is_err());
+
+        let file = ConfigFile::load(&path).unwrap();
+        assert_eq!(file.profile.as_deref(), Some("spare"));
+        assert_eq!(file.settings.threads, Some(16));
+        assert_eq!(file.settings.overmine_by_ms, Some(1500));
+        assert_eq!(file.profiles["rig"].threads, Some(12));
+        assert_eq!(file.profiles["new"].use_http, Some(true));
+        assert!(
+            ConfigFile::parse(&template(&["~/a.json".to_string()], &BTreeMap::new()))
+                .unwrap()
+                .keypairs
+                .len()
+                == 1
+        );
+        let labels = BTreeMap::from([("rig1".to_string(), "/keys/rig 1.json".to_string())]);
+        assert_eq!(
+            ConfigFile::parse(&template(&[], &labels)).unwrap().labels,
+            labels
+        );
+        let _ = fs::remove_file(path);
+    }
+}
@@ -1,9 +1,19 @@
 use std::{path::Path, sync::RwLock};
 
+use chrono::{NaiveDate, Utc};
+use clap::ValueEnum;
 use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::migrations::{self, MigrationError};
+
+pub const DATABASE_PATH: &str = "./app_db.db3";
 
 pub struct PoolSubmissionResult {
     _id: i32,
+    challenge: String,
+    pubkey: Option<String>,
+    pool: Option<String>,
     pool_difficulty: u32,
     pool_earned: u64,
     miner_percentage: f64,
@@ -14,6 +24,7 @@ pub struct PoolSubmissionResult {
 
 impl PoolSubmissionResult {
     pub fn new(
+        challenge: String,
         pool_difficulty: u32,
         pool_earned: u64,
         miner_percentage: f64,
@@ -22,6 +33,9 @@ impl PoolSubmissionResult {
     ) -> Self {
         PoolSubmissionResult {
             _id: 0,
+            challenge,
+            pubkey: None,
+            pool: None,
             pool_difficulty,
             pool_earned,
             miner_percentage,
@@ -30,6 +44,114 @@ impl PoolSubmissionResult {
             _created_at: 0,
         }
     }
+
+    /// Tags the result with the wallet and pool host that mined it.
+    pub fn for_miner(mut self, pubkey: String, pool: String) -> Self {
+        self.pubkey = Some(pubkey);
+        self.pool = Some(pool);
+        self
+    }
+}
+
+/// Restricts earnings queries to one wallet and/or pool host, and to a range of UTC
+/// days (both ends inclusive). Rows recorded before wallets and pools were tracked only
+/// match when those are unset.
+#[derive(Debug, Default, Clone)]
+pub struct EarningsFilter {
+    pub pubkey: Option<String>,
+    pub pool: Option<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+const FILTER_SQL: &str = r#"(?1 IS NULL OR pubkey = ?1)
+                 AND (?2 IS NULL OR pool = ?2)
+                 AND (?3 IS NULL OR created_at >= ?3)
+                 AND (?4 IS NULL OR created_at < date(?4, '+1 day'))"#;
+
+impl EarningsFilter {
+    fn params(&self) -> (&Option<String>, &Option<String>, Option<String>, Option<String>) {
+        (
+            &self.pubkey,
+            &self.pool,
+            self.from.map(|d| d.to_string()),
+            self.to.map(|d| d.to_string()),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    Hour,
+    Day,
+    /// Weeks start on Monday
+    Week,
+    Month,
+}
+
+impl GroupBy {
+    fn sql(&self) -> &'static str {
+        match self {
+            GroupBy::Hour => "strftime('%Y-%m-%d %H:00', created_at)",
+            GroupBy::Day => "DATE(created_at)",
+            GroupBy::Week => "DATE(created_at, 'weekday 0', '-6 days')",
+            GroupBy::Month => "strftime('%Y-%m', created_at)",
+        }
+    }
+}
+
+/// Earnings for one hour, day, week or month of a report.
+#[derive(Debug, Serialize)]
+pub struct EarningsBucket {
+    pub bucket: String,
+    pub submissions: u64,
+    pub miner_earned: u64,
+    pub pool_earned: u64,
+    pub avg_pool_difficulty: f64,
+    pub avg_miner_difficulty: f64,
+    pub avg_miner_percentage: f64,
+}
+
+/// Earnings totals for one wallet on one pool.
+pub struct WalletEarnings {
+    pub pubkey: Option<String>,
+    pub pool: Option<String>,
+    pub submissions: u64,
+    pub miner_earned: u64,
+}
+
+/// Telemetry for one mining pass, keyed by the base64 challenge so it can be joined to
+/// the pool's submission result for the same challenge.
+pub struct MiningPassRecord {
+    pub challenge: String,
+    pub pubkey: String,
+    pub pool: String,
+    pub nonce_start: u64,
+    pub nonce_end: u64,
+    pub cutoff: u64,
+    pub hashes: u64,
+    pub hash_time_ms: u64,
+    pub best_difficulty: u32,
+    pub overmined_by_ms: Option<f64>,
+}
+
+impl MiningPassRecord {
+    pub fn hashrate(&self) -> f64 {
+        if self.hash_time_ms == 0 {
+            return 0.0;
+        }
+        self.hashes as f64 * 1000.0 / self.hash_time_ms as f64
+    }
+}
+
+/// A recorded pass and, once the pool has reported it, what it earned.
+pub struct MiningPassSummary {
+    pub created_at: String,
+    pub pass: MiningPassRecord,
+    pub pool_difficulty: Option<u32>,
+    pub miner_difficulty: Option<u32>,
+    pub miner_earned: Option<u64>,
 }
 
 pub struct AppDatabase {
@@ -37,35 +159,17 @@ pub struct AppDatabase {
 }
 
 impl AppDatabase {
-    pub fn new() -> Self {
-        let conn = match Connection::open(Path::new("./app_db.db3")) {
-            Ok(c) => {
-                match c.execute(
-                    r#"CREATE TABLE IF NOT EXISTS pool_submission_results (
-                        id INTEGER PRIMARY KEY,
-                        pool_difficulty INTEGER NOT NULL,
-                        pool_earned INTEGER NOT NULL,
-                        miner_percentage NUMERIC NOT NULL,
-                        miner_difficulty INTEGER NOT NULL,
-                        miner_earned INTEGER NOT NULL,
-                        created_at  INTEGER DEFAULT CURRENT_TIMESTAMP NOT NULL
-                    )"#,
-                    (),
-                ) {
-                    Ok(_) => c,
-                    Err(e) => {
-                        eprintln!("Error creating pool_submission_results table!");
-                        panic!("Error: {e}");
-                    }
-                }
-            }
-            Err(_e) => {
-                panic!("Failed to open app database");
-            }
-        };
-        AppDatabase {
+    pub fn new() -> Result<Self, MigrationError> {
+        Self::open(Path::new(DATABASE_PATH))
+    }
+
+    /// Opens the database, applying any pending schema migrations.
+    pub fn open(path: &Path) -> Result<Self, MigrationError> {
+        let mut conn = Connection::open(path)?;
+        migrations::migrate(&mut conn, path)?;
+        Ok(AppDatabase {
             connection: RwLock::new(conn),
-        }
+        })
     }
 
     pub fn add_new_pool_submission(&self, new_pool_submission_result: PoolSubmissionResult) {
@@ -75,64 +179,186 @@ impl AppDatabase {
                 pool_earned,
                 miner_percentage,
                 miner_difficulty,
-                miner_earned
-            ) VALUES (?1, ?2, ?3, ?4, ?5)"#,
+                miner_earned,
+                challenge,
+                pubkey,
+                pool
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"#,
             (
                 &new_pool_submission_result.pool_difficulty,
                 &new_pool_submission_result.pool_earned,
                 &new_pool_submission_result.miner_percentage,
                 &new_pool_submission_result.miner_difficulty,
                 &new_pool_submission_result.miner_earned,
+                &new_pool_submission_result.challenge,
+                &new_pool_submission_result.pubkey,
+                &new_pool_submission_result.pool,
             ),
         ) {
             eprintln!("Error: Failed to insert pool submission result.\nE: {e}");
         }
     }
 
-    pub fn get_todays_earnings(&self) -> u64 {
+    pub fn add_new_mining_pass(&self, pass: MiningPassRecord) {
+        // Nonces are full u64s, stored bit for bit in sqlite's signed integers
+        if let Err(e) = self.connection.write().unwrap().execute(
+            r#"INSERT INTO mining_passes (
+                challenge,
+                nonce_start,
+                nonce_end,
+                cutoff,
+                hashes,
+                hash_time_ms,
+                hashrate,
+                best_difficulty,
+                overmined_by_ms,
+                pubkey,
+                pool
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)"#,
+            (
+                &pass.challenge,
+                pass.nonce_start as i64,
+                pass.nonce_end as i64,
+                pass.cutoff as i64,
+                pass.hashes as i64,
+                pass.hash_time_ms as i64,
+                pass.hashrate(),
+                pass.best_difficulty,
+                pass.overmined_by_ms,
+                &pass.pubkey,
+                &pass.pool,
+            ),
+        ) {
+            eprintln!("Error: Failed to insert mining pass.\nE: {e}");
+        }
+    }
+
+    /// The most recent passes, newest first, with the pool result for their challenge.
+    pub fn get_recent_passes(&self, limit: u32) -> Vec<MiningPassSummary> {
         match self.connection.write().unwrap().prepare(
-            r#"SELECT SUM(miner_earned) as total_earned
-               FROM pool_submission_results
-               WHERE created_at >= date('now', 'start of day')
+            r#"SELECT p.created_at, p.challenge, p.nonce_start, p.nonce_end, p.cutoff, p.hashes,
+                      p.hash_time_ms, p.best_difficulty, p.overmined_by_ms,
+                      r.pool_difficulty, r.miner_difficulty, r.miner_earned,
+                      p.pubkey, p.pool
+               FROM mining_passes p
+               LEFT JOIN pool_submission_results r
+                   ON r.challenge = p.challenge AND r.pubkey IS p.pubkey AND r.pool IS p.pool
+               ORDER BY p.id DESC
+               LIMIT ?1
             "#,
         ) {
             Ok(mut stmt) => {
-                let total_earned: Option<u64> = stmt.query_row([], |row| row.get(0)).unwrap();
-                match total_earned {
-                    Some(sum) => return sum,
-                    None => return 0,
+                let passes_iter = stmt
+                    .query_map([limit], |row| {
+                        Ok(MiningPassSummary {
+                            created_at: row.get(0)?,
+                            pass: MiningPassRecord {
+                                challenge: row.get(1)?,
+                                pubkey: row.get::<_, Option<String>>(12)?.unwrap_or_default(),
+                                pool: row.get::<_, Option<String>>(13)?.unwrap_or_default(),
+                                nonce_start: row.get::<_, i64>(2)? as u64,
+                                nonce_end: row.get::<_, i64>(3)? as u64,
+                                cutoff: row.get::<_, i64>(4)? as u64,
+                                hashes: row.get::<_, i64>(5)? as u64,
+                                hash_time_ms: row.get::<_, i64>(6)? as u64,
+                                best_difficulty: row.get(7)?,
+                                overmined_by_ms: row.get(8)?,
+                            },
+                            pool_difficulty: row.get(9)?,
+                            miner_difficulty: row.get(10)?,
+                            miner_earned: row.get(11)?,
+                        })
+                    })
+                    .unwrap();
+
+                let mut passes = vec![];
+                for pass in passes_iter {
+                    match pass {
+                        Ok(pass) => passes.push(pass),
+                        Err(_) => {
+                            eprintln!("Error getting mining pass");
+                        }
+                    }
                 }
+
+                passes
+            }
+            Err(e) => {
+                eprintln!("Error: Failed to get mining passes.\nE: {e}");
+                vec![]
+            }
+        }
+    }
+
+    /// Today's earnings, ignoring the filter's date range.
+    pub fn get_todays_earnings(&self, filter: &EarningsFilter) -> u64 {
+        let filter = EarningsFilter {
+            from: Some(Utc::now().date_naive()),
+            to: None,
+            ..filter.clone()
+        };
+        match self.connection.write().unwrap().prepare(&format!(
+            r#"SELECT SUM(miner_earned) as total_earned
+               FROM pool_submission_results
+               WHERE {}
+            "#,
+            FILTER_SQL,
+        )) {
+            Ok(mut stmt) => {
+                let total_earned: Option<u64> = stmt
+                    .query_row(filter.params(), |row| row.get(0))
+                    .unwrap();
+                total_earned.unwrap_or(0)
             }
             Err(e) => {
                 eprintln!("Error: Failed to get todays earnings.\nE: {e}");
-                return 0;
+                0
             }
         }
     }
 
-    pub fn get_daily_earnings(&self, _days: u32) -> Vec<(String, u64)> {
-        match self.connection.write().unwrap().prepare(
-            r#"SELECT DATE(created_at) as day,SUM(miner_earned) as total_earned
+    /// Earnings in the filter's date range, bucketed by `group_by`, oldest first.
+    pub fn get_earnings_report(
+        &self,
+        filter: &EarningsFilter,
+        group_by: GroupBy,
+    ) -> Vec<EarningsBucket> {
+        match self.connection.write().unwrap().prepare(&format!(
+            r#"SELECT {} as bucket,
+                      COUNT(*) as submissions,
+                      SUM(miner_earned) as total_earned,
+                      SUM(pool_earned) as pool_earned,
+                      AVG(pool_difficulty) as pool_difficulty,
+                      AVG(miner_difficulty) as miner_difficulty,
+                      AVG(miner_percentage) as miner_percentage
                FROM pool_submission_results
-               WHERE created_at >= date('now', '-6 days')
-               GROUP BY DATE(created_at)
-               ORDER BY DATE(created_at)
+               WHERE {}
+               GROUP BY bucket
+               ORDER BY bucket
             "#,
-        ) {
+            group_by.sql(),
+            FILTER_SQL,
+        )) {
             Ok(mut stmt) => {
                 let earnings_iter = stmt
-                    .query_map([], |row| {
-                        let day: String = row.get(0).unwrap();
-                        let total_earned: u64 = row.get(1).unwrap();
-                        Ok((day, total_earned))
+                    .query_map(filter.params(), |row| {
+                        Ok(EarningsBucket {
+                            bucket: row.get(0)?,
+                            submissions: row.get(1)?,
+                            miner_earned: row.get(2)?,
+                            pool_earned: row.get(3)?,
+                            avg_pool_difficulty: row.get(4)?,
+                            avg_miner_difficulty: row.get(5)?,
+                            avg_miner_percentage: row.get(6)?,
+                        })
                     })
                     .unwrap();
 
                 let mut earnings = vec![];
                 for earning in earnings_iter {
                     match earning {
-                        Ok((day, total_earned)) => {
-                            earnings.push((day, total_earned));
+                        Ok(earning) => {
+                            earnings.push(earning);
                         }
                         Err(_) => {
                             eprintln!("Error getting earning");
@@ -140,12 +366,173 @@ impl AppDatabase {
                     }
                 }
 
-                return earnings;
+                earnings
             }
             Err(e) => {
-                eprintln!("Error: Failed to get todays earnings.\nE: {e}");
-                return vec![];
+                eprintln!("Error: Failed to get earnings report.\nE: {e}");
+                vec![]
             }
         }
     }
+
+    /// Totals per wallet and pool in the filter's date range, highest earner first.
+    pub fn get_wallet_earnings(&self, filter: &EarningsFilter) -> Vec<WalletEarnings> {
+        match self.connection.write().unwrap().prepare(&format!(
+            r#"SELECT pubkey, pool, COUNT(*) as submissions, SUM(miner_earned) as total_earned
+               FROM pool_submission_results
+               WHERE {}
+               GROUP BY pubkey, pool
+               ORDER BY total_earned DESC
+            "#,
+            FILTER_SQL,
+        )) {
+            Ok(mut stmt) => {
+                let wallets_iter = stmt
+                    .query_map(filter.params(), |row| {
+                        Ok(WalletEarnings {
+                            pubkey: row.get(0)?,
+                            pool: row.get(1)?,
+                            submissions: row.get(2)?,
+                            miner_earned: row.get(3)?,
+                        })
+                    })
+                    .unwrap();
+
+                let mut wallets = vec![];
+                for wallet in wallets_iter {
+                    match wallet {
+                        Ok(wallet) => wallets.push(wallet),
+                        Err(_) => {
+                            eprintln!("Error getting wallet earnings");
+                        }
+                    }
+                }
+
+                wallets
+            }
+            Err(e) => {
+                eprintln!("Error: Failed to get wallet earnings.\nE: {e}");
+                vec![]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_db::TempDb;
+
+    fn temp_db(name: &str) -> (TempDb, AppDatabase) {
+        let path = TempDb::new(&format!("database-{}", name));
+        let app_db = AppDatabase::open(&path).unwrap();
+        (path, app_db)
+    }
+
+    fn result(pubkey: &str, pool: &str, miner_earned: u64) -> PoolSubmissionResult {
+        PoolSubmissionResult::new("challenge".to_string(), 20, 1_000, 1.0, 15, miner_earned)
+            .for_miner(pubkey.to_string(), pool.to_string())
+    }
+
+    #[test]
+    fn filters_earnings_by_wallet_and_pool() {
+        let (_path, app_db) = temp_db("wallets");
+        app_db.add_new_pool_submission(result("alice", "ec1ipse.me", 10));
+        app_db.add_new_pool_submission(result("alice", "other.pool", 20));
+        app_db.add_new_pool_submission(result("bob", "ec1ipse.me", 40));
+        // Recorded before wallets were tracked
+        app_db.add_new_pool_submission(PoolSubmissionResult::new(
+            "challenge".to_string(),
+            20,
+            1_000,
+            1.0,
+            15,
+            80,
+        ));
+
+        let alice = EarningsFilter {
+            pubkey: Some("alice".to_string()),
+            ..Default::default()
+        };
+        let alice_here = EarningsFilter {
+            pool: Some("ec1ipse.me".to_string()),
+            ..alice.clone()
+        };
+        assert_eq!(app_db.get_todays_earnings(&EarningsFilter::default()), 150);
+        assert_eq!(app_db.get_todays_earnings(&alice), 30);
+        assert_eq!(app_db.get_todays_earnings(&alice_here), 10);
+
+        let wallets = app_db.get_wallet_earnings(&EarningsFilter::default());
+        let totals: Vec<_> = wallets
+            .iter()
+            .map(|w| (w.pubkey.as_deref(), w.pool.as_deref(), w.miner_earned))
+            .collect();
+        assert_eq!(
+            totals,
+            vec![
+                (None, None, 80),
+                (Some("bob"), Some("ec1ipse.me"), 40),
+                (Some("alice"), Some("other.pool"), 20),
+                (Some("alice"), Some("ec1ipse.me"), 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_earnings_by_period_within_range() {
+        let (_path, app_db) = temp_db("report");
+        for (created_at, difficulty, earned) in [
+            ("2024-09-30 23:10:00", 10, 1),
+            ("2024-10-01 08:15:00", 20, 2),
+            ("2024-10-01 08:45:00", 30, 4),
+            ("2024-10-07 12:00:00", 40, 8),
+            ("2024-11-02 12:00:00", 50, 16),
+        ] {
+            app_db
+                .connection
+                .write()
+                .unwrap()
+                .execute(
+                    r#"INSERT INTO pool_submission_results (
+                        pool_difficulty, pool_earned, miner_percentage, miner_difficulty,
+                        miner_earned, created_at
+                    ) VALUES (?1, 100, 5.0, 15, ?2, ?3)"#,
+                    (difficulty, earned, created_at),
+                )
+                .unwrap();
+        }
+        let october = EarningsFilter {
+            from: NaiveDate::from_ymd_opt(2024, 10, 1),
+            to: NaiveDate::from_ymd_opt(2024, 10, 31),
+            ..Default::default()
+        };
+        let totals = |group_by| {
+            app_db
+                .get_earnings_report(&october, group_by)
+                .into_iter()
+                .map(|b| (b.bucket, b.submissions, b.miner_earned))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            totals(GroupBy::Hour),
+            vec![
+                ("2024-10-01 08:00".to_string(), 2, 6),
+                ("2024-10-07 12:00".to_string(), 1, 8),
+            ]
+        );
+        // 2024-10-07 is a Monday, the 1st falls in the week starting 2024-09-30
+        assert_eq!(
+            totals(GroupBy::Week),
+            vec![
+                ("2024-09-30".to_string(), 2, 6),
+                ("2024-10-07".to_string(), 1, 8),
+            ]
+        );
+        assert_eq!(totals(GroupBy::Month), vec![("2024-10".to_string(), 3, 14)]);
+
+        let day = &app_db.get_earnings_report(&october, GroupBy::Day)[0];
+        assert_eq!(day.avg_pool_difficulty, 25.0);
+        assert_eq!(day.pool_earned, 200);
+    }
 }
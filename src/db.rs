@@ -0,0 +1,96 @@
+use clap::{arg, Parser, Subcommand};
+use colored::*;
+use rusqlite::Connection;
+use std::path::PathBuf;
+
+use crate::database::DATABASE_PATH;
+use crate::error::Error;
+use crate::migrations::{self, MigrationError};
+
+#[derive(Debug, Parser)]
+pub struct DbArgs {
+    #[command(subcommand)]
+    pub command: DbCommand,
+    #[arg(
+        long,
+        value_name = "PATH",
+        default_value = DATABASE_PATH,
+        help = "Path to the local app database"
+    )]
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DbCommand {
+    #[command(about = "Apply pending schema migrations, backing up the database first if needed.")]
+    Migrate,
+    #[command(about = "Show the schema version and any pending migrations.")]
+    Status,
+}
+
+pub fn db(args: &DbArgs) -> Result<(), Error> {
+    match args.command {
+        DbCommand::Migrate => migrate(args)?,
+        DbCommand::Status => status(args)?,
+    }
+    Ok(())
+}
+
+fn migrate(args: &DbArgs) -> Result<(), MigrationError> {
+    let mut conn = Connection::open(&args.path)?;
+    let report = migrations::migrate(&mut conn, &args.path)?;
+
+    if let Some(backup) = &report.backup {
+        println!("Backed up database to {}", backup.display());
+    }
+    for migration in &report.applied {
+        println!(
+            "Applied migration {}: {}",
+            migration.version, migration.description
+        );
+    }
+    println!(
+        "{}",
+        format!(
+            "Database is at schema version {}.",
+            migrations::current_version(&conn)?
+        )
+        .green()
+    );
+    Ok(())
+}
+
+fn status(args: &DbArgs) -> Result<(), MigrationError> {
+    if !args.path.exists() {
+        println!("No database at {}.", args.path.display());
+        return Ok(());
+    }
+    let conn = Connection::open(&args.path)?;
+    println!("Database: {}", args.path.display());
+    println!(
+        "Schema version: {} (latest {})",
+        migrations::current_version(&conn)?,
+        migrations::latest_version()
+    );
+
+    let pending = migrations::pending(&conn)?;
+    if pending.is_empty() {
+        println!("{}", "Up to date.".green());
+    }
+    for migration in pending {
+        let note = if migration.destructive {
+            " (destructive, backed up first)"
+        } else {
+            ""
+        };
+        println!(
+            "{}",
+            format!(
+                "Pending migration {}: {}{}",
+                migration.version, migration.description, note
+            )
+            .yellow()
+        );
+    }
+    Ok(())
+}
@@ -1,12 +1,16 @@
-use base64::{prelude::BASE64_STANDARD, Engine};
 use clap::Parser;
 use colored::*;
 use inquire::{InquireError, Text};
-use reqwest::StatusCode;
-use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer, transaction::Transaction};
+use serde::Serialize;
+use solana_sdk::{pubkey::Pubkey, signer::Signer, transaction::Transaction};
 use std::{str::FromStr, time::Duration};
 
-use crate::balance::get_token_balance;
+use crate::api::{ApiError, PoolApi};
+use crate::http::Backoff;
+use crate::error::Error;
+use crate::output;
+use crate::signer::Wallet;
+use crate::textln;
 
 #[derive(Debug, Parser)]
 pub struct BoostArgs {
@@ -23,21 +27,37 @@ pub struct BoostArgs {
         help = "Auto stake input amount when staking window opens."
     )]
     pub auto: bool,
+
+    #[arg(long, short, action, help = "Auto approve confirmations.")]
+    pub y: bool,
 }
 
-pub async fn delegate_boost(args: BoostArgs, key: Keypair, url: String, unsecure: bool) {
-    let base_url = url;
-    let client = reqwest::Client::new();
-    let url_prefix = if unsecure {
-        "http".to_string()
-    } else {
-        "https".to_string()
+#[derive(Debug, Serialize)]
+struct BoostReport {
+    mint: String,
+    amount: f64,
+    status: &'static str,
+}
+
+pub async fn delegate_boost(args: BoostArgs, key: Wallet, url: String, unsecure: bool) -> Result<(), Error> {
+    let api = PoolApi::new(&url, unsecure);
+    let mint = match Pubkey::from_str(&args.mint) {
+        Ok(mint) => mint,
+        Err(_) => {
+            let message = format!("Invalid mint address: {}", args.mint);
+            return Err(Error::Usage(message));
+        }
+    };
+    let balance = match api.token_balance(&key.pubkey(), &mint).await {
+        Ok(balance) => balance.unwrap_or(0.0),
+        Err(e) => {
+            return Err(Error::api("Failed to fetch token balance", e))
+        }
     };
-    let balance = get_token_balance(&key, base_url.clone(), unsecure, args.mint.clone()).await;
 
     // Ensure stake amount does not exceed balance
     let boost_amount = if args.amount > balance {
-        println!(
+        textln!(
             "  You do not have enough to stake {} boost tokens.\n  Adjusting stake amount to the maximum available: {} boost tokens",
             args.amount, balance
         );
@@ -47,154 +67,92 @@ pub async fn delegate_boost(args: BoostArgs, key: Keypair, url: String, unsecure
     };
 
     // RED TEXT
-    match Text::new(
-        &format!(
-            "  Are you sure you want to stake {} boost tokens? (Y/n or 'esc' to cancel)",
-            boost_amount
+    if !args.y {
+        if output::json() {
+            return Err(Error::ConfirmationRequired);
+        }
+        match Text::new(
+            &format!(
+                "  Are you sure you want to stake {} boost tokens? (Y/n or 'esc' to cancel)",
+                boost_amount
+            )
+            .red()
+            .to_string(),
         )
-        .red()
-        .to_string(),
-    )
-    .prompt()
-    {
-        Ok(confirm) => {
-            if confirm.trim().eq_ignore_ascii_case("esc") {
-                println!("  Boosting canceled.");
-                return;
-            } else if confirm.trim().is_empty() || confirm.trim().to_lowercase() == "y" {
-                // Proceed with staking
-            } else {
-                println!("  Boosting canceled.");
-                return;
+        .prompt()
+        {
+            Ok(confirm) => {
+                if confirm.trim().eq_ignore_ascii_case("esc") {
+                    textln!("  Boosting canceled.");
+                    return Ok(());
+                } else if confirm.trim().is_empty() || confirm.trim().to_lowercase() == "y" {
+                    // Proceed with staking
+                } else {
+                    textln!("  Boosting canceled.");
+                    return Ok(());
+                }
+            }
+            Err(InquireError::OperationCanceled) => {
+                textln!("  Boosting operation canceled.");
+                return Ok(());
+            }
+            Err(_) => {
+                textln!("  Invalid input. Boosting canceled.");
+                return Ok(());
             }
-        }
-        Err(InquireError::OperationCanceled) => {
-            println!("  Boosting operation canceled.");
-            return;
-        }
-        Err(_) => {
-            println!("  Invalid input. Boosting canceled.");
-            return;
         }
     }
 
     if !args.auto {
         // Non-auto staking logic
-        let timestamp = get_timestamp(&client, &url_prefix, &base_url).await;
-        println!("  Server Timestamp: {}", timestamp);
+        let timestamp = get_timestamp(&api).await;
+        textln!("  Server Timestamp: {}", timestamp);
         if let Some(secs_passed_hour) = timestamp.checked_rem(600) {
-            println!("  SECS PASSED HOUR: {}", secs_passed_hour);
+            textln!("  SECS PASSED HOUR: {}", secs_passed_hour);
             if secs_passed_hour < 300 {
-                println!("  Staking window opened. Staking...");
+                textln!("  Staking window opened. Staking...");
             } else {
-                println!("  Staking window not currently open. Please use --auto or wait until the start of the next hour.");
-                return;
+                return Err(Error::StakingWindowClosed);
             }
         } else {
-            println!("  Timestamp checked_rem error. Please try again.");
-            return;
+            return Err(Error::Other("Timestamp checked_rem error. Please try again.".to_string()));
         }
     } else {
         // Auto staking logic with retry mechanism
         loop {
-            let timestamp = get_timestamp(&client, &url_prefix, &base_url).await;
-            println!("  Server Timestamp: {}", timestamp);
+            let timestamp = get_timestamp(&api).await;
+            textln!("  Server Timestamp: {}", timestamp);
             if let Some(secs_passed_hour) = timestamp.checked_rem(600) {
                 if secs_passed_hour < 300 {
-                    println!("  Staking window opened. Staking...");
+                    textln!("  Staking window opened. Staking...");
 
                     // Attempt staking transaction
                     loop {
-                        let resp = client
-                            .get(format!(
-                                "{}://{}/pool/authority/pubkey",
-                                url_prefix, base_url
-                            ))
-                            .send()
-                            .await
-                            .unwrap()
-                            .text()
-                            .await
-                            .unwrap();
-                        let pool_pubkey = Pubkey::from_str(&resp).unwrap();
-
-                        let resp = client
-                            .get(format!(
-                                "{}://{}/pool/fee_payer/pubkey",
-                                url_prefix, base_url
-                            ))
-                            .send()
-                            .await
-                            .unwrap()
-                            .text()
-                            .await
-                            .unwrap();
-                        let fee_pubkey = Pubkey::from_str(&resp).unwrap();
-
-                        let resp = client
-                            .get(format!("{}://{}/latest-blockhash", url_prefix, base_url))
-                            .send()
-                            .await
-                            .unwrap()
-                            .text()
-                            .await
-                            .unwrap();
-                        let decoded_blockhash = BASE64_STANDARD.decode(resp).unwrap();
-                        let deserialized_blockhash =
-                            bincode::deserialize(&decoded_blockhash).unwrap();
-
-                        let boost_amount_u64 = (boost_amount
-                            * 10f64.powf(ore_api::consts::TOKEN_DECIMALS as f64))
-                            as u64;
-                        let ix = ore_miner_delegation::instruction::delegate_boost_v2(
-                            key.pubkey(),
-                            pool_pubkey,
-                            Pubkey::from_str(&args.mint).unwrap(),
-                            boost_amount_u64,
-                        );
-
-                        let mut tx = Transaction::new_with_payer(&[ix], Some(&fee_pubkey));
-                        tx.partial_sign(&[&key], deserialized_blockhash);
-                        let serialized_tx = bincode::serialize(&tx).unwrap();
-                        let encoded_tx = BASE64_STANDARD.encode(&serialized_tx);
-
-                        let resp = client
-                            .post(format!(
-                                "{}://{}/v2/stake-boost?pubkey={}&mint={}&amount={}",
-                                url_prefix,
-                                base_url,
-                                key.pubkey().to_string(),
-                                args.mint,
-                                boost_amount_u64
-                            ))
-                            .body(encoded_tx)
-                            .send()
-                            .await;
-
-                        if let Ok(res) = resp {
-                            if let Ok(txt) = res.text().await {
-                                match txt.as_str() {
-                                    "SUCCESS" => {
-                                        println!("  Successfully boosted!");
-                                        return; // Exit the loop and function when successful
-                                    }
-                                    other => {
-                                        println!("  Transaction failed: {}", other);
-                                    }
-                                }
-                            } else {
-                                println!("  Transaction failed, retrying...");
+                        match send_boost(&api, &key, &mint, boost_amount).await {
+                            Ok(()) => {
+                                textln!("  Successfully boosted!");
+                                // Exit the loop and function when successful
+                                emit_boosted(&mint, boost_amount);
+                                return Ok(());
+                            }
+                            Err(ApiError::Rejected(other)) => {
+                                textln!("  Transaction failed: {}", other);
+                            }
+                            // Asking again would only prompt the signer's operator again
+                            Err(e @ ApiError::Signer(_)) => {
+                                return Err(Error::api("Transaction failed", e));
+                            }
+                            Err(e) => {
+                                textln!("  Transaction failed: {}, retrying...", e);
                             }
-                        } else {
-                            println!("  Transaction failed, retrying...");
                         }
 
                         // Wait before trying again
                         tokio::time::sleep(Duration::from_secs(3)).await;
                     }
                 } else {
-                    println!("  Staking window opens in {} minutes {} seconds.", (600 - secs_passed_hour) / 60, secs_passed_hour % 60);
-                    println!("  You can let this run until it is complete.");
+                    textln!("  Staking window opens in {} minutes {} seconds.", (600 - secs_passed_hour) / 60, secs_passed_hour % 60);
+                    textln!("  You can let this run until it is complete.");
                     tokio::time::sleep(Duration::from_secs(60)).await;
                 }
             } else {
@@ -204,111 +162,144 @@ pub async fn delegate_boost(args: BoostArgs, key: Keypair, url: String, unsecure
     }
 
     // Non-auto and auto logic converge for transaction execution
-    let resp = client
-        .get(format!(
-            "{}://{}/pool/authority/pubkey",
-            url_prefix, base_url
-        ))
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-    let pool_pubkey = Pubkey::from_str(&resp).unwrap();
+    match send_boost(&api, &key, &mint, boost_amount).await {
+        Ok(()) => {
+            textln!("  Successfully boosted!");
+            emit_boosted(&mint, boost_amount);
+            Ok(())
+        }
+        Err(e) => Err(Error::api("Transaction failed", e)),
+    }
+}
 
-    let resp = client
-        .get(format!(
-            "{}://{}/pool/fee_payer/pubkey",
-            url_prefix, base_url
-        ))
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-    let fee_pubkey = Pubkey::from_str(&resp).unwrap();
+fn emit_boosted(mint: &Pubkey, amount: f64) {
+    output::emit(
+        "delegate-boost",
+        &BoostReport {
+            mint: mint.to_string(),
+            amount,
+            status: "boosted",
+        },
+    );
+}
 
-    let resp = client
-        .get(format!("{}://{}/latest-blockhash", url_prefix, base_url))
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-    let decoded_blockhash = BASE64_STANDARD.decode(resp).unwrap();
-    let deserialized_blockhash = bincode::deserialize(&decoded_blockhash).unwrap();
+/// Builds the delegate transaction, signs it for `key` and hands it to the pool.
+async fn send_boost(api: &PoolApi, key: &Wallet, mint: &Pubkey, amount: f64) -> Result<(), ApiError> {
+    let pool_pubkey = api.pool_authority().await?;
+    let fee_pubkey = api.fee_payer().await?;
+    let blockhash = api.latest_blockhash().await?;
 
-    let boost_amount_u64 =
-        (boost_amount * 10f64.powf(ore_api::consts::TOKEN_DECIMALS as f64)) as u64;
+    let amount_u64 = (amount * 10f64.powf(ore_api::consts::TOKEN_DECIMALS as f64)) as u64;
     let ix = ore_miner_delegation::instruction::delegate_boost_v2(
         key.pubkey(),
         pool_pubkey,
-        Pubkey::from_str(&args.mint).unwrap(),
-        boost_amount_u64,
+        *mint,
+        amount_u64,
     );
 
     let mut tx = Transaction::new_with_payer(&[ix], Some(&fee_pubkey));
-    tx.partial_sign(&[&key], deserialized_blockhash);
-    let serialized_tx = bincode::serialize(&tx).unwrap();
-    let encoded_tx = BASE64_STANDARD.encode(&serialized_tx);
-
-    let resp = client
-        .post(format!(
-            "{}://{}/v2/stake-boost?pubkey={}&mint={}&amount={}",
-            url_prefix,
-            base_url,
-            key.pubkey().to_string(),
-            args.mint,
-            boost_amount_u64
-        ))
-        .body(encoded_tx)
-        .send()
-        .await;
-    if let Ok(res) = resp {
-        if let Ok(txt) = res.text().await {
-            match txt.as_str() {
-                "SUCCESS" => {
-                    println!("  Successfully boosted!");
-                }
-                other => {
-                    println!("  Transaction failed: {}", other);
-                }
-            }
-        } else {
-            println!("  Transaction failed, please wait and try again.");
-        }
-    } else {
-        println!("  Transaction failed, please wait and try again.");
-    }
+    key.sign_transaction(&mut tx, blockhash).await?;
+    api.stake_boost(&key.pubkey(), mint, amount_u64, &tx).await
 }
 
 // Helper function to fetch server timestamp
-async fn get_timestamp(client: &reqwest::Client, url_prefix: &str, base_url: &str) -> u64 {
+async fn get_timestamp(api: &PoolApi) -> u64 {
+    let mut backoff = Backoff::new(Duration::from_secs(3), Duration::from_secs(30));
     loop {
-        if let Ok(response) = client
-            .get(format!("{}://{}/timestamp", url_prefix, base_url))
-            .send()
-            .await
-        {
-            match response.status() {
-                StatusCode::OK => {
-                    if let Ok(ts) = response.text().await {
-                        if let Ok(ts) = ts.parse::<u64>() {
-                            return ts;
-                        }
-                    }
-                }
-                _ => {
-                    println!("  Server restarting, trying again in 3 seconds...");
-                    tokio::time::sleep(Duration::from_secs(3)).await;
-                    continue;
-                }
+        let delay = backoff.next_delay();
+        match api.timestamp().await {
+            Ok(ts) => return ts,
+            Err(ApiError::Server { .. }) => {
+                textln!(
+                    "  Server restarting, trying again in {} seconds...",
+                    delay.as_secs()
+                );
+            }
+            Err(e) => {
+                textln!(
+                    "  Unable to retrieve timestamp ({}), retrying in {} seconds...",
+                    e,
+                    delay.as_secs()
+                );
             }
         }
-        println!("  Unable to retrieve timestamp, retrying in 3 seconds...");
-        tokio::time::sleep(Duration::from_secs(3)).await;
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_pool::MockPool;
+    use solana_sdk::signature::Keypair;
+    use base64::{prelude::BASE64_STANDARD, Engine};
+    use solana_sdk::hash::Hash;
+
+    const ORE_MINT: &str = "oreoU2P8bN6jkk3jbaiVxYnG1dCXcYxwhwyK9jSybcp";
+
+    fn boost_pool(pool: &MockPool, fee_payer: Pubkey) {
+        pool.respond("/v2/miner/balance", 200, "10");
+        pool.respond("/pool/authority/pubkey", 200, Pubkey::new_unique().to_string());
+        pool.respond("/pool/fee_payer/pubkey", 200, fee_payer.to_string());
+        pool.respond(
+            "/latest-blockhash",
+            200,
+            BASE64_STANDARD.encode(bincode::serialize(&Hash::new_unique()).unwrap()),
+        );
+        pool.respond("/v2/stake-boost", 200, "SUCCESS");
+    }
+
+    #[tokio::test]
+    async fn boost_sends_partially_signed_transaction() {
+        let pool = MockPool::start().await;
+        let fee_payer = Pubkey::new_unique();
+        boost_pool(&pool, fee_payer);
+        // 200 seconds into the 10 minute cycle, the staking window is open
+        pool.set_timestamp(1_700_000_000);
+        let key = Keypair::new();
+        let pubkey = key.pubkey();
+
+        let args = BoostArgs {
+            amount: 2.5,
+            mint: ORE_MINT.to_string(),
+            auto: false,
+            y: true,
+        };
+        delegate_boost(args, key.into(), pool.url(), true).await.unwrap();
+
+        let state = pool.state();
+        let boosts = state.requests_to("/v2/stake-boost");
+        assert_eq!(boosts.len(), 1);
+        assert_eq!(boosts[0].query["amount"], "250000000000");
+
+        let tx: Transaction =
+            bincode::deserialize(&BASE64_STANDARD.decode(&boosts[0].body).unwrap()).unwrap();
+        assert_eq!(tx.message.account_keys[0], fee_payer);
+        let index = tx
+            .message
+            .account_keys
+            .iter()
+            .position(|k| *k == pubkey)
+            .unwrap();
+        assert!(tx.signatures[index].verify(&pubkey.to_bytes(), &tx.message_data()));
+    }
+
+    #[tokio::test]
+    async fn boost_waits_for_staking_window() {
+        let pool = MockPool::start().await;
+        boost_pool(&pool, Pubkey::new_unique());
+        // 500 seconds into the cycle, the window has closed
+        pool.set_timestamp(1_700_000_300);
+
+        let args = BoostArgs {
+            amount: 2.5,
+            mint: ORE_MINT.to_string(),
+            auto: false,
+            y: true,
+        };
+        let result = delegate_boost(args, Keypair::new().into(), pool.url(), true).await;
+
+        assert!(matches!(result, Err(Error::StakingWindowClosed)));
+        assert!(pool.state().requests_to("/v2/stake-boost").is_empty());
     }
 }
@@ -1,17 +1,253 @@
-use spl_token::amount_to_ui_amount;
+use chrono::{Duration, NaiveDate, Utc};
+use clap::{arg, Parser, ValueEnum};
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+use spl_token::{amount_to_ui_amount, amount_to_ui_amount_string};
 
-use crate::database::AppDatabase;
+use crate::database::{AppDatabase, EarningsBucket, EarningsFilter, GroupBy, WalletEarnings};
+use crate::error::Error;
+use crate::output;
 
-pub fn earnings() {
-    let app_db = AppDatabase::new();
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Table,
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Parser)]
+pub struct EarningsArgs {
+    #[arg(
+        long,
+        value_name = "PUBKEY",
+        help = "Only count earnings mined by this wallet"
+    )]
+    pub pubkey: Option<Pubkey>,
+    #[arg(
+        long,
+        value_name = "HOST",
+        help = "Only count earnings from this pool host, as passed to --url"
+    )]
+    pub pool: Option<String>,
+    #[arg(
+        long,
+        value_name = "YYYY-MM-DD",
+        help = "First UTC day to include [default: 6 days ago]"
+    )]
+    pub from: Option<NaiveDate>,
+    #[arg(
+        long,
+        value_name = "YYYY-MM-DD",
+        help = "Last UTC day to include [default: today]"
+    )]
+    pub to: Option<NaiveDate>,
+    #[arg(
+        long,
+        value_name = "PERIOD",
+        default_value = "day",
+        help = "Total earnings per hour, day, week or month"
+    )]
+    pub group_by: GroupBy,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "table",
+        help = "Print a table, or export as csv or json"
+    )]
+    pub format: ReportFormat,
+}
+
+impl Default for EarningsArgs {
+    fn default() -> Self {
+        EarningsArgs {
+            pubkey: None,
+            pool: None,
+            from: None,
+            to: None,
+            group_by: GroupBy::Day,
+            format: ReportFormat::Table,
+        }
+    }
+}
+
+/// A report row with amounts in ORE, for exports.
+#[derive(Serialize)]
+struct ExportRow<'a> {
+    period: &'a str,
+    submissions: u64,
+    miner_earned_ore: String,
+    pool_earned_ore: String,
+    avg_pool_difficulty: f64,
+    avg_miner_difficulty: f64,
+    avg_miner_percentage: f64,
+}
+
+/// Totals for one wallet and pool, in ORE.
+#[derive(Serialize)]
+struct WalletRow<'a> {
+    pubkey: Option<&'a str>,
+    pool: Option<&'a str>,
+    submissions: u64,
+    miner_earned_ore: String,
+}
+
+/// The `--output json` document: the report plus the per-wallet totals the table shows.
+#[derive(Serialize)]
+struct EarningsReport<'a> {
+    group_by: GroupBy,
+    from: Option<String>,
+    to: Option<String>,
+    periods: Vec<ExportRow<'a>>,
+    wallets: Vec<WalletRow<'a>>,
+}
+
+impl<'a> From<&'a WalletEarnings> for WalletRow<'a> {
+    fn from(w: &'a WalletEarnings) -> Self {
+        WalletRow {
+            pubkey: w.pubkey.as_deref(),
+            pool: w.pool.as_deref(),
+            submissions: w.submissions,
+            miner_earned_ore: amount_to_ui_amount_string(w.miner_earned, ore_api::consts::TOKEN_DECIMALS),
+        }
+    }
+}
+
+impl<'a> From<&'a EarningsBucket> for ExportRow<'a> {
+    fn from(b: &'a EarningsBucket) -> Self {
+        ExportRow {
+            period: &b.bucket,
+            submissions: b.submissions,
+            miner_earned_ore: amount_to_ui_amount_string(b.miner_earned, ore_api::consts::TOKEN_DECIMALS),
+            pool_earned_ore: amount_to_ui_amount_string(b.pool_earned, ore_api::consts::TOKEN_DECIMALS),
+            avg_pool_difficulty: b.avg_pool_difficulty,
+            avg_miner_difficulty: b.avg_miner_difficulty,
+            avg_miner_percentage: b.avg_miner_percentage,
+        }
+    }
+}
+
+pub fn earnings(args: &EarningsArgs) -> Result<(), Error> {
+    let app_db = AppDatabase::new()?;
+    let filter = EarningsFilter {
+        pubkey: args.pubkey.map(|p| p.to_string()),
+        pool: args.pool.clone(),
+        from: Some(
+            args.from
+                .unwrap_or_else(|| Utc::now().date_naive() - Duration::days(6)),
+        ),
+        to: args.to,
+    };
 
-    let daily_earnings = app_db.get_daily_earnings(7);
+    let report = app_db.get_earnings_report(&filter, args.group_by);
 
-    for de in daily_earnings {
+    // --output json takes over from --format so every command speaks the same shape
+    if output::json() {
+        let wallets = app_db.get_wallet_earnings(&filter);
+        output::emit(
+            "earnings",
+            &EarningsReport {
+                group_by: args.group_by,
+                from: filter.from.map(|d| d.to_string()),
+                to: filter.to.map(|d| d.to_string()),
+                periods: report.iter().map(ExportRow::from).collect(),
+                wallets: wallets.iter().map(WalletRow::from).collect(),
+            },
+        );
+        return Ok(());
+    }
+
+    match args.format {
+        ReportFormat::Table => {
+            for b in &report {
+                println!(
+                    "{}: {}, Total Mined: {} ORE, Submissions: {}, Avg Pool Diff: {:.1}, Avg Miner Diff: {:.1}, Avg Share: {:.4}%",
+                    period_label(args.group_by),
+                    b.bucket,
+                    amount_to_ui_amount(b.miner_earned, ore_api::consts::TOKEN_DECIMALS),
+                    b.submissions,
+                    b.avg_pool_difficulty,
+                    b.avg_miner_difficulty,
+                    b.avg_miner_percentage,
+                );
+            }
+            print_wallets(&app_db, &filter);
+        }
+        ReportFormat::Csv => {
+            print!("{}", to_csv(&report));
+        }
+        ReportFormat::Json => {
+            let rows: Vec<ExportRow> = report.iter().map(ExportRow::from).collect();
+            println!("{}", serde_json::to_string_pretty(&rows).unwrap());
+        }
+    }
+    Ok(())
+}
+
+fn period_label(group_by: GroupBy) -> &'static str {
+    match group_by {
+        GroupBy::Hour => "Hour",
+        GroupBy::Day => "Day",
+        GroupBy::Week => "Week of",
+        GroupBy::Month => "Month",
+    }
+}
+
+fn to_csv(report: &[EarningsBucket]) -> String {
+    let mut csv = String::from(
+        "period,submissions,miner_earned_ore,pool_earned_ore,avg_pool_difficulty,avg_miner_difficulty,avg_miner_percentage\n",
+    );
+    for b in report {
+        let row = ExportRow::from(b);
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            row.period,
+            row.submissions,
+            row.miner_earned_ore,
+            row.pool_earned_ore,
+            row.avg_pool_difficulty,
+            row.avg_miner_difficulty,
+            row.avg_miner_percentage,
+        ));
+    }
+    csv
+}
+
+fn print_wallets(app_db: &AppDatabase, filter: &EarningsFilter) {
+    let wallets = app_db.get_wallet_earnings(filter);
+    if !wallets.is_empty() {
+        println!("\nBy wallet:");
+    }
+    for w in wallets {
         println!(
-            "Day: {}, Total Mined: {} ORE",
-            de.0,
-            amount_to_ui_amount(de.1, ore_api::consts::TOKEN_DECIMALS)
+            "Wallet: {}, Pool: {}, Submissions: {}, Total Mined: {} ORE",
+            w.pubkey.as_deref().unwrap_or("unknown"),
+            w.pool.as_deref().unwrap_or("unknown"),
+            w.submissions,
+            amount_to_ui_amount(w.miner_earned, ore_api::consts::TOKEN_DECIMALS)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_has_a_row_per_period_in_ore() {
+        let report = vec![EarningsBucket {
+            bucket: "2024-10-07".to_string(),
+            submissions: 3,
+            miner_earned: 150_000_000_000,
+            pool_earned: 2_000_000_000_000,
+            avg_pool_difficulty: 24.5,
+            avg_miner_difficulty: 18.0,
+            avg_miner_percentage: 7.5,
+        }];
+
+        assert_eq!(
+            to_csv(&report),
+            "period,submissions,miner_earned_ore,pool_earned_ore,avg_pool_difficulty,avg_miner_difficulty,avg_miner_percentage\n\
+             2024-10-07,3,1.50000000000,20.00000000000,24.5,18,7.5\n"
         );
     }
 }
@@ -0,0 +1,272 @@
+//! The error every command returns, and the process exit code for each kind of failure.
+//!
+//! Commands print their progress as they go and return an `Error` when they can't finish;
+//! `main` prints it, or the `--output json` error document, and exits with `exit_code()`
+//! so scripts can tell a failed claim from a queued one.
+
+use std::fmt;
+use std::time::Duration;
+
+use inquire::InquireError;
+use solana_sdk::signer::SignerError;
+
+use crate::api::ApiError;
+use crate::config::ConfigError;
+use crate::http::HttpError;
+use crate::keystore::KeystoreError;
+use crate::migrations::MigrationError;
+
+/// Process exit codes, stable so scripts can branch on them.
+pub mod exit {
+    /// Anything without a more specific code
+    pub const FAILURE: i32 = 1;
+    /// Bad arguments, or a prompt that `--output json` can't answer, such as a passphrase
+    pub const USAGE: i32 = 2;
+    pub const NOT_SIGNED_UP: i32 = 3;
+    pub const NO_STAKE_ACCOUNT: i32 = 4;
+    /// The pool refused a claim or transaction
+    pub const REJECTED: i32 = 5;
+    pub const RATE_LIMITED: i32 = 6;
+    /// The pool failed or answered with something unexpected
+    pub const SERVER: i32 = 7;
+    /// The pool could not be reached
+    pub const NETWORK: i32 = 8;
+    /// Below the minimum claim, or nothing to claim
+    pub const NOTHING_TO_CLAIM: i32 = 9;
+    /// Not allowed yet: claim cooldown, staking window closed
+    pub const TRY_LATER: i32 = 10;
+    /// The config file, keypair or local database couldn't be used, or a wrong passphrase
+    pub const LOCAL: i32 = 11;
+    /// The remote signer couldn't be reached or refused to sign
+    pub const SIGNER: i32 = 12;
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Usage(String),
+    /// `--output json` never prompts, so confirmations have to come from `-y`
+    ConfirmationRequired,
+    /// A pool request failed while doing `context`
+    Api {
+        context: String,
+        source: ApiError,
+    },
+    NoStakeAccount {
+        mint: String,
+    },
+    BelowMinimumClaim,
+    NothingToClaim,
+    ClaimCooldown {
+        wait: Duration,
+    },
+    StakingWindowClosed,
+    /// Some boost migrations went through and others didn't
+    MigrationsFailed {
+        failed: usize,
+    },
+    Keypair {
+        path: String,
+    },
+    /// An encrypted keypair couldn't be read, decrypted or written
+    Keystore {
+        path: String,
+        source: KeystoreError,
+    },
+    /// The remote signer couldn't be reached or answered with nonsense
+    Signer(SignerError),
+    Config(ConfigError),
+    Database(MigrationError),
+    Prompt(InquireError),
+    Other(String),
+}
+
+impl Error {
+    pub fn api(context: impl Into<String>, source: ApiError) -> Self {
+        Error::Api {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// Machine-readable name of the failure, for `--output json`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Usage(_) => "usage",
+            Error::ConfirmationRequired => "confirmation_required",
+            Error::Api { source, .. } => match source {
+                ApiError::NotSignedUp => "not_signed_up",
+                ApiError::NoStakeAccount => "no_stake_account",
+                ApiError::RateLimited { .. } => "rate_limited",
+                ApiError::Server { .. } => "server_error",
+                ApiError::Rejected(_) => "rejected",
+                ApiError::InvalidResponse { .. } => "invalid_response",
+                ApiError::Http(HttpError::CircuitOpen { .. }) => "pool_unavailable",
+                ApiError::Http(_) => "network_error",
+                ApiError::Signer(e) => signer_code(e),
+            },
+            Error::NoStakeAccount { .. } => "no_stake_account",
+            Error::BelowMinimumClaim => "below_minimum",
+            Error::NothingToClaim => "nothing_to_claim",
+            Error::ClaimCooldown { .. } => "cooldown",
+            Error::StakingWindowClosed => "staking_window_closed",
+            Error::MigrationsFailed { .. } => "migrations_failed",
+            Error::Keypair { .. } => "keypair",
+            Error::Keystore { source, .. } => match source {
+                KeystoreError::WrongPassphrase => "wrong_passphrase",
+                KeystoreError::PassphraseRequired => "passphrase_required",
+                _ => "keystore",
+            },
+            Error::Signer(e) => signer_code(e),
+            Error::Config(_) => "config",
+            Error::Database(_) => "database",
+            Error::Prompt(_) => "prompt",
+            Error::Other(_) => "failed",
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Usage(_) | Error::ConfirmationRequired => exit::USAGE,
+            Error::Keystore {
+                source: KeystoreError::PassphraseRequired,
+                ..
+            } => exit::USAGE,
+            Error::Api { source, .. } => match source {
+                ApiError::NotSignedUp => exit::NOT_SIGNED_UP,
+                ApiError::NoStakeAccount => exit::NO_STAKE_ACCOUNT,
+                ApiError::RateLimited { .. } => exit::RATE_LIMITED,
+                ApiError::Server { .. } | ApiError::InvalidResponse { .. } => exit::SERVER,
+                ApiError::Rejected(_) => exit::REJECTED,
+                ApiError::Http(_) => exit::NETWORK,
+                ApiError::Signer(_) => exit::SIGNER,
+            },
+            Error::NoStakeAccount { .. } => exit::NO_STAKE_ACCOUNT,
+            Error::Signer(_) => exit::SIGNER,
+            Error::BelowMinimumClaim | Error::NothingToClaim => exit::NOTHING_TO_CLAIM,
+            Error::ClaimCooldown { .. } | Error::StakingWindowClosed => exit::TRY_LATER,
+            Error::Keypair { .. }
+            | Error::Keystore { .. }
+            | Error::Config(_)
+            | Error::Database(_) => exit::LOCAL,
+            Error::MigrationsFailed { .. } | Error::Prompt(_) | Error::Other(_) => exit::FAILURE,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Usage(message) | Error::Other(message) => write!(f, "{}", message),
+            Error::ConfirmationRequired => {
+                write!(f, "confirmation required, pass -y to approve with --output json")
+            }
+            Error::Api { context, source } => write!(f, "{}: {}", context, source),
+            Error::NoStakeAccount { mint } => {
+                write!(f, "Failed to find stake account for mint: {}", mint)
+            }
+            Error::BelowMinimumClaim => write!(
+                f,
+                "You have not reached the required claim limit of 0.005 ORE. Keep accumulating rewards before you can withdraw."
+            ),
+            Error::NothingToClaim => write!(f, "There are no rewards to claim."),
+            Error::ClaimCooldown { wait } => write!(
+                f,
+                "You cannot claim until the time is up. Time left until next claim available: {}m {}s",
+                (wait.as_secs() / 60) % 60,
+                wait.as_secs() % 60
+            ),
+            Error::StakingWindowClosed => write!(
+                f,
+                "Staking window not currently open. Please use --auto or wait until the start of the next hour."
+            ),
+            Error::MigrationsFailed { failed } => {
+                write!(f, "{} boost migration(s) failed, please wait and try again.", failed)
+            }
+            Error::Keypair { path } => write!(f, "Failed to load keypair from file: {}", path),
+            Error::Keystore { path, source } => write!(f, "{}: {}", path, source),
+            Error::Signer(e) => write!(f, "Remote signer: {}", e),
+            Error::Config(e) => write!(f, "{}", e),
+            Error::Database(e) => write!(f, "{}", e),
+            Error::Prompt(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Api { source, .. } => Some(source),
+            Error::Keystore { source, .. } => Some(source),
+            Error::Signer(e) => Some(e),
+            Error::Config(e) => Some(e),
+            Error::Database(e) => Some(e),
+            Error::Prompt(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+fn signer_code(e: &SignerError) -> &'static str {
+    match e {
+        SignerError::UserCancel(_) => "signature_refused",
+        SignerError::Connection(_) => "signer_unreachable",
+        _ => "signer",
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(e: ConfigError) -> Self {
+        Error::Config(e)
+    }
+}
+
+impl From<MigrationError> for Error {
+    fn from(e: MigrationError) -> Self {
+        Error::Database(e)
+    }
+}
+
+impl From<InquireError> for Error {
+    fn from(e: InquireError) -> Self {
+        Error::Prompt(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failure_classes_have_distinct_exit_codes() {
+        let not_signed_up = Error::api("There are no rewards to claim", ApiError::NotSignedUp);
+        assert_eq!(not_signed_up.code(), "not_signed_up");
+        assert_eq!(not_signed_up.exit_code(), exit::NOT_SIGNED_UP);
+        assert_eq!(
+            not_signed_up.to_string(),
+            "There are no rewards to claim: this wallet is not signed up with the pool"
+        );
+
+        let rejected = Error::api("Transaction failed", ApiError::Rejected("nope".into()));
+        assert_eq!(rejected.exit_code(), exit::REJECTED);
+
+        let cooldown = Error::ClaimCooldown {
+            wait: Duration::from_secs(125),
+        };
+        assert_eq!(cooldown.exit_code(), exit::TRY_LATER);
+        assert!(cooldown.to_string().ends_with("2m 5s"));
+
+        let refused = Error::api(
+            "Failed to claim",
+            ApiError::Signer(SignerError::UserCancel("no".into())),
+        );
+        assert_eq!(refused.code(), "signature_refused");
+        assert_eq!(refused.exit_code(), exit::SIGNER);
+        assert_eq!(
+            Error::Signer(SignerError::Connection("down".into())).code(),
+            "signer_unreachable"
+        );
+
+        assert_eq!(Error::ConfirmationRequired.exit_code(), exit::USAGE);
+        assert_ne!(Error::NothingToClaim.exit_code(), 0);
+    }
+}
@@ -0,0 +1,140 @@
+//! Ordered pool servers for a mining session, with the health of each.
+//!
+//! The session mines on the first pool until it keeps failing, then moves down the
+//! list. While it is on a backup it probes the primary and goes back once it answers.
+
+use std::time::Instant;
+
+#[derive(Debug, Clone)]
+pub struct PoolHealth {
+    pub url: String,
+    /// Failed attempts since the last pass handed out by this pool
+    pub consecutive_failures: u32,
+    pub last_failure: Option<Instant>,
+    pub last_success: Option<Instant>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PoolList {
+    pools: Vec<PoolHealth>,
+    active: usize,
+    /// Consecutive failures before moving to the next pool
+    failover_after: u32,
+}
+
+impl PoolList {
+    /// `urls[0]` is the primary. Duplicates are dropped.
+    pub fn new(urls: Vec<String>, failover_after: u32) -> Self {
+        let mut pools: Vec<PoolHealth> = Vec::new();
+        for url in urls {
+            if !pools.iter().any(|p| p.url == url) {
+                pools.push(PoolHealth {
+                    url,
+                    consecutive_failures: 0,
+                    last_failure: None,
+                    last_success: None,
+                });
+            }
+        }
+        assert!(!pools.is_empty(), "a session needs at least one pool");
+        PoolList {
+            pools,
+            active: 0,
+            failover_after: failover_after.max(1),
+        }
+    }
+
+    pub fn active(&self) -> &PoolHealth {
+        &self.pools[self.active]
+    }
+
+    pub fn primary(&self) -> &PoolHealth {
+        &self.pools[0]
+    }
+
+    pub fn on_primary(&self) -> bool {
+        self.active == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.pools.len()
+    }
+
+    /// The active pool handed out work.
+    pub fn record_success(&mut self) {
+        let pool = &mut self.pools[self.active];
+        pool.consecutive_failures = 0;
+        pool.last_success = Some(Instant::now());
+    }
+
+    /// Counts a failed attempt on the active pool. Once it has failed `failover_after`
+    /// times in a row the next pool becomes active and its url is returned.
+    pub fn record_failure(&mut self) -> Option<&str> {
+        let pool = &mut self.pools[self.active];
+        pool.consecutive_failures += 1;
+        pool.last_failure = Some(Instant::now());
+        if pool.consecutive_failures >= self.failover_after {
+            self.fail_over()
+        } else {
+            None
+        }
+    }
+
+    /// Gives up on the active pool at once, e.g. when it stopped sending work.
+    pub fn fail_over(&mut self) -> Option<&str> {
+        if self.pools.len() < 2 {
+            return None;
+        }
+        self.pools[self.active].consecutive_failures = 0;
+        self.active = (self.active + 1) % self.pools.len();
+        Some(&self.pools[self.active].url)
+    }
+
+    /// Makes the primary active again after it answered a probe.
+    pub fn return_to_primary(&mut self) {
+        self.active = 0;
+        self.pools[0].consecutive_failures = 0;
+        self.pools[0].last_success = Some(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(list: &[&str]) -> Vec<String> {
+        list.iter().map(|u| u.to_string()).collect()
+    }
+
+    #[test]
+    fn fails_over_in_order_after_repeated_failures() {
+        let mut pools = PoolList::new(urls(&["a", "b", "c", "b"]), 2);
+        assert_eq!(pools.len(), 3);
+
+        assert_eq!(pools.record_failure(), None);
+        assert_eq!(pools.record_failure(), Some("b"));
+        assert!(!pools.on_primary());
+
+        // Success resets the count
+        pools.record_failure();
+        pools.record_success();
+        assert_eq!(pools.record_failure(), None);
+        assert_eq!(pools.record_failure(), Some("c"));
+        assert_eq!(pools.fail_over(), Some("a"));
+        assert!(pools.on_primary());
+
+        pools.fail_over();
+        pools.return_to_primary();
+        assert_eq!(pools.active().url, "a");
+        assert_eq!(pools.primary().consecutive_failures, 0);
+    }
+
+    #[test]
+    fn a_single_pool_never_fails_over() {
+        let mut pools = PoolList::new(urls(&["a"]), 1);
+        assert_eq!(pools.record_failure(), None);
+        assert_eq!(pools.fail_over(), None);
+        assert_eq!(pools.active().url, "a");
+        assert_eq!(pools.active().consecutive_failures, 1);
+    }
+}
@@ -0,0 +1,486 @@
+//! The one HTTP client every pool call goes through.
+//!
+//! Requests get a timeout and a retry policy from their `EndpointClass`. Retries back
+//! off exponentially with jitter so a fleet of rigs doesn't hit a restarting pool in
+//! lockstep, and a per-host circuit breaker stops calling a pool that keeps failing.
+
+use once_cell::sync::Lazy;
+use rand::Rng;
+use reqwest::{Method, StatusCode};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// What a request does, which decides how it may be retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndpointClass {
+    /// Idempotent reads such as balances and rewards
+    Read,
+    /// `/timestamp` and `/latest-blockhash`, which go stale quickly
+    Auth,
+    /// Requests that change state, e.g. claims and stake transactions. Only retried
+    /// when the pool can't have acted on them.
+    Transaction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    pub timeout: Duration,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl EndpointClass {
+    pub fn policy(self) -> RetryPolicy {
+        match self {
+            EndpointClass::Read => RetryPolicy {
+                attempts: 4,
+                timeout: Duration::from_secs(10),
+                base_delay: Duration::from_millis(500),
+                max_delay: Duration::from_secs(8),
+            },
+            EndpointClass::Auth => RetryPolicy {
+                attempts: 3,
+                timeout: Duration::from_secs(5),
+                base_delay: Duration::from_millis(250),
+                max_delay: Duration::from_secs(2),
+            },
+            EndpointClass::Transaction => RetryPolicy {
+                attempts: 3,
+                timeout: Duration::from_secs(30),
+                base_delay: Duration::from_secs(1),
+                max_delay: Duration::from_secs(10),
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum HttpError {
+    /// No response within the class timeout
+    Timeout { url: String },
+    /// The connection could not be made, so the request never reached the pool
+    Connect { url: String, message: String },
+    /// Any other transport failure, including reading the body
+    Request { url: String, message: String },
+    /// 429, after retrying
+    RateLimited {
+        url: String,
+        retry_after: Option<Duration>,
+    },
+    /// A non-success status, after retrying where the class allows it
+    Status {
+        url: String,
+        status: StatusCode,
+        body: String,
+    },
+    /// The host failed too often recently and is not being called
+    CircuitOpen { host: String, retry_in: Duration },
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpError::Timeout { url } => write!(f, "request to {} timed out", url),
+            HttpError::Connect { url, message } => {
+                write!(f, "failed to connect to {}: {}", url, message)
+            }
+            HttpError::Request { url, message } => {
+                write!(f, "request to {} failed: {}", url, message)
+            }
+            HttpError::RateLimited { url, .. } => write!(f, "rate limited by {}", url),
+            HttpError::Status { url, status, body } if body.is_empty() => {
+                write!(f, "{} returned {}", url, status)
+            }
+            HttpError::Status { url, status, body } => {
+                write!(f, "{} returned {}: {}", url, status, body)
+            }
+            HttpError::CircuitOpen { host, retry_in } => write!(
+                f,
+                "{} is failing, not retrying for another {}s",
+                host,
+                retry_in.as_secs().max(1)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+#[derive(Debug)]
+pub struct HttpResponse {
+    pub status: StatusCode,
+    pub body: String,
+    /// From the Retry-After header, in seconds
+    pub retry_after: Option<Duration>,
+}
+
+impl HttpResponse {
+    /// The body of a successful response.
+    pub fn text(self, url: &str) -> Result<String, HttpError> {
+        if self.status.is_success() {
+            Ok(self.body)
+        } else {
+            Err(HttpError::Status {
+                url: url.to_string(),
+                status: self.status,
+                body: self.body,
+            })
+        }
+    }
+}
+
+pub struct HttpRequest {
+    client: &'static HttpClient,
+    method: Method,
+    url: String,
+    class: EndpointClass,
+    headers: Vec<(&'static str, String)>,
+    body: Option<String>,
+}
+
+impl HttpRequest {
+    pub fn class(mut self, class: EndpointClass) -> Self {
+        self.class = class;
+        self
+    }
+
+    pub fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    pub async fn send(self) -> Result<HttpResponse, HttpError> {
+        self.client.send(self).await
+    }
+
+    fn host(&self) -> String {
+        url::Url::parse(&self.url)
+            .ok()
+            .and_then(|u| {
+                u.host_str().map(|h| {
+                    format!(
+                        "{}{}",
+                        h,
+                        u.port().map_or(String::new(), |p| format!(":{}", p))
+                    )
+                })
+            })
+            .unwrap_or_else(|| self.url.clone())
+    }
+}
+
+/// Exponential backoff with equal jitter: each delay is half the capped exponential
+/// step plus a random share of the other half, so retries spread out but never bunch
+/// up at zero.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    base: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Backoff {
+            base,
+            max,
+            attempt: 0,
+        }
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let step = self
+            .base
+            .saturating_mul(1u32 << self.attempt.min(16))
+            .min(self.max);
+        self.attempt += 1;
+        let half = step / 2;
+        half + half.mul_f64(rand::thread_rng().gen::<f64>())
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+const BREAKER_THRESHOLD: u32 = 5;
+const BREAKER_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Opens after `BREAKER_THRESHOLD` failed calls in a row. Once the cooldown passes one
+/// call is let through; its outcome closes or re-opens the breaker.
+#[derive(Debug, Default)]
+struct CircuitBreaker {
+    failures: u32,
+    open_until: Option<Instant>,
+    trial_in_flight: bool,
+}
+
+impl CircuitBreaker {
+    /// Err with the time left if calls should not be made now.
+    fn check(&mut self, now: Instant) -> Result<(), Duration> {
+        match self.open_until {
+            Some(until) if now < until => Err(until - now),
+            Some(_) if self.trial_in_flight => Err(Duration::ZERO),
+            Some(_) => {
+                self.trial_in_flight = true;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn record(&mut self, ok: bool, now: Instant) {
+        self.trial_in_flight = false;
+        if ok {
+            self.failures = 0;
+            self.open_until = None;
+        } else {
+            self.failures += 1;
+            if self.failures >= BREAKER_THRESHOLD {
+                self.open_until = Some(now + BREAKER_COOLDOWN);
+            }
+        }
+    }
+}
+
+pub struct HttpClient {
+    client: reqwest::Client,
+    breakers: Mutex<HashMap<String, CircuitBreaker>>,
+}
+
+static HTTP: Lazy<HttpClient> = Lazy::new(HttpClient::new);
+
+/// The shared client, so connections and breaker state are reused across commands.
+pub fn client() -> &'static HttpClient {
+    &HTTP
+}
+
+/// Whether a failed attempt may be retried under this class.
+fn retryable(class: EndpointClass, result: &Result<HttpResponse, HttpError>) -> bool {
+    match result {
+        Ok(res) if res.status == StatusCode::TOO_MANY_REQUESTS => true,
+        Ok(res) => res.status.is_server_error() && class != EndpointClass::Transaction,
+        Err(HttpError::Connect { .. }) => true,
+        Err(_) => class != EndpointClass::Transaction,
+    }
+}
+
+/// Counts against the host's breaker: transport failures and server errors.
+fn host_failed(result: &Result<HttpResponse, HttpError>) -> bool {
+    match result {
+        Ok(res) => res.status.is_server_error(),
+        Err(_) => true,
+    }
+}
+
+impl HttpClient {
+    fn new() -> Self {
+        HttpClient {
+            client: reqwest::Client::builder()
+                .tcp_nodelay(true)
+                .tcp_keepalive(Some(Duration::from_secs(60)))
+                .pool_idle_timeout(Some(Duration::from_secs(30)))
+                .pool_max_idle_per_host(5)
+                .build()
+                .expect("Failed to setup client connection"),
+            breakers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// A `Read` request unless `class` says otherwise.
+    pub fn get(&'static self, url: impl Into<String>) -> HttpRequest {
+        self.request(Method::GET, EndpointClass::Read, url.into())
+    }
+
+    /// A `Transaction` request unless `class` says otherwise.
+    pub fn post(&'static self, url: impl Into<String>) -> HttpRequest {
+        self.request(Method::POST, EndpointClass::Transaction, url.into())
+    }
+
+    fn request(&'static self, method: Method, class: EndpointClass, url: String) -> HttpRequest {
+        HttpRequest {
+            client: self,
+            method,
+            url,
+            class,
+            headers: vec![],
+            body: None,
+        }
+    }
+
+    /// Sends the request, retrying as its class allows. Responses are returned whatever
+    /// their status once retries are used up; `HttpResponse::text` checks it.
+    pub async fn send(&self, request: HttpRequest) -> Result<HttpResponse, HttpError> {
+        let policy = request.class.policy();
+        let host = request.host();
+        let mut backoff = Backoff::new(policy.base_delay, policy.max_delay);
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            if let Err(retry_in) = self.breaker(&host, |b| b.check(Instant::now())) {
+                return Err(HttpError::CircuitOpen { host, retry_in });
+            }
+
+            let result = self.send_once(&request, policy.timeout).await;
+            let failed = host_failed(&result);
+            self.breaker(&host, |b| b.record(!failed, Instant::now()));
+
+            if attempt >= policy.attempts || !retryable(request.class, &result) {
+                return match result {
+                    Ok(res) if res.status == StatusCode::TOO_MANY_REQUESTS => {
+                        Err(HttpError::RateLimited {
+                            url: request.url.clone(),
+                            retry_after: res.retry_after,
+                        })
+                    }
+                    result => result,
+                };
+            }
+
+            let mut delay = backoff.next_delay();
+            if let Ok(HttpResponse {
+                retry_after: Some(after),
+                ..
+            }) = &result
+            {
+                delay = delay.max(*after);
+            }
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    fn breaker<T>(&self, host: &str, f: impl FnOnce(&mut CircuitBreaker) -> T) -> T {
+        let mut breakers = self.breakers.lock().unwrap();
+        f(breakers.entry(host.to_string()).or_default())
+    }
+
+    /// One attempt with no retries and no breaker, for callers with their own loop.
+    pub async fn send_once(
+        &self,
+        request: &HttpRequest,
+        timeout: Duration,
+    ) -> Result<HttpResponse, HttpError> {
+        let mut builder = self
+            .client
+            .request(request.method.clone(), &request.url)
+            .timeout(timeout);
+        for (name, value) in &request.headers {
+            builder = builder.header(*name, value);
+        }
+        if let Some(body) = &request.body {
+            builder = builder.body(body.clone());
+        }
+
+        let url = &request.url;
+        let res = builder.send().await.map_err(|e| transport_error(url, e))?;
+        let status = res.status();
+        let retry_after = res
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|secs| secs.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let body = res.text().await.map_err(|e| transport_error(url, e))?;
+        Ok(HttpResponse {
+            status,
+            body,
+            retry_after,
+        })
+    }
+}
+
+fn transport_error(url: &str, e: reqwest::Error) -> HttpError {
+    let url = url.to_string();
+    if e.is_timeout() {
+        HttpError::Timeout { url }
+    } else if e.is_connect() {
+        HttpError::Connect {
+            url,
+            message: e.to_string(),
+        }
+    } else {
+        HttpError::Request {
+            url,
+            message: e.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_pool::MockPool;
+
+    #[test]
+    fn backoff_grows_with_jitter_up_to_the_cap() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(800));
+        let delays: Vec<Duration> = (0..6).map(|_| backoff.next_delay()).collect();
+
+        for (i, delay) in delays.iter().enumerate() {
+            let step = Duration::from_millis(100 * (1 << i)).min(Duration::from_millis(800));
+            assert!(
+                *delay >= step / 2 && *delay <= step,
+                "{:?} for step {:?}",
+                delay,
+                step
+            );
+        }
+        backoff.reset();
+        assert!(backoff.next_delay() <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn breaker_opens_then_lets_one_trial_through() {
+        let now = Instant::now();
+        let mut breaker = CircuitBreaker::default();
+        for _ in 0..BREAKER_THRESHOLD {
+            assert!(breaker.check(now).is_ok());
+            breaker.record(false, now);
+        }
+        assert!(breaker.check(now).is_err());
+
+        let later = now + BREAKER_COOLDOWN;
+        assert!(breaker.check(later).is_ok());
+        assert!(breaker.check(later).is_err());
+        breaker.record(true, later);
+        assert!(breaker.check(later).is_ok());
+    }
+
+    #[tokio::test]
+    async fn retries_reads_but_not_transactions_after_server_errors() {
+        let pool = MockPool::start().await;
+        pool.fail("/miner/rewards", 2);
+        pool.respond("/miner/rewards", 200, "0.5");
+        pool.fail("/v2/claim", 1);
+
+        let url = format!("http://{}/miner/rewards?pubkey=x", pool.url());
+        let rewards = client()
+            .get(url.as_str())
+            .send()
+            .await
+            .unwrap()
+            .text(&url)
+            .unwrap();
+        assert_eq!(rewards, "0.5");
+        assert_eq!(pool.state().requests_to("/miner/rewards").len(), 3);
+
+        let url = format!("http://{}/v2/claim", pool.url());
+        let claim = client().post(url.as_str()).send().await;
+        assert!(matches!(
+            claim.unwrap().text(&url),
+            Err(HttpError::Status {
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                ..
+            })
+        ));
+        assert_eq!(pool.state().requests_to("/v2/claim").len(), 1);
+    }
+}
@@ -0,0 +1,1050 @@
+use bip39::{Language, Mnemonic, MnemonicType, Seed};
+use clap::{Parser, Subcommand};
+use colored::*;
+use inquire::{Confirm, Select};
+use serde::Serialize;
+use solana_sdk::derivation_path::DerivationPath;
+use solana_sdk::signature::{read_keypair_file, write_keypair_file, Keypair};
+use solana_sdk::signer::{SeedDerivable, Signer};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+use crate::error::Error;
+use crate::keystore::{self, KeystoreError};
+use crate::output;
+use crate::textln;
+
+/// Where `generate-keypair` derives its wallet, and `keys recover` looks by default
+pub const DEFAULT_DERIVATION_PATH: &str = "m/44'/501'/0'/0'";
+
+#[derive(Debug, Parser)]
+pub struct KeysArgs {
+    #[command(subcommand)]
+    pub command: KeysCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum KeysCommand {
+    #[command(about = "List the keypairs in the keypair list with their labels.")]
+    List,
+    #[command(about = "Add a keypair file to the keypair list.")]
+    Add(AddArgs),
+    #[command(about = "Remove a keypair from the keypair list, leaving its file in place.")]
+    Remove(KeyArgs),
+    #[command(about = "Show the public key and details of a keypair.")]
+    Show(ShowArgs),
+    #[command(about = "Name a keypair so the label can be passed as --keypair.")]
+    Label(LabelArgs),
+    #[command(about = "Make a keypair the one commands use when --keypair isn't given.")]
+    Default(KeyArgs),
+    #[command(about = "Encrypt a plaintext keypair file with a passphrase.")]
+    Encrypt(KeyFileArgs),
+    #[command(about = "Decrypt an encrypted keypair file back to plaintext.")]
+    Decrypt(KeyFileArgs),
+    #[command(
+        about = "Recover a keypair from its recovery phrase and add it to the keypair list."
+    )]
+    Recover(RecoverArgs),
+    #[command(
+        about = "Derive numbered hot wallets from one stored seed and add them to the keypair list."
+    )]
+    Derive(DeriveArgs),
+}
+
+#[derive(Debug, Parser)]
+pub struct KeyArgs {
+    #[arg(value_name = "KEY", help = "Label or path of the keypair")]
+    pub key: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct ShowArgs {
+    #[arg(
+        value_name = "KEY",
+        help = "Label or path of the keypair [default: --keypair]"
+    )]
+    pub key: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct AddArgs {
+    #[arg(value_name = "PATH", help = "Keypair file, plaintext or encrypted")]
+    pub path: PathBuf,
+
+    #[arg(long, value_name = "LABEL", help = "Label to pass as --keypair")]
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct LabelArgs {
+    #[arg(value_name = "KEY", help = "Label or path of the keypair")]
+    pub key: String,
+
+    #[arg(
+        value_name = "LABEL",
+        help = "New label of letters, digits, - and _, omitted to remove the label"
+    )]
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct KeyFileArgs {
+    #[arg(value_name = "PATH", help = "Keypair file [default: --keypair]")]
+    pub path: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Write the result here instead of replacing the file"
+    )]
+    pub out: Option<PathBuf>,
+}
+
+#[derive(Debug, Parser)]
+pub struct RecoverArgs {
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Read the 12 or 24 word recovery phrase from this file instead of prompting"
+    )]
+    pub phrase_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Ask for the BIP39 passphrase the phrase was created with"
+    )]
+    pub bip39_passphrase: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with = "account",
+        help = "Derivation path, e.g. m/44'/501'/0'/0' [default: m/44'/501'/0'/0']"
+    )]
+    pub derivation_path: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "INDEX",
+        help = "Account index, short for the derivation path m/44'/501'/INDEX'/0'"
+    )]
+    pub account: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Where to write the keypair [default: ~/.config/solana/mining-hot-wallet.json]"
+    )]
+    pub out: Option<PathBuf>,
+
+    #[arg(long, help = "Encrypt the keypair file with a passphrase")]
+    pub encrypt: bool,
+
+    #[arg(long, short, action, help = "Auto approve confirmations.")]
+    pub y: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct DeriveArgs {
+    #[arg(long, value_name = "N", help = "Number of wallets to derive")]
+    pub count: u32,
+
+    #[arg(
+        long,
+        value_name = "INDEX",
+        default_value_t = 0,
+        help = "First account index, wallets use m/44'/501'/INDEX'/0'"
+    )]
+    pub start: u32,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        default_value = "hot-wallet",
+        help = "Wallet files are named NAME-INDEX.json and labeled NAME-INDEX"
+    )]
+    pub label: String,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Directory to write the wallets to [default: ~/.config/solana]"
+    )]
+    pub dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Encrypted seed to derive from [default: ~/.config/ore-hq-client/seed.json]"
+    )]
+    pub seed: Option<PathBuf>,
+
+    #[arg(
+        long,
+        conflicts_with = "phrase_file",
+        help = "Create the seed from a new recovery phrase"
+    )]
+    pub new_phrase: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Create the seed from the recovery phrase in this file"
+    )]
+    pub phrase_file: Option<PathBuf>,
+
+    #[arg(long, help = "Ask for a BIP39 passphrase when creating the seed")]
+    pub bip39_passphrase: bool,
+
+    #[arg(long, help = "Encrypt the wallet files with the seed's passphrase")]
+    pub encrypt: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct DeriveReport {
+    seed: String,
+    /// Only when the seed was created from a new phrase, which is its backup
+    phrase: Option<String>,
+    wallets: Vec<DerivedWallet>,
+}
+
+#[derive(Debug, Serialize)]
+struct DerivedWallet {
+    index: u32,
+    label: String,
+    derivation_path: String,
+    pubkey: String,
+    path: String,
+    /// `created`, or `existing` when the file already held this wallet
+    status: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct RecoverReport {
+    path: String,
+    pubkey: String,
+    derivation_path: String,
+    encrypted: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct KeyListReport {
+    keys: Vec<KeyEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct KeyEntry {
+    label: Option<String>,
+    path: String,
+    /// None when the file is missing or isn't a keypair
+    pubkey: Option<String>,
+    encrypted: bool,
+    /// Whether commands use this keypair when `--keypair` isn't given
+    default: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct KeyFileReport {
+    path: String,
+    pubkey: String,
+    encrypted: bool,
+}
+
+/// Runs a `keys` command on `keypair`, the `--keypair` path, unless it names its own file.
+pub fn keys(args: &KeysArgs, keypair: &str) -> Result<(), Error> {
+    match &args.command {
+        KeysCommand::List => list(keypair),
+        KeysCommand::Add(args) => add(args, keypair),
+        KeysCommand::Remove(args) => remove(args, keypair),
+        KeysCommand::Show(args) => show(args, keypair),
+        KeysCommand::Label(args) => label(args, keypair),
+        KeysCommand::Default(args) => set_default(args),
+        KeysCommand::Encrypt(file) => encrypt(file, keypair),
+        KeysCommand::Decrypt(file) => decrypt(file, keypair),
+        KeysCommand::Recover(args) => recover(args),
+        KeysCommand::Derive(args) => derive(args),
+    }
+}
+
+/// The path a `--keypair` value names: a label's keypair, else the value itself.
+pub fn resolve(keypair: &str) -> String {
+    let path = config::labels()
+        .remove(keypair)
+        .unwrap_or_else(|| keypair.to_string());
+    crate::expand_tilde(&path)
+}
+
+fn list(keypair: &str) -> Result<(), Error> {
+    let labels = config::labels();
+    let mut paths: Vec<String> = vec![];
+    let listed = config::keypairs()
+        .into_iter()
+        .chain(labels.values().cloned());
+    for path in listed.chain(Path::new(keypair).exists().then(|| keypair.to_string())) {
+        let path = canonical(&path);
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    let keys: Vec<KeyEntry> = paths
+        .iter()
+        .map(|path| entry(path, keypair, &labels))
+        .collect();
+
+    if keys.is_empty() {
+        textln!("  No keypairs yet, add one with keys add <PATH>.");
+    } else {
+        textln!("  {:<16} {:<44}  {}", "Label", "Public key", "File");
+    }
+    for key in &keys {
+        textln!(
+            "  {:<16} {:<44}  {}{}",
+            key.label.as_deref().unwrap_or("-"),
+            key.pubkey.as_deref().unwrap_or("(unreadable)"),
+            key.path,
+            details(key).dimmed()
+        );
+    }
+    output::emit("keys", &KeyListReport { keys });
+    Ok(())
+}
+
+fn add(args: &AddArgs, keypair: &str) -> Result<(), Error> {
+    let path = args.path.display().to_string();
+    if !keystore::is_keypair_file(&path) {
+        return Err(Error::Keypair { path });
+    }
+    add_to_keypair_list(&args.path)?;
+    if let Some(label) = &args.label {
+        set_label(&path, Some(label))?;
+    }
+
+    let key = entry(&canonical(&path), keypair, &config::labels());
+    textln!("  Added {} to the keypair list.", key.path);
+    output::emit("keys", &key);
+    Ok(())
+}
+
+fn remove(args: &KeyArgs, keypair: &str) -> Result<(), Error> {
+    let path = resolve(&args.key);
+    let key = entry(&canonical(&path), keypair, &config::labels());
+    if !forget(&path)? {
+        return Err(Error::Usage(format!(
+            "{} is not in the keypair list",
+            args.key
+        )));
+    }
+
+    textln!(
+        "  Removed {} from the keypair list, the file is still there.",
+        key.path
+    );
+    if key.label.is_some() && config::settings().keypair == key.label {
+        textln!("  The keypair setting still names its label, choose another with keys default.");
+    }
+    output::emit("keys", &key);
+    Ok(())
+}
+
+fn show(args: &ShowArgs, keypair: &str) -> Result<(), Error> {
+    let path = match &args.key {
+        Some(key) => resolve(key),
+        None => keypair.to_string(),
+    };
+    if !keystore::is_keypair_file(&path) {
+        return Err(Error::Keypair { path });
+    }
+
+    let key = entry(&canonical(&path), keypair, &config::labels());
+    textln!(
+        "  Public key: {}",
+        key.pubkey.as_deref().unwrap_or_default().bold()
+    );
+    textln!("  File:       {}", key.path);
+    textln!("  Label:      {}", key.label.as_deref().unwrap_or("-"));
+    textln!("  Encrypted:  {}", if key.encrypted { "yes" } else { "no" });
+    textln!("  Default:    {}", if key.default { "yes" } else { "no" });
+    output::emit("keys", &key);
+    Ok(())
+}
+
+fn label(args: &LabelArgs, keypair: &str) -> Result<(), Error> {
+    let path = resolve(&args.key);
+    if !keystore::is_keypair_file(&path) {
+        return Err(Error::Keypair { path });
+    }
+    add_to_keypair_list(Path::new(&path))?;
+    set_label(&path, args.label.as_deref())?;
+
+    let key = entry(&canonical(&path), keypair, &config::labels());
+    match &key.label {
+        Some(label) => textln!(
+            "  Labeled {} as {}, pass --keypair {} to use it.",
+            key.path,
+            label,
+            label
+        ),
+        None => textln!("  Removed the label of {}.", key.path),
+    }
+    output::emit("keys", &key);
+    Ok(())
+}
+
+fn set_default(args: &KeyArgs) -> Result<(), Error> {
+    let path = resolve(&args.key);
+    if !keystore::is_keypair_file(&path) {
+        return Err(Error::Keypair { path });
+    }
+    add_to_keypair_list(Path::new(&path))?;
+
+    let path = canonical(&path);
+    let labels = config::labels();
+    // A label keeps working if the file moves and its label is updated
+    let value = label_of(&path, &labels).unwrap_or_else(|| path.clone());
+    config::set_keypair(&value)?;
+
+    textln!("  Commands now use {} when --keypair isn't given.", value);
+    if std::env::var_os("ORE_HQ_KEYPAIR").is_some() {
+        textln!("  ORE_HQ_KEYPAIR is set and still takes precedence over the config file.");
+    }
+    output::emit("keys", &entry(&path, &path, &labels));
+    Ok(())
+}
+
+/// Names `path` `label`, or leaves it unnamed when `label` is None.
+fn set_label(path: &str, label: Option<&str>) -> Result<(), Error> {
+    let path = canonical(path);
+    let mut labels = config::labels();
+    if let Some(label) = label {
+        check_label(label)?;
+        if let Some(other) = labels.get(label).filter(|other| canonical(other) != path) {
+            return Err(Error::Usage(format!("{} already labels {}", label, other)));
+        }
+    }
+    labels.retain(|_, labeled| canonical(labeled) != path);
+    if let Some(label) = label {
+        labels.insert(label.to_string(), path);
+    }
+    config::save_labels(&labels)?;
+    Ok(())
+}
+
+/// Takes `path` off the keypair list and drops its labels. False if it wasn't on the list.
+pub fn forget(path: &str) -> Result<bool, Error> {
+    let path = canonical(path);
+    let mut keypairs = config::keypairs();
+    let mut labels = config::labels();
+    let (count, label_count) = (keypairs.len(), labels.len());
+    keypairs.retain(|listed| canonical(listed) != path);
+    labels.retain(|_, labeled| canonical(labeled) != path);
+
+    if keypairs.len() != count {
+        config::save_keypairs(&keypairs)?;
+    }
+    if labels.len() != label_count {
+        config::save_labels(&labels)?;
+    }
+    Ok(keypairs.len() != count || labels.len() != label_count)
+}
+
+/// Labels only use the characters of TOML bare keys, which can't be mistaken for a path.
+fn check_label(label: &str) -> Result<(), Error> {
+    let valid = !label.is_empty()
+        && label
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::Usage(format!(
+            "invalid label {:?}, use letters, digits, - and _",
+            label
+        )))
+    }
+}
+
+fn entry(path: &str, keypair: &str, labels: &BTreeMap<String, String>) -> KeyEntry {
+    KeyEntry {
+        label: label_of(path, labels),
+        path: path.to_string(),
+        pubkey: keystore::file_pubkey(Path::new(path))
+            .ok()
+            .map(|pubkey| pubkey.to_string()),
+        encrypted: keystore::is_encrypted(Path::new(path)),
+        default: canonical(keypair) == path,
+    }
+}
+
+fn label_of(path: &str, labels: &BTreeMap<String, String>) -> Option<String> {
+    labels
+        .iter()
+        .find(|(_, labeled)| canonical(labeled) == path)
+        .map(|(label, _)| label.clone())
+}
+
+fn details(key: &KeyEntry) -> String {
+    let mut details = vec![];
+    if key.default {
+        details.push("default");
+    }
+    if key.encrypted {
+        details.push("encrypted");
+    }
+    if details.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", details.join(", "))
+    }
+}
+
+/// Absolute path with links resolved, so one file is listed once however it was named.
+fn canonical(path: &str) -> String {
+    let path = crate::expand_tilde(path);
+    fs::canonicalize(&path)
+        .map(|path| path.display().to_string())
+        .unwrap_or(path)
+}
+
+fn encrypt(args: &KeyFileArgs, keypair: &str) -> Result<(), Error> {
+    let path = args.path.clone().unwrap_or_else(|| PathBuf::from(keypair));
+    if keystore::is_encrypted(&path) {
+        return Err(Error::Usage(format!(
+            "{} is already encrypted",
+            path.display()
+        )));
+    }
+    let key = read_keypair_file(&path).map_err(|_| Error::Keypair {
+        path: path.display().to_string(),
+    })?;
+
+    let passphrase = keystore::new_passphrase().map_err(|e| keystore_error(&path, e))?;
+    let out = args.out.clone().unwrap_or_else(|| path.clone());
+    keystore::write_encrypted(&out, &key, &passphrase).map_err(|e| keystore_error(&out, e))?;
+
+    textln!("  Encrypted {} to {}", key.pubkey(), out.display());
+    if out == path {
+        textln!("  Backups or copies of the plaintext file are not affected, remove them too.");
+    }
+    emit(&out, &key.pubkey().to_string(), true);
+    Ok(())
+}
+
+fn decrypt(args: &KeyFileArgs, keypair: &str) -> Result<(), Error> {
+    let path = args.path.clone().unwrap_or_else(|| PathBuf::from(keypair));
+    if !keystore::is_encrypted(&path) {
+        return Err(Error::Usage(format!(
+            "{} is not an encrypted keypair",
+            path.display()
+        )));
+    }
+    let key = keystore::read_keypair(&path.display().to_string())
+        .map_err(|e| keystore_error(&path, e))?;
+
+    let out = args.out.clone().unwrap_or_else(|| path.clone());
+    keystore::write_plaintext(&out, &key).map_err(|e| keystore_error(&out, e))?;
+
+    textln!("  Decrypted {} to {}", key.pubkey(), out.display());
+    emit(&out, &key.pubkey().to_string(), false);
+    Ok(())
+}
+
+fn recover(args: &RecoverArgs) -> Result<(), Error> {
+    let path = derivation_path(args)?;
+    let out = match &args.out {
+        Some(out) => out.clone(),
+        None => hot_wallet_path()?,
+    };
+    if out.exists() {
+        return Err(Error::Usage(format!(
+            "{} already exists, choose another file with --out",
+            out.display()
+        )));
+    }
+
+    let phrase = match &args.phrase_file {
+        Some(file) => fs::read_to_string(file)
+            .map_err(|e| Error::Usage(format!("Failed to read {}: {}", file.display(), e)))?,
+        None if output::json() => {
+            return Err(Error::Usage(
+                "--output json needs --phrase-file".to_string(),
+            ))
+        }
+        None => prompt_secret("  Recovery phrase (12 or 24 words): ")?,
+    };
+    let bip39_passphrase = if args.bip39_passphrase {
+        if output::json() {
+            return Err(Error::Usage(
+                "--bip39-passphrase prompts, so it can't be used with --output json".to_string(),
+            ));
+        }
+        prompt_secret("  BIP39 passphrase: ")?
+    } else {
+        String::new()
+    };
+
+    let key = derive_keypair(&phrase, &bip39_passphrase, &path)?;
+    textln!("  Derivation path: {:?}", path);
+    textln!(
+        "  Recovered public key: {}",
+        key.pubkey().to_string().bold()
+    );
+
+    if !args.y {
+        if output::json() {
+            return Err(Error::ConfirmationRequired);
+        }
+        let confirmed = Confirm::new("  Is this the wallet you expected?")
+            .with_default(false)
+            .prompt()?;
+        if !confirmed {
+            textln!("  Nothing written. Check the phrase, BIP39 passphrase and derivation path.");
+            return Ok(());
+        }
+    }
+
+    let encrypt = args.encrypt
+        || (!output::json()
+            && Confirm::new("  Protect the keypair with a passphrase?")
+                .with_default(true)
+                .prompt()?);
+    write_new_keypair(&out, &key, encrypt).map_err(|e| keystore_error(&out, e))?;
+    add_to_keypair_list(&out)?;
+
+    textln!(
+        "  Wrote {} and added it to the keypair list.",
+        out.display()
+    );
+    output::emit(
+        "keys",
+        &RecoverReport {
+            path: out.display().to_string(),
+            pubkey: key.pubkey().to_string(),
+            derivation_path: format!("{:?}", path),
+            encrypted: encrypt,
+        },
+    );
+    Ok(())
+}
+
+fn derive(args: &DeriveArgs) -> Result<(), Error> {
+    // The wallets are labeled NAME-INDEX, so it's checked before any is written
+    check_label(&args.label)?;
+    let seed_path = match &args.seed {
+        Some(path) => path.clone(),
+        None => config::default_path().with_file_name("seed.json"),
+    };
+    let dir = match &args.dir {
+        Some(dir) => dir.clone(),
+        None => hot_wallet_path()?
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+    };
+
+    let (seed, passphrase, phrase) = if seed_path.exists() {
+        if args.new_phrase || args.phrase_file.is_some() {
+            return Err(Error::Usage(format!(
+                "{} already holds a seed, remove it or pass another --seed to create a new one",
+                seed_path.display()
+            )));
+        }
+        let (seed, passphrase) =
+            keystore::read_seed(&seed_path).map_err(|e| keystore_error(&seed_path, e))?;
+        (seed, passphrase, None)
+    } else {
+        create_seed(args, &seed_path)?
+    };
+
+    let wallets = derive_wallets(args, &seed, &passphrase, &dir)?;
+
+    textln!(
+        "  {:<6} {:<16} {:<44}  {}",
+        "Index",
+        "Label",
+        "Public key",
+        "File"
+    );
+    for wallet in &wallets {
+        let existing = if wallet.status == "existing" {
+            " (existing)"
+        } else {
+            ""
+        };
+        textln!(
+            "  {:<6} {:<16} {:<44}  {}{}",
+            wallet.index,
+            wallet.label,
+            wallet.pubkey,
+            wallet.path,
+            existing.dimmed()
+        );
+    }
+    textln!("\n  Added to the keypair list, pass a label as --keypair to mine with that wallet. Every wallet can be recovered from the seed's phrase with keys recover --account INDEX.");
+
+    output::emit(
+        "keys",
+        &DeriveReport {
+            seed: seed_path.display().to_string(),
+            phrase,
+            wallets,
+        },
+    );
+    Ok(())
+}
+
+/// Writes the wallet files into `dir`, lists them and labels each NAME-INDEX.
+fn derive_wallets(
+    args: &DeriveArgs,
+    seed: &[u8],
+    passphrase: &str,
+    dir: &Path,
+) -> Result<Vec<DerivedWallet>, Error> {
+    create_dir(dir)?;
+    let mut wallets = vec![];
+    for index in args.start..args.start.saturating_add(args.count) {
+        let path = DerivationPath::new_bip44(Some(index), Some(0));
+        let key = derive_account(seed, &path)?;
+        let label = format!("{}-{}", args.label, index);
+        let file = dir.join(format!("{}.json", label));
+
+        let status = if file.exists() {
+            match keystore::file_pubkey(&file) {
+                Ok(pubkey) if pubkey == key.pubkey() => "existing",
+                _ => {
+                    return Err(Error::Usage(format!(
+                        "{} already exists and holds another keypair",
+                        file.display()
+                    )))
+                }
+            }
+        } else {
+            let written = if args.encrypt {
+                keystore::write_encrypted(&file, &key, passphrase)
+            } else {
+                keystore::write_plaintext(&file, &key)
+            };
+            written.map_err(|e| keystore_error(&file, e))?;
+            "created"
+        };
+        add_to_keypair_list(&file)?;
+        set_label(&file.display().to_string(), Some(&label))?;
+
+        wallets.push(DerivedWallet {
+            index,
+            label,
+            derivation_path: format!("{:?}", path),
+            pubkey: key.pubkey().to_string(),
+            path: file.display().to_string(),
+            status,
+        });
+    }
+    Ok(wallets)
+}
+
+/// Creates the encrypted seed from a new or given phrase. Returns the seed, its
+/// passphrase and, when new, the phrase.
+fn create_seed(
+    args: &DeriveArgs,
+    seed_path: &Path,
+) -> Result<(Vec<u8>, String, Option<String>), Error> {
+    let generate = if args.new_phrase {
+        true
+    } else if args.phrase_file.is_some() {
+        false
+    } else if output::json() {
+        return Err(Error::Usage(format!(
+            "no seed at {}, create one with --new-phrase or --phrase-file",
+            seed_path.display()
+        )));
+    } else {
+        let options = vec![
+            "  Generate a new recovery phrase",
+            "  Enter an existing recovery phrase",
+        ];
+        Select::new(
+            &format!("  No seed at {} yet.", seed_path.display()),
+            options.clone(),
+        )
+        .prompt()?
+            == options[0]
+    };
+
+    let phrase = if generate {
+        Mnemonic::new(MnemonicType::Words12, Language::English).into_phrase()
+    } else {
+        match &args.phrase_file {
+            Some(file) => fs::read_to_string(file)
+                .map_err(|e| Error::Usage(format!("Failed to read {}: {}", file.display(), e)))?,
+            None => prompt_secret("  Recovery phrase (12 or 24 words): ")?,
+        }
+    };
+    let bip39_passphrase = if args.bip39_passphrase {
+        if output::json() {
+            return Err(Error::Usage(
+                "--bip39-passphrase prompts, so it can't be used with --output json".to_string(),
+            ));
+        }
+        prompt_secret("  BIP39 passphrase: ")?
+    } else {
+        String::new()
+    };
+
+    let seed = seed_from_phrase(&phrase, &bip39_passphrase)?;
+    let first = derive_account(&seed, &DerivationPath::new_bip44(Some(0), Some(0)))?;
+    if generate {
+        textln!("  Recovery phrase for every wallet derived from this seed, write it down and keep it offline:");
+        textln!("    {}\n", phrase.bold());
+    }
+    textln!("  Choose the passphrase that encrypts the seed.");
+    let passphrase = keystore::new_passphrase().map_err(|e| keystore_error(seed_path, e))?;
+    if let Some(parent) = seed_path.parent() {
+        create_dir(parent)?;
+    }
+    keystore::write_seed(seed_path, &seed, &first.pubkey(), &passphrase)
+        .map_err(|e| keystore_error(seed_path, e))?;
+    textln!("  Stored the encrypted seed in {}\n", seed_path.display());
+
+    Ok((seed, passphrase, generate.then_some(phrase)))
+}
+
+fn derivation_path(args: &RecoverArgs) -> Result<DerivationPath, Error> {
+    if let Some(account) = args.account {
+        return Ok(DerivationPath::new_bip44(Some(account), Some(0)));
+    }
+    let path = args
+        .derivation_path
+        .as_deref()
+        .unwrap_or(DEFAULT_DERIVATION_PATH);
+    DerivationPath::from_absolute_path_str(path)
+        .map_err(|e| Error::Usage(format!("Invalid derivation path {}: {}", path, e)))
+}
+
+/// Derives the keypair of a BIP39 phrase the way Solana wallets do.
+pub fn derive_keypair(
+    phrase: &str,
+    passphrase: &str,
+    path: &DerivationPath,
+) -> Result<Keypair, Error> {
+    derive_account(&seed_from_phrase(phrase, passphrase)?, path)
+}
+
+fn seed_from_phrase(phrase: &str, passphrase: &str) -> Result<Vec<u8>, Error> {
+    let phrase = phrase
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mnemonic = Mnemonic::from_phrase(&phrase, Language::English)
+        .map_err(|e| Error::Usage(format!("Invalid recovery phrase: {}", e)))?;
+    Ok(Seed::new(&mnemonic, passphrase).as_bytes().to_vec())
+}
+
+fn derive_account(seed: &[u8], path: &DerivationPath) -> Result<Keypair, Error> {
+    Keypair::from_seed_and_derivation_path(seed, Some(path.clone()))
+        .map_err(|e| Error::Other(format!("Failed to derive the keypair: {}", e)))
+}
+
+/// Writes a new keypair file, encrypted with a new passphrase if asked to.
+pub fn write_new_keypair(path: &Path, key: &Keypair, encrypt: bool) -> Result<(), KeystoreError> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|error| KeystoreError::Io {
+            path: parent.to_path_buf(),
+            error,
+        })?;
+    }
+    if encrypt {
+        let passphrase = keystore::new_passphrase()?;
+        keystore::write_encrypted(path, key, &passphrase)
+    } else {
+        write_keypair_file(key, path)
+            .map(|_| ())
+            .map_err(|e| KeystoreError::Invalid(e.to_string()))
+    }
+}
+
+fn add_to_keypair_list(path: &Path) -> Result<(), Error> {
+    let path = canonical(&path.display().to_string());
+    let mut keypairs = config::keypairs();
+    if !keypairs.iter().any(|listed| canonical(listed) == path) {
+        keypairs.push(path);
+        config::save_keypairs(&keypairs)?;
+    }
+    Ok(())
+}
+
+fn create_dir(dir: &Path) -> Result<(), Error> {
+    fs::create_dir_all(dir).map_err(|error| {
+        keystore_error(
+            dir,
+            KeystoreError::Io {
+                path: dir.to_path_buf(),
+                error,
+            },
+        )
+    })
+}
+
+fn hot_wallet_path() -> Result<PathBuf, Error> {
+    dirs::home_dir()
+        .map(|home| home.join(".config/solana/mining-hot-wallet.json"))
+        .ok_or_else(|| Error::Other("Failed to get home directory from platform.".to_string()))
+}
+
+fn prompt_secret(message: &str) -> Result<String, Error> {
+    rpassword::prompt_password(message)
+        .map_err(|e| Error::Other(format!("Failed to read from the terminal: {}", e)))
+}
+
+fn emit(path: &Path, pubkey: &str, encrypted: bool) {
+    output::emit(
+        "keys",
+        &KeyFileReport {
+            path: path.display().to_string(),
+            pubkey: pubkey.to_string(),
+            encrypted,
+        },
+    );
+}
+
+fn keystore_error(path: &Path, source: KeystoreError) -> Error {
+    Error::Keystore {
+        path: path.display().to_string(),
+        source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PHRASE: &str =
+        "pill tomorrow foster begin walnut borrow virtual kick shift mutual shoe scatter";
+
+    fn args(derivation_path: Option<&str>, account: Option<u32>) -> RecoverArgs {
+        RecoverArgs {
+            phrase_file: None,
+            bip39_passphrase: false,
+            derivation_path: derivation_path.map(str::to_string),
+            account,
+            out: None,
+            encrypt: false,
+            y: true,
+        }
+    }
+
+    #[test]
+    fn recovers_the_wallet_generate_keypair_creates() {
+        let default = derivation_path(&args(None, None)).unwrap();
+        let key = derive_keypair(PHRASE, "", &default).unwrap();
+
+        // What generate-keypair does with a new phrase
+        let mnemonic = Mnemonic::from_phrase(PHRASE, Language::English).unwrap();
+        let seed = Seed::new(&mnemonic, "");
+        let path = DerivationPath::from_absolute_path_str(DEFAULT_DERIVATION_PATH).unwrap();
+        let generated =
+            Keypair::from_seed_and_derivation_path(seed.as_bytes(), Some(path)).unwrap();
+        assert_eq!(key.pubkey(), generated.pubkey());
+
+        // Extra whitespace and capitals don't change the wallet
+        let messy = format!("  {}\n", PHRASE.to_uppercase().replace(' ', "   "));
+        assert_eq!(
+            derive_keypair(&messy, "", &default).unwrap().pubkey(),
+            key.pubkey()
+        );
+    }
+
+    #[test]
+    fn passphrase_and_path_select_other_wallets() {
+        let default = derivation_path(&args(None, None)).unwrap();
+        let key = derive_keypair(PHRASE, "", &default).unwrap();
+        let with_passphrase = derive_keypair(PHRASE, "hunter2", &default).unwrap();
+        assert_ne!(key.pubkey(), with_passphrase.pubkey());
+
+        let account = derivation_path(&args(None, Some(3))).unwrap();
+        let explicit = derivation_path(&args(Some("m/44'/501'/3'/0'"), None)).unwrap();
+        assert_eq!(format!("{:?}", account), "m/44'/501'/3'/0'");
+        assert_eq!(
+            derive_keypair(PHRASE, "", &account).unwrap().pubkey(),
+            derive_keypair(PHRASE, "", &explicit).unwrap().pubkey()
+        );
+        assert_ne!(
+            derive_keypair(PHRASE, "", &account).unwrap().pubkey(),
+            key.pubkey()
+        );
+
+        assert!(derivation_path(&args(Some("44/501"), None)).is_err());
+        let typo = PHRASE.replace("pill", "pills");
+        assert!(matches!(
+            derive_keypair(&typo, "", &default),
+            Err(Error::Usage(_))
+        ));
+    }
+
+    #[test]
+    fn labels_cannot_look_like_paths() {
+        for label in ["rig1", "hot-wallet_2", "A"] {
+            assert!(check_label(label).is_ok(), "{}", label);
+        }
+        for label in ["", "id.json", "~/rig", "keys/rig", "rig 1", "rïg"] {
+            assert!(
+                matches!(check_label(label), Err(Error::Usage(_))),
+                "{}",
+                label
+            );
+        }
+    }
+
+    #[test]
+    fn derived_wallets_match_recovered_accounts() {
+        let seed = seed_from_phrase(PHRASE, "").unwrap();
+        for index in 0..3 {
+            let derived =
+                derive_account(&seed, &DerivationPath::new_bip44(Some(index), Some(0))).unwrap();
+            let recovered = derivation_path(&args(None, Some(index))).unwrap();
+            assert_eq!(
+                derived.pubkey(),
+                derive_keypair(PHRASE, "", &recovered).unwrap().pubkey()
+            );
+        }
+    }
+
+    #[test]
+    fn derived_wallets_are_labeled_for_keypair() {
+        let dir = std::env::temp_dir().join(format!("ore-hq-derive-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        config::use_path(&dir.join("config.toml"));
+        let args = DeriveArgs {
+            count: 2,
+            start: 4,
+            label: "rig".to_string(),
+            dir: None,
+            seed: None,
+            new_phrase: false,
+            phrase_file: None,
+            bip39_passphrase: false,
+            encrypt: false,
+        };
+        let seed = seed_from_phrase(PHRASE, "").unwrap();
+
+        // Deriving again finds the same files and keeps their labels
+        for status in ["created", "existing"] {
+            let wallets = derive_wallets(&args, &seed, "", &dir).unwrap();
+            let labels: Vec<_> = wallets.iter().map(|w| w.label.as_str()).collect();
+            assert_eq!(labels, ["rig-4", "rig-5"]);
+            for wallet in &wallets {
+                assert_eq!(wallet.status, status);
+                let keypair = resolve(&wallet.label);
+                assert_eq!(
+                    keystore::file_pubkey(Path::new(&keypair))
+                        .unwrap()
+                        .to_string(),
+                    wallet.pubkey
+                );
+            }
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,502 @@
+//! Keypair files encrypted with a passphrase.
+//!
+//! An encrypted keypair is a JSON document instead of the usual byte array: a versioned
+//! header naming the key derivation and cipher with their parameters, and the sealed
+//! keypair bytes. The seed `keys derive` keeps uses the same format. The key is derived
+//! from the passphrase with PBKDF2-HMAC-SHA256 and the keypair sealed with
+//! AES-256-GCM-SIV, with the header as associated data so it can't be edited without
+//! failing to decrypt. Plaintext keypair files keep working everywhere.
+//!
+//! The passphrase comes from `ORE_HQ_PASSPHRASE`, else the `--passphrase-file`, else a
+//! prompt. `--output json` never prompts.
+
+use aes_gcm_siv::aead::{Aead, NewAead, Payload};
+use aes_gcm_siv::{Aes256GcmSiv, Key, Nonce};
+use base64::prelude::*;
+use hmac::Hmac;
+use once_cell::sync::OnceCell;
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair};
+use solana_sdk::signer::Signer;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::output;
+
+pub const PASSPHRASE_ENV: &str = "ORE_HQ_PASSPHRASE";
+const KEYPAIR_FORMAT: &str = "ore-hq-client-keystore";
+/// A BIP39 seed that `keys derive` derives wallets from
+const SEED_FORMAT: &str = "ore-hq-client-seed";
+const VERSION: u32 = 1;
+const KDF: &str = "pbkdf2-hmac-sha256";
+const CIPHER: &str = "aes-256-gcm-siv";
+/// PBKDF2 rounds for new keystores, stored in the header so it can be raised later
+const ROUNDS: u32 = 600_000;
+/// Wrong passphrases allowed at the prompt before giving up
+const PROMPT_ATTEMPTS: usize = 3;
+
+static PASSPHRASE_FILE: OnceCell<Option<PathBuf>> = OnceCell::new();
+
+/// Sets where unattended rigs keep the passphrase, from `--passphrase-file` or the config.
+pub fn init(passphrase_file: Option<PathBuf>) {
+    let _ = PASSPHRASE_FILE.set(passphrase_file);
+}
+
+#[derive(Debug)]
+pub enum KeystoreError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// Neither a keypair byte array nor a keystore
+    Invalid(String),
+    UnsupportedVersion(u32),
+    WrongPassphrase,
+    /// No passphrase in the environment or a file, and prompting isn't allowed
+    PassphraseRequired,
+    Prompt(std::io::Error),
+}
+
+impl fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeystoreError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            KeystoreError::Invalid(reason) => write!(f, "not a keypair file: {}", reason),
+            KeystoreError::UnsupportedVersion(version) => write!(
+                f,
+                "keystore version {} is newer than this client supports, please update",
+                version
+            ),
+            KeystoreError::WrongPassphrase => write!(f, "wrong passphrase"),
+            KeystoreError::PassphraseRequired => write!(
+                f,
+                "the keypair is encrypted, set {} or --passphrase-file",
+                PASSPHRASE_ENV
+            ),
+            KeystoreError::Prompt(e) => write!(f, "failed to read the passphrase: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for KeystoreError {}
+
+/// Everything but the ciphertext, authenticated along with it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+    pubkey: String,
+    kdf: String,
+    rounds: u32,
+    salt: String,
+    cipher: String,
+    nonce: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Keystore {
+    #[serde(flatten)]
+    header: Header,
+    ciphertext: String,
+}
+
+/// Whether the file at `path` is an encrypted keystore rather than a plaintext keypair.
+pub fn is_encrypted(path: &Path) -> bool {
+    fs::read_to_string(path)
+        .map(|contents| parse(&contents, KEYPAIR_FORMAT).is_ok())
+        .unwrap_or(false)
+}
+
+/// Checks that `path` holds a keypair, without asking for the passphrase of an encrypted one.
+pub fn is_keypair_file(path: &str) -> bool {
+    is_encrypted(Path::new(path)) || read_keypair_file(path).is_ok()
+}
+
+/// The public key of a plaintext or encrypted keypair file, without decrypting it.
+pub fn file_pubkey(path: &Path) -> Result<Pubkey, KeystoreError> {
+    let contents = read(path)?;
+    if !contents.trim_start().starts_with('{') {
+        return read_keypair_file(path)
+            .map(|keypair| keypair.pubkey())
+            .map_err(|e| KeystoreError::Invalid(e.to_string()));
+    }
+    let keystore = parse(&contents, KEYPAIR_FORMAT)?;
+    keystore
+        .header
+        .pubkey
+        .parse()
+        .map_err(|_| KeystoreError::Invalid("pubkey is not base58".to_string()))
+}
+
+/// Loads a plaintext or encrypted keypair, getting the passphrase for an encrypted one.
+pub fn read_keypair(path: &str) -> Result<Keypair, KeystoreError> {
+    let contents = read(Path::new(path))?;
+    if !contents.trim_start().starts_with('{') {
+        return read_keypair_file(path).map_err(|e| KeystoreError::Invalid(e.to_string()));
+    }
+    let keystore = parse(&contents, KEYPAIR_FORMAT)?;
+    unlock(&keystore, open_keypair).map(|(keypair, _)| keypair)
+}
+
+/// Loads an encrypted BIP39 seed, returning it with the passphrase that opened it.
+pub fn read_seed(path: &Path) -> Result<(Vec<u8>, String), KeystoreError> {
+    let keystore = parse(&read(path)?, SEED_FORMAT)?;
+    unlock(&keystore, open)
+}
+
+/// Opens `keystore` with the unattended passphrase, or prompts for it a few times.
+fn unlock<T>(
+    keystore: &Keystore,
+    open: impl Fn(&Keystore, &str) -> Result<T, KeystoreError>,
+) -> Result<(T, String), KeystoreError> {
+    if let Some(passphrase) = unattended_passphrase()? {
+        return open(keystore, &passphrase).map(|opened| (opened, passphrase));
+    }
+    if output::json() {
+        return Err(KeystoreError::PassphraseRequired);
+    }
+    let mut attempts = 0;
+    loop {
+        let passphrase = prompt(&format!("  Passphrase for {}: ", keystore.header.pubkey))?;
+        match open(keystore, &passphrase) {
+            Ok(opened) => return Ok((opened, passphrase)),
+            Err(KeystoreError::WrongPassphrase) if attempts + 1 < PROMPT_ATTEMPTS => {
+                attempts += 1;
+                println!("  Wrong passphrase, please try again.");
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// The passphrase to encrypt a keypair with: the unattended one if set, else asked for twice.
+pub fn new_passphrase() -> Result<String, KeystoreError> {
+    if let Some(passphrase) = unattended_passphrase()? {
+        return Ok(passphrase);
+    }
+    if output::json() {
+        return Err(KeystoreError::PassphraseRequired);
+    }
+    loop {
+        let passphrase = prompt("  New passphrase: ")?;
+        if passphrase.is_empty() {
+            println!("  The passphrase can't be empty.");
+            continue;
+        }
+        if prompt("  Repeat the passphrase: ")? == passphrase {
+            return Ok(passphrase);
+        }
+        println!("  The passphrases don't match, please try again.");
+    }
+}
+
+/// Writes `keypair` encrypted, replacing `path` only once the new file is complete.
+pub fn write_encrypted(
+    path: &Path,
+    keypair: &Keypair,
+    passphrase: &str,
+) -> Result<(), KeystoreError> {
+    let contents = seal(
+        KEYPAIR_FORMAT,
+        &keypair.pubkey(),
+        &keypair.to_bytes(),
+        passphrase,
+        ROUNDS,
+    );
+    write_private(path, contents.as_bytes())
+}
+
+/// Writes a BIP39 seed encrypted. `pubkey` is its first account, to tell seeds apart.
+pub fn write_seed(
+    path: &Path,
+    seed: &[u8],
+    pubkey: &Pubkey,
+    passphrase: &str,
+) -> Result<(), KeystoreError> {
+    write_private(
+        path,
+        seal(SEED_FORMAT, pubkey, seed, passphrase, ROUNDS).as_bytes(),
+    )
+}
+
+/// Writes `keypair` as a plaintext byte array, like `solana-keygen`.
+pub fn write_plaintext(path: &Path, keypair: &Keypair) -> Result<(), KeystoreError> {
+    let bytes = serde_json::to_string(&keypair.to_bytes().to_vec())
+        .map_err(|e| KeystoreError::Invalid(e.to_string()))?;
+    write_private(path, bytes.as_bytes())
+}
+
+fn seal(format: &str, pubkey: &Pubkey, secret: &[u8], passphrase: &str, rounds: u32) -> String {
+    let mut salt = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+    let header = Header {
+        format: format.to_string(),
+        version: VERSION,
+        pubkey: pubkey.to_string(),
+        kdf: KDF.to_string(),
+        rounds,
+        salt: BASE64_STANDARD.encode(salt),
+        cipher: CIPHER.to_string(),
+        nonce: BASE64_STANDARD.encode(nonce),
+    };
+    let aad = serde_json::to_vec(&header).unwrap();
+    let cipher = Aes256GcmSiv::new(Key::from_slice(&derive_key(passphrase, &salt, rounds)));
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: secret,
+                aad: &aad,
+            },
+        )
+        .expect("encrypting with a fresh nonce can't fail");
+    let keystore = Keystore {
+        header,
+        ciphertext: BASE64_STANDARD.encode(ciphertext),
+    };
+    serde_json::to_string_pretty(&keystore).unwrap()
+}
+
+fn open(keystore: &Keystore, passphrase: &str) -> Result<Vec<u8>, KeystoreError> {
+    let header = &keystore.header;
+    let decode = |field: &str, value: &str| {
+        BASE64_STANDARD
+            .decode(value)
+            .map_err(|_| KeystoreError::Invalid(format!("{} is not base64", field)))
+    };
+    let salt = decode("salt", &header.salt)?;
+    let nonce = decode("nonce", &header.nonce)?;
+    let ciphertext = decode("ciphertext", &keystore.ciphertext)?;
+    if nonce.len() != 12 {
+        return Err(KeystoreError::Invalid("nonce must be 12 bytes".to_string()));
+    }
+
+    let aad = serde_json::to_vec(header).unwrap();
+    let cipher = Aes256GcmSiv::new(Key::from_slice(&derive_key(
+        passphrase,
+        &salt,
+        header.rounds,
+    )));
+    // A wrong passphrase and a tampered file look the same to the cipher
+    cipher
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &ciphertext,
+                aad: &aad,
+            },
+        )
+        .map_err(|_| KeystoreError::WrongPassphrase)
+}
+
+fn open_keypair(keystore: &Keystore, passphrase: &str) -> Result<Keypair, KeystoreError> {
+    let bytes = open(keystore, passphrase)?;
+    let keypair = Keypair::from_bytes(&bytes).map_err(|e| KeystoreError::Invalid(e.to_string()))?;
+    if keypair.pubkey().to_string() != keystore.header.pubkey {
+        return Err(KeystoreError::Invalid(
+            "pubkey does not match the keypair".to_string(),
+        ));
+    }
+    Ok(keypair)
+}
+
+fn parse(contents: &str, format: &str) -> Result<Keystore, KeystoreError> {
+    let keystore: Keystore =
+        serde_json::from_str(contents).map_err(|e| KeystoreError::Invalid(e.to_string()))?;
+    let header = &keystore.header;
+    if header.format != format {
+        return Err(KeystoreError::Invalid(format!(
+            "unknown format {}",
+            header.format
+        )));
+    }
+    if header.version != VERSION {
+        return Err(KeystoreError::UnsupportedVersion(header.version));
+    }
+    if header.kdf != KDF || header.cipher != CIPHER {
+        return Err(KeystoreError::Invalid(format!(
+            "unsupported kdf {} or cipher {}",
+            header.kdf, header.cipher
+        )));
+    }
+    if header.rounds == 0 {
+        return Err(KeystoreError::Invalid("rounds must be above 0".to_string()));
+    }
+    Ok(keystore)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], rounds: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), salt, rounds, &mut key);
+    key
+}
+
+/// The passphrase from the environment or the passphrase file, for rigs nobody types into.
+fn unattended_passphrase() -> Result<Option<String>, KeystoreError> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(Some(passphrase));
+    }
+    let Some(path) = PASSPHRASE_FILE.get().cloned().flatten() else {
+        return Ok(None);
+    };
+    let contents = read(&path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Ok(meta) = fs::metadata(&path) {
+            if meta.permissions().mode() & 0o077 != 0 {
+                log::warn!(
+                    "{} can be read by other users, restrict it with chmod 600",
+                    path.display()
+                );
+            }
+        }
+    }
+    Ok(Some(contents.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+fn prompt(message: &str) -> Result<String, KeystoreError> {
+    rpassword::prompt_password(message).map_err(KeystoreError::Prompt)
+}
+
+fn read(path: &Path) -> Result<String, KeystoreError> {
+    fs::read_to_string(path).map_err(|error| KeystoreError::Io {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+/// Writes a file only its owner can read, through a temporary file renamed over `path`.
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), KeystoreError> {
+    let io_err = |error| KeystoreError::Io {
+        path: path.to_path_buf(),
+        error,
+    };
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp = PathBuf::from(tmp_name);
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&tmp).map_err(io_err)?;
+    file.write_all(contents).map_err(io_err)?;
+    file.sync_all().map_err(io_err)?;
+    fs::rename(&tmp, path).map_err(io_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Few rounds, debug builds hash slowly
+    const TEST_ROUNDS: u32 = 10;
+
+    fn seal_keypair(keypair: &Keypair, passphrase: &str) -> String {
+        let secret = keypair.to_bytes();
+        seal(
+            KEYPAIR_FORMAT,
+            &keypair.pubkey(),
+            &secret,
+            passphrase,
+            TEST_ROUNDS,
+        )
+    }
+
+    fn decrypt(contents: &str, passphrase: &str) -> Result<Keypair, KeystoreError> {
+        open_keypair(&parse(contents, KEYPAIR_FORMAT)?, passphrase)
+    }
+
+    #[test]
+    fn keystores_open_only_with_their_passphrase() {
+        let keypair = Keypair::new();
+        let contents = seal_keypair(&keypair, "correct horse");
+        assert!(!contents.contains(&keypair.to_base58_string()));
+
+        let opened = decrypt(&contents, "correct horse").unwrap();
+        assert_eq!(opened.to_bytes(), keypair.to_bytes());
+        assert!(matches!(
+            decrypt(&contents, "battery staple"),
+            Err(KeystoreError::WrongPassphrase)
+        ));
+
+        // The header is authenticated along with the ciphertext
+        let mut keystore: Keystore = serde_json::from_str(&contents).unwrap();
+        keystore.header.pubkey = Keypair::new().pubkey().to_string();
+        let tampered = serde_json::to_string(&keystore).unwrap();
+        assert!(matches!(
+            decrypt(&tampered, "correct horse"),
+            Err(KeystoreError::WrongPassphrase)
+        ));
+
+        let mut keystore: Keystore = serde_json::from_str(&contents).unwrap();
+        keystore.header.version = 2;
+        let newer = serde_json::to_string(&keystore).unwrap();
+        assert!(matches!(
+            decrypt(&newer, "correct horse"),
+            Err(KeystoreError::UnsupportedVersion(2))
+        ));
+    }
+
+    #[test]
+    fn plaintext_and_encrypted_files_both_load() {
+        let dir = std::env::temp_dir();
+        let id = std::process::id();
+        let plain = dir.join(format!("ore-hq-client-plain-{}.json", id));
+        let sealed = dir.join(format!("ore-hq-client-sealed-{}.json", id));
+        let keypair = Keypair::new();
+
+        write_plaintext(&plain, &keypair).unwrap();
+        write_private(&sealed, seal_keypair(&keypair, "pw").as_bytes()).unwrap();
+
+        assert!(!is_encrypted(&plain));
+        assert!(is_encrypted(&sealed));
+        assert!(is_keypair_file(plain.to_str().unwrap()));
+        assert!(is_keypair_file(sealed.to_str().unwrap()));
+        let loaded = read_keypair(plain.to_str().unwrap()).unwrap();
+        assert_eq!(loaded.pubkey(), keypair.pubkey());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&sealed).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let _ = fs::remove_file(plain);
+        let _ = fs::remove_file(sealed);
+    }
+
+    #[test]
+    fn seeds_and_keypairs_are_not_interchangeable() {
+        let keypair = Keypair::new();
+        let seed = [7u8; 64];
+        let contents = seal(SEED_FORMAT, &keypair.pubkey(), &seed, "pw", TEST_ROUNDS);
+
+        let keystore = parse(&contents, SEED_FORMAT).unwrap();
+        assert_eq!(open(&keystore, "pw").unwrap(), seed);
+        assert!(matches!(
+            decrypt(&contents, "pw"),
+            Err(KeystoreError::Invalid(_))
+        ));
+        assert!(parse(&seal_keypair(&keypair, "pw"), SEED_FORMAT).is_err());
+
+        let path =
+            std::env::temp_dir().join(format!("ore-hq-client-seed-{}.json", std::process::id()));
+        write_private(&path, contents.as_bytes()).unwrap();
+        assert!(file_pubkey(&path).is_err());
+        write_private(&path, seal_keypair(&keypair, "pw").as_bytes()).unwrap();
+        assert_eq!(file_pubkey(&path).unwrap(), keypair.pubkey());
+        let _ = fs::remove_file(path);
+    }
+}
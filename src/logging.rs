@@ -0,0 +1,366 @@
+//! Log output for the mining commands.
+//!
+//! On a terminal records print as plain messages, the way the miners always have. When
+//! stdout is not a terminal, or with `--log-format json`, every line carries a timestamp,
+//! level and target so headless rigs produce parseable logs. `--log-file` additionally
+//! appends to a file that is rotated by size.
+
+use chrono::Utc;
+use clap::{arg, Args, ValueEnum};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct LogArgs {
+    #[arg(
+        long,
+        value_name = "FILTER",
+        default_value = "info",
+        help = "Log level, optionally per module, e.g. info,session=debug"
+    )]
+    pub log_level: String,
+
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "text",
+        help = "Log line format"
+    )]
+    pub log_format: LogFormat,
+
+    #[arg(long, value_name = "PATH", help = "Also append logs to this file")]
+    pub log_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "MB",
+        default_value = "10",
+        help = "Rotate the log file once it reaches this size"
+    )]
+    pub log_max_size: u64,
+
+    #[arg(
+        long,
+        value_name = "COUNT",
+        default_value = "5",
+        help = "Number of rotated log files to keep"
+    )]
+    pub log_max_files: usize,
+}
+
+/// Log levels per target, most specific prefix wins.
+#[derive(Debug, Clone, PartialEq)]
+struct Filter {
+    default: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Filter {
+    fn parse(spec: &str) -> Result<Self, String> {
+        let mut filter = Filter {
+            default: LevelFilter::Info,
+            targets: vec![],
+        };
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let parse_level = |level: &str| {
+                level
+                    .parse::<LevelFilter>()
+                    .map_err(|_| format!("Invalid log level: {}", level))
+            };
+            match part.split_once('=') {
+                Some((target, level)) => filter
+                    .targets
+                    .push((target.trim().to_string(), parse_level(level.trim())?)),
+                None => filter.default = parse_level(part)?,
+            }
+        }
+        // Longest prefix first so it wins
+        filter.targets.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+        Ok(filter)
+    }
+
+    /// Targets may be given with or without the crate prefix, e.g. `session`.
+    fn level_for(&self, target: &str) -> LevelFilter {
+        let short = target.strip_prefix("ore_hq_client::").unwrap_or(target);
+        self.targets
+            .iter()
+            .find(|(prefix, _)| {
+                [target, short]
+                    .iter()
+                    .any(|t| *t == prefix || t.starts_with(&format!("{}::", prefix)))
+            })
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.targets
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    ts: String,
+    level: &'a str,
+    target: &'a str,
+    message: &'a str,
+}
+
+/// Appends to a file, rolling it to `.1`, `.2`, ... once it grows past `max_bytes`.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_bytes,
+            max_files,
+        })
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.max_files == 0 {
+            self.file = File::create(&self.path)?;
+        } else {
+            for n in (1..self.max_files).rev() {
+                let from = self.rotated(n);
+                if from.exists() {
+                    fs::rename(from, self.rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+            self.file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
+
+struct Logger {
+    filter: Filter,
+    format: LogFormat,
+    /// Print bare messages, colors and all, as the interactive miners always have
+    plain_console: bool,
+    file: Option<Mutex<RotatingFile>>,
+}
+
+impl Logger {
+    fn format_line(&self, record: &Record, message: &str) -> String {
+        let ts = Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        let target = record
+            .target()
+            .strip_prefix("ore_hq_client::")
+            .unwrap_or(record.target());
+        match self.format {
+            LogFormat::Json => {
+                let line = JsonLine {
+                    ts,
+                    level: record.level().as_str(),
+                    target,
+                    message,
+                };
+                format!("{}\n", serde_json::to_string(&line).unwrap())
+            }
+            LogFormat::Text => {
+                format!("{} {:<5} {}: {}\n", ts, record.level(), target, message)
+            }
+        }
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filter.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = record.args().to_string();
+
+        if self.plain_console {
+            if record.level() <= Level::Warn {
+                eprintln!("{}", message);
+            } else {
+                println!("{}", message);
+            }
+        } else {
+            let line = self.format_line(record, &strip_ansi(&message));
+            let _ = io::stdout().lock().write_all(line.as_bytes());
+        }
+
+        if let Some(file) = &self.file {
+            let line = self.format_line(record, &strip_ansi(&message));
+            let _ = file.lock().unwrap().write_line(&line);
+        }
+    }
+
+    fn flush(&self) {
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Removes color codes and cursor movement, which only make sense on a terminal.
+fn strip_ansi(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1B' {
+            if chars.next() == Some('[') {
+                // Skip parameters up to the final byte of the sequence
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+static INTERACTIVE: OnceCell<bool> = OnceCell::new();
+
+/// True when console output is read by a person, so cursor tricks are fine.
+pub fn is_interactive() -> bool {
+    *INTERACTIVE.get_or_init(|| io::stdout().is_terminal())
+}
+
+pub fn init(args: &LogArgs) -> Result<(), String> {
+    let filter = Filter::parse(&args.log_level)?;
+    let file = match &args.log_file {
+        Some(path) => Some(Mutex::new(
+            RotatingFile::open(path, args.log_max_size * 1024 * 1024, args.log_max_files)
+                .map_err(|e| format!("Failed to open log file {}: {}", path.display(), e))?,
+        )),
+        None => None,
+    };
+    let plain_console = args.log_format == LogFormat::Text && io::stdout().is_terminal();
+    if !plain_console {
+        colored::control::set_override(false);
+    }
+    let _ = INTERACTIVE.set(plain_console);
+
+    log::set_max_level(filter.max_level());
+    log::set_boxed_logger(Box::new(Logger {
+        filter,
+        format: args.log_format,
+        plain_console,
+        file,
+    }))
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_by_most_specific_target() {
+        let filter = Filter::parse("warn,session=debug,ore_hq_client::session::x=error").unwrap();
+
+        assert_eq!(filter.level_for("ore_hq_client::mine"), LevelFilter::Warn);
+        assert_eq!(
+            filter.level_for("ore_hq_client::session"),
+            LevelFilter::Debug
+        );
+        assert_eq!(
+            filter.level_for("ore_hq_client::sessions"),
+            LevelFilter::Warn
+        );
+        assert_eq!(
+            filter.level_for("ore_hq_client::session::x"),
+            LevelFilter::Error
+        );
+        assert_eq!(filter.max_level(), LevelFilter::Debug);
+        assert!(Filter::parse("loud").is_err());
+    }
+
+    #[test]
+    fn json_lines_have_no_color_codes() {
+        let logger = Logger {
+            filter: Filter::parse("info").unwrap(),
+            format: LogFormat::Json,
+            plain_console: false,
+            file: None,
+        };
+        let message = strip_ansi("\x1B[1A \x1B[2mMined\x1B[0m \x1B[96mdiff 21\x1B[0m");
+        let line = logger.format_line(
+            &Record::builder()
+                .level(Level::Info)
+                .target("ore_hq_client::minepmc")
+                .args(format_args!("unused"))
+                .build(),
+            &message,
+        );
+
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["level"], "INFO");
+        assert_eq!(value["target"], "minepmc");
+        assert_eq!(value["message"], " Mined diff 21");
+    }
+
+    #[test]
+    fn rotates_log_file_by_size() {
+        let dir = std::env::temp_dir().join(format!("ore-hq-client-logs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("miner.log");
+        let mut file = RotatingFile::open(&path, 10, 2).unwrap();
+
+        for line in ["aaaaaaaa\n", "bbbbbbbb\n", "cccccccc\n", "dddddddd\n"] {
+            file.write_line(line).unwrap();
+        }
+
+        let read = |p: PathBuf| fs::read_to_string(p).unwrap();
+        assert_eq!(read(path.clone()), "dddddddd\n");
+        assert_eq!(read(file.rotated(1)), "cccccccc\n");
+        assert_eq!(read(file.rotated(2)), "bbbbbbbb\n");
+        assert!(!file.rotated(3).exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -4,44 +4,65 @@ static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 use balance::balance;
 use claim::ClaimArgs;
 use claim_stake_rewards::ClaimStakeRewardsArgs;
-use clap::{Parser, Subcommand};
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
 use core_affinity::get_core_ids;
 use dirs::home_dir;
+use error::Error;
 use generate_key::generate_key;
 use inquire::{Confirm, Select, Text};
 use mine::{mine, MineArgs};
-use minepmc::minepmc;
+use minepmc::{minepmc, PmcArgs};
 use protomine::{protomine, MineArgs as ProtoMineArgs};
 use semver::Version;
-use serde_json;
+use signer::Wallet;
 use signup::{signup, SignupArgs};
 use solana_sdk::pubkey::Pubkey;
-use solana_sdk::signature::read_keypair_file;
+use solana_sdk::signer::Signer;
 use std::fs;
-use std::io::{self, BufRead, Write};
 use std::path::PathBuf;
 use std::process::Command;
 use std::str::FromStr;
 
+mod api;
+mod backend;
 mod balance;
+mod benchmark;
 mod claim;
+mod config;
 mod database;
+mod db;
 mod delegate_boost;
 mod earnings;
+mod error;
+mod failover;
 mod generate_key;
+mod http;
+mod keys;
+mod keystore;
+mod logging;
+mod metrics;
+mod migrations;
 mod mine;
 mod minepmc;
+#[cfg(test)]
+mod mock_pool;
+mod output;
+mod passes;
+mod protocol;
 mod protomine;
+mod session;
+mod shutdown;
+mod signer;
 mod signup;
 mod stake_balance;
 mod stats;
+#[cfg(test)]
+mod temp_db;
 mod undelegate_boost;
 mod undelegate_stake;
 mod migrate_boosts_to_v2;
 mod claim_stake_rewards;
 
-const CONFIG_FILE: &str = "keypair_list";
-
 const TOKEN_OPTIONS: &[(&str, &str)] = &[
     ("ORE Token", "oreoU2P8bN6jkk3jbaiVxYnG1dCXcYxwhwyK9jSybcp"),
     ("ORE-SOL LP", "DrSS5RM7zUd9qjUEdDaf31vnDUSbCrMto6mjqTrHFifN"),
@@ -68,6 +89,20 @@ struct Args {
     )]
     keypair: String,
 
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "File holding the passphrase of an encrypted keypair [env: ORE_HQ_PASSPHRASE_FILE]"
+    )]
+    passphrase_file: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        help = "Sign with `signer serve` at unix:PATH or tcp:HOST:PORT instead of a keypair file [env: ORE_HQ_SIGNER]"
+    )]
+    signer: Option<String>,
+
     #[arg(
         long,
         short,
@@ -79,6 +114,40 @@ struct Args {
     #[arg(long, short, action, help = "Use vim mode for menu navigation.")]
     vim: bool,
 
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Config file to use [default: ~/.config/ore-hq-client/config.toml, env: ORE_HQ_CONFIG]"
+    )]
+    config: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Config file profile to use [env: ORE_HQ_PROFILE]"
+    )]
+    profile: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Named pool from the config file to connect to [env: ORE_HQ_POOL]"
+    )]
+    pool: Option<String>,
+
+    #[command(flatten)]
+    log: logging::LogArgs,
+
+    #[arg(
+        long,
+        value_enum,
+        global = true,
+        value_name = "FORMAT",
+        default_value_t = output::OutputFormat::Text,
+        help = "Output of the account commands; json prints one document and never prompts"
+    )]
+    output: output::OutputFormat,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -88,7 +157,12 @@ enum Commands {
     #[command(about = "Connect to pool and start mining. (Default)")]
     Mine(MineArgs),
 	#[command(about = "Connect to pool and start mining using pmc's hashing.")]
-    MinePmc(MineArgs),
+    MinePmc {
+        #[command(flatten)]
+        mine: MineArgs,
+        #[command(flatten)]
+        pmc: PmcArgs,
+    },
 	#[command(about = "Connect to pool and start mining using Prototype Software.")]
     Protomine(ProtoMineArgs),
     #[command(about = "Transfer SOL to the pool authority to sign up.")]
@@ -104,7 +178,9 @@ enum Commands {
     #[command(about = "Generate a new solana keypair for mining.")]
     GenerateKeypair,
     #[command(about = "Displays locally tracked earnings.")]
-    Earnings,
+    Earnings(earnings::EarningsArgs),
+    #[command(about = "Displays locally recorded mining passes and what they earned.")]
+    Passes(passes::PassesArgs),
     #[command(about = "Delegate boost for the pool miner.")]
     DelegateBoost(delegate_boost::BoostArgs),
     #[command(about = "Undelegate boost for the pool miner.")]
@@ -113,95 +189,161 @@ enum Commands {
     MigrateBoosts,
     #[command(about = "Claim stake rewards.")]
     ClaimStakeRewards(claim_stake_rewards::ClaimStakeRewardsArgs),
+    #[command(about = "Benchmark the hashing backends offline, without connecting to a pool.")]
+    Benchmark(benchmark::BenchmarkArgs),
+    #[command(about = "Inspect or migrate the local app database.")]
+    Db(db::DbArgs),
+    #[command(about = "Show or edit the config file.")]
+    Config(config::ConfigArgs),
+    #[command(about = "List, label, encrypt, recover and derive keypairs.")]
+    Keys(keys::KeysArgs),
+    #[command(about = "Serve signatures to rigs mining with --signer, keeping the keypair off them.")]
+    Signer(signer::SignerArgs),
 }
 
 #[tokio::main]
 async fn main() {
-    let args = Args::parse();
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    output::init(args.output);
+
+    let command = matches.subcommand_name().unwrap_or("menu").to_string();
+    if let Err(e) = run(args, &matches).await {
+        output::report_error(&command, &e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+async fn run(mut args: Args, matches: &ArgMatches) -> Result<(), Error> {
+    logging::init(&args.log).map_err(Error::Usage)?;
+
+    // Config commands must work even when the file doesn't parse
+    if let Some(Commands::Config(config_args)) = &args.command {
+        return config::config(
+            config_args,
+            args.config.as_deref(),
+            args.profile.as_deref(),
+            args.pool.as_deref(),
+        );
+    }
+
+    let settings = config::load(
+        args.config.as_deref(),
+        args.profile.as_deref(),
+        args.pool.as_deref(),
+    )?;
+    config::apply(&mut args, matches, &settings);
+    let pick_pool = !config::pool_chosen(matches, &settings);
+    keystore::init(args.passphrase_file.as_deref().map(|p| PathBuf::from(expand_tilde(p))));
 
     // Ensure the URL is set to the default if not provided
-    let mut args = args;
     if args.url.is_empty() {
         args.url = "ec1ipse.me".to_string();
     }
 
-    // Does the config file exist? If not, create one
-    let config_path = PathBuf::from(CONFIG_FILE);
-    if !config_path.exists() {
-        fs::File::create(&config_path).expect("Failed to create configuration file.");
+    // The benchmark runs offline and needs no keypair
+    if let Some(Commands::Benchmark(bench_args)) = &args.command {
+        benchmark::benchmark(bench_args);
+        return Ok(());
+    }
+
+    if let Some(Commands::Db(db_args)) = &args.command {
+        return db::db(db_args);
+    }
+
+    if let Err(e) = config::import_legacy_keypairs() {
+        textln!("  Failed to import the keypair_list file: {}", e);
     }
 
     // Check if keypair path is provided or fallback to the default
-    let keypair_path = expand_tilde(&args.keypair);
+    let keypair_path = keys::resolve(&args.keypair);
+
+    if let Some(Commands::Keys(keys_args)) = &args.command {
+        return keys::keys(keys_args, &keypair_path);
+    }
+
+    if let Some(Commands::Signer(signer_args)) = &args.command {
+        return signer::signer(signer_args, &keypair_path).await;
+    }
+
     let keypair_exists = PathBuf::from(&keypair_path).exists();
 
-    if keypair_exists {
-        // Keypair path is provided and exists, proceed directly
-        let key = read_keypair_file(&keypair_path).expect(&format!(
-            "Failed to load keypair from file: {}",
-            keypair_path
-        ));
-
-        if let Some(command) = args.command {
-            // A valid command is provided, execute it directly
-            if let Err(_) = run_command(Some(command), key, args.url, args.use_http, None).await {
-                println!("  An error occurred while executing the command.");
-            }
-        } else {
-            // No command provided, run the menu
-            if let Err(_) = run_menu(args.vim).await {
-                println!("  An error occurred, exiting program.");
-            }
+    // The menu is interactive, so scripts have to name a command and a keypair
+    if output::json() {
+        if args.command.is_none() {
+            return Err(Error::Usage("--output json needs a command".to_string()));
         }
-    } else {
-        // The keypair does not exist, proceed directly to the menu without showing an error
-        if let Err(_) = run_menu(args.vim).await {
-            println!("  An error occurred, exiting program.");
+        if !keypair_exists && args.signer.is_none() {
+            return Err(Error::Keypair { path: keypair_path });
         }
     }
+
+    // A remote signer stands in for the keypair file
+    if let Some(address) = &args.signer {
+        if args.command.is_none() {
+            return run_menu(args, pick_pool).await.map_err(menu_error);
+        }
+        let key = Wallet::remote(address)?;
+        return run_command(args.command, key, args.url, args.use_http, None).await;
+    }
+
+    if !keypair_exists {
+        // The keypair does not exist, proceed directly to the menu without showing an error
+        return run_menu(args, pick_pool).await.map_err(menu_error);
+    }
+
+    // Keypair path is provided and exists, proceed directly
+    let key = Wallet::load(&keypair_path).map_err(|source| Error::Keystore {
+        path: keypair_path.clone(),
+        source,
+    })?;
+
+    match args.command {
+        // A valid command is provided, execute it directly
+        Some(command) => run_command(Some(command), key, args.url, args.use_http, None).await,
+        // No command provided, run the menu
+        None => run_menu(args, pick_pool).await.map_err(menu_error),
+    }
 }
 
-fn get_keypair_path(default_keypair: &str) -> Option<String> {
-    let config_path = PathBuf::from(CONFIG_FILE);
+/// The menu's prompts fail with their own errors; commands it runs keep theirs.
+fn menu_error(e: Box<dyn std::error::Error>) -> Error {
+    match e.downcast::<Error>() {
+        Ok(e) => *e,
+        Err(e) => Error::Other(e.to_string()),
+    }
+}
+
+fn get_keypair_path(_default_keypair: &str) -> Option<String> {
     let mut keypair_paths = Vec::new();
     let mut seen_paths = std::collections::HashSet::new();
 
-    if config_path.exists() {
-        let file = match fs::File::open(&config_path) {
-            Ok(f) => f,
-            Err(_) => {
-                println!("  Failed to open configuration file.");
-                return ask_for_custom_keypair();
-            }
-        };
-        let reader = io::BufReader::new(file);
+    {
         let mut valid_keypair_paths = Vec::new();
 
-        for line in reader.lines() {
-            if let Ok(path) = line {
-                let expanded_path = expand_tilde(&path);
-                let path_buf = PathBuf::from(&expanded_path);
-
-                if path_buf.exists() && !seen_paths.contains(&expanded_path) {
-                    seen_paths.insert(expanded_path.clone());
-
-                    if path_buf.is_dir() {
-                        // Add all keypair files in the directory
-                        for entry in fs::read_dir(path_buf).expect("Failed to read directory") {
-                            let entry = entry.expect("Failed to get directory entry");
-                            let file_path = entry.path();
-                            if file_path.is_file() {
-                                let file_path_str = file_path.to_string_lossy().to_string();
-                                if !seen_paths.contains(&file_path_str) {
-                                    valid_keypair_paths
-                                        .push(replace_home_with_tilde(&file_path_str));
-                                    seen_paths.insert(file_path_str);
-                                }
+        for path in config::keypairs() {
+            let expanded_path = expand_tilde(&path);
+            let path_buf = PathBuf::from(&expanded_path);
+
+            if path_buf.exists() && !seen_paths.contains(&expanded_path) {
+                seen_paths.insert(expanded_path.clone());
+
+                if path_buf.is_dir() {
+                    // Add all keypair files in the directory
+                    for entry in fs::read_dir(path_buf).expect("Failed to read directory") {
+                        let entry = entry.expect("Failed to get directory entry");
+                        let file_path = entry.path();
+                        if file_path.is_file() {
+                            let file_path_str = file_path.to_string_lossy().to_string();
+                            if !seen_paths.contains(&file_path_str) {
+                                valid_keypair_paths
+                                    .push(replace_home_with_tilde(&file_path_str));
+                                seen_paths.insert(file_path_str);
                             }
                         }
-                    } else {
-                        valid_keypair_paths.push(replace_home_with_tilde(&expanded_path));
                     }
+                } else {
+                    valid_keypair_paths.push(replace_home_with_tilde(&expanded_path));
                 }
             }
         }
@@ -209,12 +351,10 @@ fn get_keypair_path(default_keypair: &str) -> Option<String> {
         if !valid_keypair_paths.is_empty() {
             keypair_paths = valid_keypair_paths.clone();
             // Update config file with only valid paths
-            let mut file = fs::File::create(&config_path)
-                .expect("Failed to open configuration file for writing.");
-            for path in valid_keypair_paths {
-                writeln!(file, "{}", expand_tilde(&path))
-                    .expect("Failed to write keypair path to configuration file.");
-            }
+            let valid_keypair_paths: Vec<String> =
+                valid_keypair_paths.iter().map(|p| expand_tilde(p)).collect();
+            config::save_keypairs(&valid_keypair_paths)
+                .expect("Failed to write keypair paths to configuration file.");
         }
     }
 
@@ -235,7 +375,10 @@ fn get_keypair_path(default_keypair: &str) -> Option<String> {
             .unwrap_or(false);
 
         if generate_new_keypair {
-            generate_key();
+            if let Err(e) = generate_key() {
+                output::report_error("generate-keypair", &e);
+                std::process::exit(e.exit_code());
+            }
             println!("  Keypair generated successfully. Exiting program.");
         } else {
             println!("  Exiting program without generating a keypair.");
@@ -282,12 +425,12 @@ fn get_keypair_path(default_keypair: &str) -> Option<String> {
             "  Custom" => return ask_for_custom_keypair(),
             "  Remove" => {
                 remove_keypair();
-                return get_keypair_path(default_keypair);
+                return get_keypair_path(_default_keypair);
             }
             _ => {
                 let selected_path = expand_tilde(&selection);
                 if PathBuf::from(&selected_path).exists() {
-                    if load_keypair(&selected_path).is_some() {
+                    if keystore::is_keypair_file(&selected_path) {
                         return Some(selected_path);
                     } else {
                         println!("  Please select a valid keypair.");
@@ -305,23 +448,15 @@ fn get_keypair_path(default_keypair: &str) -> Option<String> {
 }
 
 fn remove_keypair() {
-    let config_path = PathBuf::from(CONFIG_FILE);
     let mut keypair_paths = Vec::new();
 
     let solana_default_keypair = expand_tilde("~/.config/solana/id.json");
     let hot_wallet_keypair = expand_tilde("~/.config/solana/mining-hot-wallet.json");
 
-    if config_path.exists() {
-        let file = fs::File::open(&config_path).expect("  Failed to open configuration file.");
-        let reader = io::BufReader::new(file);
-
-        for line in reader.lines() {
-            if let Ok(path) = line {
-                let expanded_path = expand_tilde(&path);
-                let display_path = replace_home_with_tilde(&expanded_path);
-                keypair_paths.push(display_path);
-            }
-        }
+    for path in config::keypairs() {
+        let expanded_path = expand_tilde(&path);
+        let display_path = replace_home_with_tilde(&expanded_path);
+        keypair_paths.push(display_path);
     }
 
     if keypair_paths.is_empty() {
@@ -352,19 +487,9 @@ fn remove_keypair() {
         return;
     }
 
-    let remove_index = keypair_paths.iter().position(|p| p == &selection).unwrap();
-
-    keypair_paths.remove(remove_index);
-
-    // Write the updated list back to the config file
-    let mut file =
-        fs::File::create(&config_path).expect("Failed to open configuration file for writing.");
-
-    for path in keypair_paths {
-        let expanded_path = expand_tilde(&path);
-        writeln!(file, "{}", expanded_path)
-            .expect("Failed to write keypair path to configuration file.");
-    }
+    // Drops its labels along with it
+    keys::forget(&expand_tilde(&selection))
+        .expect("Failed to write keypair paths to configuration file.");
 
     println!("  Keypair path '{}' has been removed.", selection);
 }
@@ -380,9 +505,9 @@ fn replace_home_with_tilde(path: &str) -> String {
 }
 
 fn expand_tilde(path: &str) -> String {
-    if path.starts_with("~") {
+    if path.starts_with('~') {
         if let Some(home_dir) = home_dir() {
-            return path.replacen("~", &home_dir.to_string_lossy(), 1);
+            return path.replacen('~', &home_dir.to_string_lossy(), 1);
         }
     }
     path.to_string()
@@ -424,18 +549,8 @@ fn ask_for_custom_keypair() -> Option<String> {
             }
 
             // Read and normalize existing paths from the configuration file
-            let config_path = PathBuf::from(CONFIG_FILE);
-            let mut existing_paths = Vec::new();
-            if config_path.exists() {
-                let file =
-                    fs::File::open(&config_path).expect("Failed to open configuration file.");
-                let reader = io::BufReader::new(file);
-                for line in reader.lines() {
-                    if let Ok(path) = line {
-                        existing_paths.push(expand_tilde(&path));
-                    }
-                }
-            }
+            let mut existing_paths: Vec<String> =
+                config::keypairs().iter().map(|p| expand_tilde(p)).collect();
 
             // Normalize paths for comparison
             let original_count = keypair_files.len();
@@ -455,14 +570,9 @@ fn ask_for_custom_keypair() -> Option<String> {
             }
 
             // Update the configuration file with unique paths
-            let mut file = fs::OpenOptions::new()
-                .append(true)
-                .open(&config_path)
-                .expect("Failed to open configuration file for appending.");
-            for path in &keypair_files {
-                writeln!(file, "{}", expand_tilde(path))
-                    .expect("Failed to write keypair path to configuration file.");
-            }
+            existing_paths.extend(keypair_files.iter().map(|p| expand_tilde(p)));
+            config::save_keypairs(&existing_paths)
+                .expect("Failed to write keypair paths to configuration file.");
 
             // Prompt the user to select a keypair from the directory
             let selection = match Select::new(
@@ -484,7 +594,7 @@ fn ask_for_custom_keypair() -> Option<String> {
 
             let selected_path = expand_tilde(&selection);
             if PathBuf::from(&selected_path).exists() {
-                if load_keypair(&selected_path).is_some() {
+                if keystore::is_keypair_file(&selected_path) {
                     return Some(selected_path);
                 } else {
                     println!("  Please select a valid keypair.");
@@ -508,13 +618,9 @@ fn ask_for_custom_keypair() -> Option<String> {
             .unwrap_or(true);
 
             if add_to_list {
-                let config_path = PathBuf::from(CONFIG_FILE);
-                let mut file = fs::OpenOptions::new()
-                    .append(true)
-                    .open(&config_path)
-                    .expect("Failed to open configuration file for appending.");
-
-                writeln!(file, "{}", expanded_path)
+                let mut keypair_paths = config::keypairs();
+                keypair_paths.push(expanded_path.clone());
+                config::save_keypairs(&keypair_paths)
                     .expect("Failed to write keypair path to configuration file.");
             }
 
@@ -524,40 +630,32 @@ fn ask_for_custom_keypair() -> Option<String> {
 }
 
 fn check_keypair_exists(path: &str) -> bool {
-    let config_path = PathBuf::from(CONFIG_FILE);
-
-    if config_path.exists() {
-        let file = fs::File::open(&config_path).expect("Failed to open configuration file.");
-        let reader = io::BufReader::new(file);
-
-        for line in reader.lines() {
-            if let Ok(existing_path) = line {
-                if expand_tilde(&existing_path) == path {
-                    return true;
-                }
-            }
-        }
-    }
-
-    false
+    config::keypairs()
+        .iter()
+        .any(|existing_path| expand_tilde(existing_path) == path)
 }
 
-fn load_keypair(keypair_path: &str) -> Option<solana_sdk::signature::Keypair> {
+fn load_keypair(keypair_path: &str) -> Option<Wallet> {
     use std::panic::{self, AssertUnwindSafe};
 
-    let result = panic::catch_unwind(AssertUnwindSafe(|| read_keypair_file(keypair_path)));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| Wallet::load(keypair_path)));
 
     match result {
         Ok(Ok(keypair)) => Some(keypair),
-        Ok(Err(_)) | Err(_) => {
+        Ok(Err(e)) => {
+            println!("  Failed to load keypair from file {}: {}", keypair_path, e);
+            None
+        }
+        Err(_) => {
             println!("  Failed to load keypair from file: {}", keypair_path);
             None
         }
     }
 }
 
-async fn run_menu(vim_mode: bool) -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+/// Runs the interactive menu, offering the configured pools when `pick_pool`.
+async fn run_menu(args: Args, pick_pool: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let vim_mode = args.vim;
     let version = env!("CARGO_PKG_VERSION");
 
     let update_available = match is_update_available().await {
@@ -620,7 +718,7 @@ async fn run_menu(vim_mode: bool) -> Result<(), Box<dyn std::error::Error>> {
     }
 
     if let Some("  Generate Keypair") = selection {
-        generate_key();
+        generate_key()?;
         return Ok(());
     }
 
@@ -633,92 +731,149 @@ async fn run_menu(vim_mode: bool) -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
-    let base_url = if args.url == "ec1ipse.me" {
-        let url_input = Text::new("  Please enter the server URL:")
-            .with_default("ec1ipse.me")
-            .prompt()
-            .unwrap_or_else(|_| "ec1ipse.me".to_string());
-        url_input
+    let mut pool_keypair = config::settings()
+        .pool
+        .and_then(|name| config::pools().remove(&name))
+        .and_then(|pool| pool.keypair);
+    let mut unsecure_conn = args.use_http;
+
+    let base_url = if pick_pool {
+        match select_pool()? {
+            Some((url, use_http, keypair)) => {
+                unsecure_conn = use_http.unwrap_or(unsecure_conn);
+                pool_keypair = keypair;
+                url
+            }
+            None => Text::new("  Please enter the server URL:")
+                .with_default("ec1ipse.me")
+                .prompt()
+                .unwrap_or_else(|_| "ec1ipse.me".to_string()),
+        }
     } else {
         args.url.clone()
     };
 
-    let unsecure_conn = args.use_http;
-
-    let keypair_path = loop {
-        match get_keypair_path(&args.keypair) {
-            Some(path) => break path,
-            None => println!("  Failed to get keypair path. Please try again."),
+    let key = match &args.signer {
+        Some(address) => Wallet::remote(address)?,
+        None => {
+            let keypair_path = match pool_keypair.map(|k| keys::resolve(&k)) {
+                Some(path) if PathBuf::from(&path).exists() => path,
+                _ => loop {
+                    match get_keypair_path(&args.keypair) {
+                        Some(path) => break path,
+                        None => println!("  Failed to get keypair path. Please try again."),
+                    }
+                },
+            };
+            load_keypair(&keypair_path).ok_or_else(|| Error::Keypair {
+                path: keypair_path.clone(),
+            })?
         }
     };
 
-    let key = load_keypair(&keypair_path).unwrap_or_else(|| {
-        println!("  Returning to keypair selection.");
-        std::process::exit(1);
-    });
-
     run_command(
         args.command,
         key,
         base_url,
         unsecure_conn,
-        selection.as_deref(),
+        selection,
     )
     .await?;
     Ok(())
 }
 
+/// Offers the pools named in the config file. Returns the chosen pool's url, whether it
+/// uses http and its keypair, or None to enter a url instead.
+#[allow(clippy::type_complexity)]
+fn select_pool() -> Result<Option<(String, Option<bool>, Option<String>)>, Box<dyn std::error::Error>> {
+    let pools = config::pools();
+    if pools.is_empty() {
+        return Ok(None);
+    }
+
+    let mut options: Vec<String> = pools
+        .iter()
+        .map(|(name, pool)| format!("  {} ({})", name, pool.host))
+        .collect();
+    options.push("  Other".to_string());
+    let selection = Select::new("  Select a pool:", options.clone()).prompt()?;
+    let Some(index) = options.iter().position(|o| *o == selection) else {
+        return Ok(None);
+    };
+    let Some(name) = pools.keys().nth(index) else {
+        return Ok(None);
+    };
+
+    let settings = config::select_pool(name)?;
+    Ok(Some((
+        settings.url.unwrap_or_else(|| "ec1ipse.me".to_string()),
+        settings.use_http,
+        pools[name].keypair.clone(),
+    )))
+}
+
 async fn run_command(
     command: Option<Commands>,
-    key: solana_sdk::signature::Keypair,
+    key: Wallet,
     base_url: String,
     unsecure_conn: bool,
     selection: Option<&str>,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), Error> {
     match command {
         Some(Commands::Mine(args)) => {
-            mine(args, key, base_url, unsecure_conn).await;
+            mine(args, key, base_url, unsecure_conn).await?;
         }
-        Some(Commands::MinePmc(args)) => {
+        Some(Commands::MinePmc { mine: args, pmc }) => {
 			// let key = read_keypair_file(args.keypair.clone()).expect(&format!("Failed to load keypair from file: {}", args.keypair));
-            minepmc(args, key, base_url, unsecure_conn).await;
+            minepmc(args, pmc, key, base_url, unsecure_conn).await?;
         }
         Some(Commands::Protomine(args)) => {
-            protomine(args, key, base_url, unsecure_conn).await;
+            protomine(args, key, base_url, unsecure_conn).await?;
         }
         Some(Commands::Signup(args)) => {
-            signup(args, base_url, key, unsecure_conn).await;
+            signup(args, base_url, key, unsecure_conn).await?;
         }
         Some(Commands::Claim(args)) => {
-            claim::claim(args, key, base_url, unsecure_conn).await;
+            claim::claim(args, key, base_url, unsecure_conn).await?;
         }
         Some(Commands::Balance) => {
-            balance(&key, base_url, unsecure_conn).await;
+            balance(&key, base_url, unsecure_conn).await?;
         }
         Some(Commands::Unstake(args)) => {
-            undelegate_stake::undelegate_stake(args, &key, base_url, unsecure_conn).await;
+            undelegate_stake::undelegate_stake(args, &key, base_url, unsecure_conn).await?;
         }
         Some(Commands::StakeBalance) => {
-            stake_balance::stake_balance(&key, base_url, unsecure_conn).await;
+            stake_balance::stake_balance(&key, base_url, unsecure_conn).await?;
         }
         Some(Commands::GenerateKeypair) => {
-            generate_key::generate_key();
+            generate_key::generate_key()?;
         }
-        Some(Commands::Earnings) => {
-            earnings::earnings();
+        Some(Commands::Earnings(args)) => {
+            earnings::earnings(&args)?;
+        }
+        Some(Commands::Passes(args)) => {
+            passes::passes(&args)?;
         }
         Some(Commands::DelegateBoost(args)) => {
-            delegate_boost::delegate_boost(args, key, base_url, unsecure_conn).await;
+            delegate_boost::delegate_boost(args, key, base_url, unsecure_conn).await?;
         }
         Some(Commands::UndelegateBoost(args)) => {
-            undelegate_boost::undelegate_boost(args, key, base_url, unsecure_conn).await;
+            undelegate_boost::undelegate_boost(args, key, base_url, unsecure_conn).await?;
         }
         Some(Commands::MigrateBoosts) => {
-            migrate_boosts_to_v2::migrate_boosts_to_v2(key, base_url, unsecure_conn).await;
+            migrate_boosts_to_v2::migrate_boosts_to_v2(key, base_url, unsecure_conn).await?;
         }
         Some(Commands::ClaimStakeRewards(args)) => {
-            claim_stake_rewards::claim_stake_rewards(args, key, base_url, unsecure_conn).await;
+            claim_stake_rewards::claim_stake_rewards(args, key, base_url, unsecure_conn).await?;
         }
+        // These need no wallet and are dispatched by `run` before one is loaded
+        Some(
+            Commands::Benchmark(_)
+            | Commands::Db(_)
+            | Commands::Config(_)
+            | Commands::Keys(_)
+            | Commands::Signer(_),
+        ) => unreachable!("dispatched by run"),
         None => {
             if let Some(choice) = selection {
                 match choice {
@@ -727,12 +882,15 @@ async fn run_command(
                         let max_threads = core_ids.len();
 
                         // Ask for the number of threads
+                        let default_threads = config::settings()
+                            .threads
+                            .map_or(max_threads, |t| t as usize);
                         let threads: u32 = loop {
                             let input = Text::new(&format!(
                                 "  Enter the number of threads (default: {}):",
-                                max_threads
+                                default_threads
                             ))
-                            .with_default(&max_threads.to_string())
+                            .with_default(&default_threads.to_string())
                             .prompt()?;
 
                             match input.trim().parse::<u32>() {
@@ -751,7 +909,9 @@ async fn run_command(
                         let buffer: u32 = loop {
                             let buffer_input =
                                 Text::new("  Enter the buffer time in seconds (optional):")
-                                    .with_default("0")
+                                    .with_default(
+                                        &config::settings().buffer.unwrap_or(0).to_string(),
+                                    )
                                     .prompt()?;
 
                             match buffer_input.trim().parse::<u32>() {
@@ -764,20 +924,32 @@ async fn run_command(
                             }
                         };
 
-                        let args = MineArgs { threads, buffer };
-                        mine(args, key, base_url, unsecure_conn).await;
+                        let settings = config::settings();
+                        let args = MineArgs {
+                            threads,
+                            buffer,
+                            backend: settings.backend,
+                            verify_solutions: false,
+                            metrics_addr: None,
+                            failover: config::failover_urls(&settings),
+                            finish_pass: false,
+                        };
+                        mine(args, key, base_url, unsecure_conn).await?;
                     }
                     "  MinePmc (Community Implementation)" => {
                         let core_ids = get_core_ids().unwrap();
                         let max_threads = core_ids.len();
 
                         // Ask for the number of threads
+                        let default_threads = config::settings()
+                            .threads
+                            .map_or(max_threads, |t| t as usize);
                         let threads: u32 = loop {
                             let input = Text::new(&format!(
                                 "  Enter the number of threads (default: {}):",
-                                max_threads
+                                default_threads
                             ))
-                            .with_default(&max_threads.to_string())
+                            .with_default(&default_threads.to_string())
                             .prompt()?;
 
                             match input.trim().parse::<u32>() {
@@ -796,7 +968,9 @@ async fn run_command(
                         let buffer: u32 = loop {
                             let buffer_input =
                                 Text::new("  Enter the buffer time in seconds (optional):")
-                                    .with_default("0")
+                                    .with_default(
+                                        &config::settings().buffer.unwrap_or(0).to_string(),
+                                    )
                                     .prompt()?;
 
                             match buffer_input.trim().parse::<u32>() {
@@ -809,8 +983,23 @@ async fn run_command(
                             }
                         };
 
-                        let args = MineArgs { threads, buffer };
-                        minepmc(args, key, base_url, unsecure_conn).await;
+                        let settings = config::settings();
+                        let args = MineArgs {
+                            threads,
+                            buffer,
+                            backend: settings.backend,
+                            verify_solutions: false,
+                            metrics_addr: None,
+                            failover: config::failover_urls(&settings),
+                            finish_pass: false,
+                        };
+                        let pmc = PmcArgs {
+                            overmine_by_ms: settings.overmine_by_ms,
+                            nonce_init_interval: settings.nonce_init_interval,
+                            core_offset: settings.core_offset,
+                            miner_name: settings.miner_name.clone(),
+                        };
+                        minepmc(args, pmc, key, base_url, unsecure_conn).await?;
                     }
 
                     "  Protomine" => {
@@ -829,8 +1018,13 @@ async fn run_command(
 
                         let args = ProtoMineArgs {
                             threads: threads.try_into().unwrap(),
+                            backend: config::settings().backend,
+                            verify_solutions: false,
+                            metrics_addr: None,
+                            failover: config::failover_urls(&config::settings()),
+                            finish_pass: false,
                         };
-                        protomine(args, key, base_url, unsecure_conn).await;
+                        protomine(args, key, base_url, unsecure_conn).await?;
                     }
                     "  Sign up" => {
                         let use_different_pubkey = Confirm::new("  Would you like to sign up a different pubkey than your selected keypair's pubkey?")
@@ -842,7 +1036,7 @@ async fn run_command(
                             let alt_pubkey = loop {
                                 let input = Text::new("  Enter the miner public key to sign up:")
                                     .prompt()?;
-                                match Pubkey::from_str(&input.trim()) {
+                                match Pubkey::from_str(input.trim()) {
                                     Ok(pk) => break pk.to_string(),
                                     Err(_) => {
                                         println!("  Invalid public key format. Please try again.");
@@ -857,7 +1051,7 @@ async fn run_command(
                             SignupArgs { pubkey: None }
                         };
 
-                        signup(signup_args, base_url, key, unsecure_conn).await;
+                        signup(signup_args, base_url, key, unsecure_conn).await?;
                     }
                     "  Claim Rewards" => {
                         let use_separate_pubkey = Confirm::new(
@@ -877,7 +1071,7 @@ async fn run_command(
                             y: false,
                             receiver_pubkey,
                         };
-                        claim::claim(args, key, base_url, unsecure_conn).await;
+                        claim::claim(args, key, base_url, unsecure_conn).await?;
                     }
                     "  Claim Stake Rewards" => {
                         let use_separate_pubkey = Confirm::new(
@@ -918,12 +1112,12 @@ async fn run_command(
                             mint_pubkey: mint,
                             receiver_pubkey,
                         };
-                        claim_stake_rewards::claim_stake_rewards(args, key, base_url, unsecure_conn).await;
+                        claim_stake_rewards::claim_stake_rewards(args, key, base_url, unsecure_conn).await?;
                     }
                     "  View Balances" => {
-                        balance(&key, base_url.clone(), unsecure_conn).await;
+                        balance(&key, base_url.clone(), unsecure_conn).await?;
                         println!();
-                        earnings::earnings();
+                        earnings::earnings(&earnings::EarningsArgs::default())?;
                     }
                     "  Stake Boost" => {
                         let token_selection = Select::new(
@@ -945,13 +1139,16 @@ async fn run_command(
                             .map(|(_, address)| address.to_string())
                             .expect("  Invalid token selection.");
 
-                        let token_balance = balance::get_token_balance(
-                            &key,
-                            base_url.clone(),
-                            unsecure_conn,
-                            mint.clone(),
-                        )
-                        .await;
+                        let api = api::PoolApi::new(&base_url, unsecure_conn);
+                        let mint_pubkey = Pubkey::from_str(&mint).unwrap();
+                        let token_balance =
+                            match api.token_balance(&key.pubkey(), &mint_pubkey).await {
+                                Ok(balance) => balance.unwrap_or(0.0),
+                                Err(e) => {
+                                    let context = format!("Failed to fetch {} balance", token_selection);
+                                    return Err(Error::api(context, e));
+                                }
+                            };
                         println!(
                             "  Current balance for {}: {}",
                             token_selection, token_balance
@@ -980,14 +1177,14 @@ async fn run_command(
                             }
                         };
 
-                        let boost_args = delegate_boost::BoostArgs { amount, mint, auto: true };
+                        let boost_args = delegate_boost::BoostArgs { amount, mint, auto: true, y: false };
                         delegate_boost::delegate_boost(
                             boost_args,
                             key,
                             base_url.clone(),
                             unsecure_conn,
                         )
-                        .await;
+                        .await?;
                     }
                     "  Unstake Boost" => {
                         let token_selection = Select::new(
@@ -1009,13 +1206,20 @@ async fn run_command(
                             .map(|(_, address)| address.to_string())
                             .expect("  Invalid token selection.");
 
-                        let boosted_stake_balance = balance::get_boosted_stake_balance_v2(
-                            &key,
-                            base_url.clone(),
-                            unsecure_conn,
-                            mint.clone(),
-                        )
-                        .await;
+                        let api = api::PoolApi::new(&base_url, unsecure_conn);
+                        let mint_pubkey = Pubkey::from_str(&mint).unwrap();
+                        let boosted_stake_balance =
+                            match api.boost_stake_v2(&key.pubkey(), &mint_pubkey).await {
+                                Ok(balance) => balance,
+                                Err(api::ApiError::NoStakeAccount) => 0.0,
+                                Err(e) => {
+                                    let context = format!(
+                                        "Failed to fetch {} boosted stake balance",
+                                        token_selection
+                                    );
+                                    return Err(Error::api(context, e));
+                                }
+                            };
 
                         println!(
                             "  Current boosted stake balance for {}: {}",
@@ -1045,21 +1249,21 @@ async fn run_command(
                             }
                         };
 
-                        let unboost_args = undelegate_boost::UnboostArgs { amount, mint };
+                        let unboost_args = undelegate_boost::UnboostArgs { amount, mint, y: false };
                         undelegate_boost::undelegate_boost(
                             unboost_args,
                             key,
                             base_url.clone(),
                             unsecure_conn,
                         )
-                        .await;
+                        .await?;
                     }
 
                     "  Migrate Boosts" => {
-                        migrate_boosts_to_v2::migrate_boosts_to_v2(key, base_url.clone(), unsecure_conn).await;
+                        migrate_boosts_to_v2::migrate_boosts_to_v2(key, base_url.clone(), unsecure_conn).await?;
                     },
                     "  Unstake (Legacy)" => {
-                        stake_balance::stake_balance(&key, base_url.clone(), unsecure_conn).await;
+                        stake_balance::stake_balance(&key, base_url.clone(), unsecure_conn).await?;
 
                         loop {
                             let unstake_input = Text::new(
@@ -1079,6 +1283,7 @@ async fn run_command(
                                         Ok(unstake_amount) if unstake_amount > 0.0 => {
                                             let args = undelegate_stake::UnstakeArgs {
                                                 amount: unstake_amount,
+                                                y: false,
                                             };
                                             undelegate_stake::undelegate_stake(
                                                 args,
@@ -1086,7 +1291,7 @@ async fn run_command(
                                                 base_url.clone(),
                                                 unsecure_conn,
                                             )
-                                            .await;
+                                            .await?;
                                             break;
                                         }
                                         Ok(_) => {
@@ -1164,21 +1369,17 @@ async fn update_client() -> Result<(), Box<dyn std::error::Error>> {
 
 async fn get_latest_crate_version(crate_name: &str) -> Result<String, Box<dyn std::error::Error>> {
     let url = format!("https://crates.io/api/v1/crates/{}", crate_name);
-    let client = reqwest::Client::new();
-    let resp = client
+    let body = http::client()
         .get(&url)
         .header("User-Agent", "ore-hq-client")
         .send()
-        .await?;
+        .await?
+        .text(&url)?;
 
-    if resp.status().is_success() {
-        let json: serde_json::Value = resp.json().await?;
-        if let Some(version) = json["crate"]["max_version"].as_str() {
-            Ok(version.to_string())
-        } else {
-            Err("Failed to parse version from response".into())
-        }
+    let json: serde_json::Value = serde_json::from_str(&body)?;
+    if let Some(version) = json["crate"]["max_version"].as_str() {
+        Ok(version.to_string())
     } else {
-        Err(format!("Failed to fetch crate info: {}", resp.status()).into())
+        Err("Failed to parse version from response".into())
     }
 }
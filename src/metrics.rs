@@ -0,0 +1,343 @@
+//! Prometheus metrics for the mining commands, served with `--metrics-addr`.
+//!
+//! The session records into the global `METRICS` whether or not anything is serving
+//! them; all values are plain atomics so recording costs nothing on the hot paths.
+
+use log::info;
+use once_cell::sync::Lazy;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::protocol::ServerMessagePoolSubmissionResult;
+use crate::session::PassReport;
+use crate::stats::{GLOBAL_TX_OVERTIME, MINER_ACCURACY_BUFFER, TX_TIME_BUFFER};
+
+pub static METRICS: Lazy<Metrics> = Lazy::new(Metrics::default);
+
+#[derive(Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// An f64 stored as its bits.
+#[derive(Default)]
+pub struct Gauge(AtomicU64);
+
+impl Gauge {
+    pub fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    pub passes: Counter,
+    pub hashes: Counter,
+    pub hashrate: Gauge,
+    pub pass_best_difficulty: Gauge,
+    pub solutions_submitted: Counter,
+    pub submitted_difficulty: Gauge,
+    pub invalid_solutions: Counter,
+    pub pool_difficulty: Gauge,
+    pub miner_supplied_difficulty: Gauge,
+    /// In ORE
+    pub miner_earned: Gauge,
+    pub miner_earned_grains: Counter,
+    pub miner_percentage: Gauge,
+    pub active_miners: Gauge,
+    pub pool_results: Counter,
+    pub reconnects: Counter,
+    pub websocket_errors: Counter,
+    pub connect_failures: Counter,
+    pub failovers: Counter,
+}
+
+impl Metrics {
+    pub fn record_pass(&self, report: &PassReport) {
+        self.passes.inc();
+        self.hashes.add(report.hashes);
+        let secs = report.hash_time.as_secs_f64();
+        if secs > 0.0 {
+            self.hashrate.set(report.hashes as f64 / secs);
+        }
+        self.pass_best_difficulty.set(report.best_difficulty as f64);
+    }
+
+    pub fn record_submission(&self, difficulty: u32) {
+        self.solutions_submitted.inc();
+        self.submitted_difficulty.set(difficulty as f64);
+    }
+
+    pub fn record_pool_result(&self, data: &ServerMessagePoolSubmissionResult) {
+        self.pool_results.inc();
+        self.pool_difficulty.set(data.difficulty as f64);
+        self.miner_supplied_difficulty
+            .set(data.miner_supplied_difficulty as f64);
+        self.miner_earned.set(data.miner_earned_rewards);
+        self.miner_earned_grains.add(
+            (data.miner_earned_rewards * 10f64.powf(ore_api::consts::TOKEN_DECIMALS as f64)) as u64,
+        );
+        self.miner_percentage.set(data.miner_percentage);
+        self.active_miners.set(data.active_miners as f64);
+    }
+
+    /// The Prometheus text exposition of every metric, plus the stats.rs buffers.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let counters = [
+            ("ore_passes_total", "Mining passes completed", &self.passes),
+            ("ore_hashes_total", "Nonces hashed", &self.hashes),
+            (
+                "ore_solutions_submitted_total",
+                "Improving solutions sent to the pool",
+                &self.solutions_submitted,
+            ),
+            (
+                "ore_invalid_solutions_total",
+                "Solutions dropped by local verification",
+                &self.invalid_solutions,
+            ),
+            (
+                "ore_pool_results_total",
+                "Pool submission results received",
+                &self.pool_results,
+            ),
+            (
+                "ore_reconnects_total",
+                "Websocket connections that ended and were retried",
+                &self.reconnects,
+            ),
+            (
+                "ore_websocket_errors_total",
+                "Errors reading from the pool websocket",
+                &self.websocket_errors,
+            ),
+            (
+                "ore_connect_failures_total",
+                "Failed websocket connection attempts",
+                &self.connect_failures,
+            ),
+            (
+                "ore_miner_earned_grains_total",
+                "Grains of ORE earned by this miner since start",
+                &self.miner_earned_grains,
+            ),
+            (
+                "ore_failovers_total",
+                "Switches to the next pool after the active one kept failing",
+                &self.failovers,
+            ),
+        ];
+        for (name, help, counter) in counters {
+            write_metric(&mut out, name, help, "counter", counter.get() as f64);
+        }
+
+        let gauges = [
+            (
+                "ore_hashrate",
+                "Hashes per second over the last pass",
+                &self.hashrate,
+            ),
+            (
+                "ore_pass_best_difficulty",
+                "Best difficulty found in the last pass",
+                &self.pass_best_difficulty,
+            ),
+            (
+                "ore_submitted_difficulty",
+                "Difficulty of the last solution sent to the pool",
+                &self.submitted_difficulty,
+            ),
+            (
+                "ore_pool_difficulty",
+                "Difficulty the pool last submitted on chain",
+                &self.pool_difficulty,
+            ),
+            (
+                "ore_miner_supplied_difficulty",
+                "Best difficulty the pool last counted for this miner",
+                &self.miner_supplied_difficulty,
+            ),
+            (
+                "ore_miner_earned",
+                "ORE earned by this miner in the last pool submission",
+                &self.miner_earned,
+            ),
+            (
+                "ore_miner_percentage",
+                "Share of the last pool reward earned by this miner",
+                &self.miner_percentage,
+            ),
+            (
+                "ore_active_miners",
+                "Active miners in the pool",
+                &self.active_miners,
+            ),
+        ];
+        for (name, help, gauge) in gauges {
+            write_metric(&mut out, name, help, "gauge", gauge.get());
+        }
+
+        {
+            let accuracy = MINER_ACCURACY_BUFFER.lock().unwrap();
+            write_metric(
+                &mut out,
+                "ore_miner_accuracy_median_ms",
+                "Median overmine accuracy (mine-pmc)",
+                "gauge",
+                accuracy.calculate_median(),
+            );
+            write_metric(
+                &mut out,
+                "ore_miner_accuracy_min_ms",
+                "Lowest recent overmine accuracy (mine-pmc)",
+                "gauge",
+                accuracy.calculate_min(),
+            );
+            write_metric(
+                &mut out,
+                "ore_miner_accuracy_max_ms",
+                "Highest recent overmine accuracy (mine-pmc)",
+                "gauge",
+                accuracy.calculate_max(),
+            );
+        }
+        {
+            let tx_time = TX_TIME_BUFFER.lock().unwrap();
+            write_metric(
+                &mut out,
+                "ore_tx_time_median_seconds",
+                "Median time for the pool to land its transaction (mine-pmc)",
+                "gauge",
+                tx_time.calculate_median(),
+            );
+            write_metric(
+                &mut out,
+                "ore_tx_time_max_seconds",
+                "Longest recent time for the pool to land its transaction (mine-pmc)",
+                "gauge",
+                tx_time.calculate_max(),
+            );
+        }
+        let overtime = *GLOBAL_TX_OVERTIME.lock().unwrap();
+        write_metric(
+            &mut out,
+            "ore_tx_overtime_total",
+            "Pool transactions that took over 10s (mine-pmc)",
+            "counter",
+            overtime as f64,
+        );
+        out
+    }
+}
+
+fn write_metric(out: &mut String, name: &str, help: &str, kind: &str, value: f64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// Binds the metrics address up front, so a bad `--metrics-addr` stops the miner.
+pub async fn bind(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Serving metrics on http://{}/metrics", addr);
+    Ok(listener)
+}
+
+/// Serves `METRICS` on `GET /metrics` until the process exits.
+pub async fn serve(listener: TcpListener) {
+    while let Ok((mut stream, _)) = listener.accept().await {
+        tokio::spawn(async move {
+            // Only the request line matters, scrapers send tiny requests
+            let mut buf = [0u8; 1024];
+            let n = stream.read(&mut buf).await.unwrap_or(0);
+            let request = String::from_utf8_lossy(&buf[..n]);
+            let (status, body) = if request.starts_with("GET /metrics ") {
+                ("200 OK", METRICS.render())
+            } else {
+                ("404 Not Found", "Not Found\n".to_string())
+            };
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn renders_recorded_values() {
+        let metrics = Metrics::default();
+        metrics.record_pass(&PassReport {
+            hashes: 3_000,
+            hash_time: Duration::from_secs(2),
+            best_difficulty: 19,
+            overmined_by_ms: None,
+        });
+        metrics.record_submission(17);
+        metrics.record_submission(19);
+        metrics.reconnects.inc();
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE ore_hashrate gauge\nore_hashrate 1500\n"));
+        assert!(text.contains("\nore_hashes_total 3000\n"));
+        assert!(text.contains("\nore_solutions_submitted_total 2\n"));
+        assert!(text.contains("\nore_submitted_difficulty 19\n"));
+        assert!(text.contains("\nore_reconnects_total 1\n"));
+        assert!(text.contains("\nore_miner_accuracy_median_ms "));
+    }
+
+    #[tokio::test]
+    async fn serves_metrics_over_http() {
+        let listener = bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve(listener));
+
+        let url = format!("http://{}/metrics", addr);
+        let mut response = None;
+        for _ in 0..50 {
+            if let Ok(res) = reqwest::get(&url).await {
+                response = Some(res);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let response = response.unwrap();
+        assert!(response.status().is_success());
+        assert!(response.text().await.unwrap().contains("ore_hashrate"));
+
+        let missing = reqwest::get(format!("http://{}/other", addr))
+            .await
+            .unwrap();
+        assert_eq!(missing.status(), 404);
+        server.abort();
+    }
+}
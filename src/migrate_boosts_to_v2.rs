@@ -1,243 +1,127 @@
-use base64::{prelude::BASE64_STANDARD, Engine};
-use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+use serde::Serialize;
+use solana_sdk::{pubkey::Pubkey, signer::Signer};
 use std::str::FromStr;
 
-use crate::balance;
+use crate::api::{ApiError, PoolApi};
+use crate::error::Error;
+use crate::output;
+use crate::signer::Wallet;
+use crate::textln;
 
-pub async fn migrate_boosts_to_v2(key: Keypair, url: String, unsecure: bool) {
-    println!("Migrating Boosts...");
-    let base_url = url;
-    let client = reqwest::Client::new();
-    let url_prefix = if unsecure {
-        "http".to_string()
-    } else {
-        "https".to_string()
-    };
-
-    let token_mints = vec![
-        (Pubkey::from_str("oreoU2P8bN6jkk3jbaiVxYnG1dCXcYxwhwyK9jSybcp").unwrap(), "ORE Token"),
-        (Pubkey::from_str("DrSS5RM7zUd9qjUEdDaf31vnDUSbCrMto6mjqTrHFifN").unwrap(), "ORE-SOL LP"),
-        (Pubkey::from_str("meUwDp23AaxhiNKaQCyJ2EAF2T4oe1gSkEkGXSRVdZb").unwrap(), "ORE-ISC LP"),
-    ];
-
-    let ore_v1_boost_amount = balance::get_boosted_stake_balance(&key, base_url.clone(), unsecure, token_mints[0].0.to_string()).await;
-    let ore_sol_v1_boost_amount = balance::get_boosted_stake_balance(&key, base_url.clone(), unsecure, token_mints[1].0.to_string()).await;
-    let ore_isc_v1_boost_amount = balance::get_boosted_stake_balance(&key, base_url.clone(), unsecure, token_mints[2].0.to_string()).await;
-
-    let resp = client
-        .get(format!(
-            "{}://{}/pool/authority/pubkey",
-            url_prefix, base_url
-        ))
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-    let pool_pubkey = Pubkey::from_str(&resp).unwrap();
+#[derive(Debug, Serialize)]
+struct MigrationReport {
+    migrations: Vec<Migration>,
+}
 
-    let resp = client
-        .get(format!(
-            "{}://{}/pool/fee_payer/pubkey",
-            url_prefix, base_url
-        ))
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-    let fee_pubkey = Pubkey::from_str(&resp).unwrap();
+#[derive(Debug, Serialize)]
+struct Migration {
+    mint: String,
+    label: &'static str,
+    amount: f64,
+    /// `migrated`, `nothing_to_migrate` or `failed`
+    status: &'static str,
+    error: Option<String>,
+}
 
-    if ore_v1_boost_amount > 0.0 {
-        println!("Migrating {} ORE", ore_v1_boost_amount);
-        // migrate ore boost
-        let ore_v2_boost_amount = balance::get_boosted_stake_balance_v2(&key, base_url.clone(), unsecure, token_mints[0].0.to_string()).await;
-        let mut ixs = vec![];
-        // init boost account
-        if ore_v2_boost_amount < 0.0 {
-            // add init ix
-            let ix = ore_miner_delegation::instruction::init_delegate_boost_v2(key.pubkey(), pool_pubkey, fee_pubkey, token_mints[0].0);
-            ixs.push(ix);
-        }
-        // migrate balance
-        let ix = ore_miner_delegation::instruction::migrate_boost_to_v2(key.pubkey(), pool_pubkey, token_mints[0].0);
-        ixs.push(ix);
-        let mut tx = solana_sdk::transaction::Transaction::new_with_payer(&ixs, Some(&fee_pubkey));
-        let resp = client
-            .get(format!("{}://{}/latest-blockhash", url_prefix, base_url))
-            .send()
-            .await
-            .unwrap()
-            .text()
-            .await
-            .unwrap();
-        let decoded_blockhash = BASE64_STANDARD.decode(resp).unwrap();
-        let deserialized_blockhash = bincode::deserialize(&decoded_blockhash).unwrap();
-        tx.partial_sign(&[&key], deserialized_blockhash);
-        let serialized_tx = bincode::serialize(&tx).unwrap();
-        let encoded_tx = BASE64_STANDARD.encode(&serialized_tx);
+pub async fn migrate_boosts_to_v2(key: Wallet, url: String, unsecure: bool) -> Result<(), Error> {
+    textln!("Migrating Boosts...");
+    let api = PoolApi::new(&url, unsecure);
 
-        let needs_init = ixs.len() > 1;
+    let token_mints = [
+        (Pubkey::from_str("oreoU2P8bN6jkk3jbaiVxYnG1dCXcYxwhwyK9jSybcp").unwrap(), "ORE", "ore"),
+        (Pubkey::from_str("DrSS5RM7zUd9qjUEdDaf31vnDUSbCrMto6mjqTrHFifN").unwrap(), "ORE-SOL", "ore-sol"),
+        (Pubkey::from_str("meUwDp23AaxhiNKaQCyJ2EAF2T4oe1gSkEkGXSRVdZb").unwrap(), "ORE-ISC", "ore-isc"),
+    ];
 
-        let resp = client
-            .post(format!(
-                "{}://{}/v2/migrate-boost?pubkey={}&mint={}&init={}",
-                url_prefix,
-                base_url,
-                key.pubkey().to_string(),
-                token_mints[0].0.to_string(),
-                needs_init
-            ))
-            .body(encoded_tx)
-            .send()
-            .await;
-        if let Ok(res) = resp {
-            if let Ok(txt) = res.text().await {
-                match txt.as_str() {
-                    "SUCCESS" => {
-                        println!("  Successfully migrated ore boost!");
-                    }
-                    other => {
-                        println!("  Boost Migration Transaction failed: {}", other);
-                    }
-                }
-            } else {
-                println!("  Boost Migration Transaction failed, please wait and try again.");
+    let mut v1_boost_amounts = vec![];
+    for (mint, label, _) in token_mints.iter() {
+        match api.boost_stake(&key.pubkey(), mint).await {
+            Ok(amount) => v1_boost_amounts.push(amount),
+            Err(ApiError::NoStakeAccount) => v1_boost_amounts.push(0.0),
+            Err(e) => {
+                let context = format!("Failed to fetch boost v1 {} balance", label);
+                return Err(Error::api(context, e));
             }
-        } else {
-            println!("  Boost Migration Transaction failed, please wait and try again.");
         }
-    } else {
-        println!("No boost v1 ORE to migrate");
     }
 
-    if ore_sol_v1_boost_amount > 0.0 {
-        println!("Migrating {} ORE-SOL", ore_sol_v1_boost_amount);
-        // migrate ore boost
-        let ore_v2_boost_amount = balance::get_boosted_stake_balance_v2(&key, base_url.clone(), unsecure, token_mints[1].0.to_string()).await;
-        let mut ixs = vec![];
-        // init boost account
-        if ore_v2_boost_amount < 0.0 {
-            // add init ix
-            let ix = ore_miner_delegation::instruction::init_delegate_boost_v2(key.pubkey(), pool_pubkey, fee_pubkey, token_mints[1].0);
-            ixs.push(ix);
+    let (pool_pubkey, fee_pubkey) = match (api.pool_authority().await, api.fee_payer().await) {
+        (Ok(pool_pubkey), Ok(fee_pubkey)) => (pool_pubkey, fee_pubkey),
+        (Err(e), _) | (_, Err(e)) => {
+            return Err(Error::api("Failed to fetch pool accounts", e))
         }
-        // migrate balance
-        let ix = ore_miner_delegation::instruction::migrate_boost_to_v2(key.pubkey(), pool_pubkey, token_mints[1].0);
-        ixs.push(ix);
-        let mut tx = solana_sdk::transaction::Transaction::new_with_payer(&ixs, Some(&fee_pubkey));
-        let resp = client
-            .get(format!("{}://{}/latest-blockhash", url_prefix, base_url))
-            .send()
-            .await
-            .unwrap()
-            .text()
-            .await
-            .unwrap();
-        let decoded_blockhash = BASE64_STANDARD.decode(resp).unwrap();
-        let deserialized_blockhash = bincode::deserialize(&decoded_blockhash).unwrap();
-        tx.partial_sign(&[&key], deserialized_blockhash);
-        let serialized_tx = bincode::serialize(&tx).unwrap();
-        let encoded_tx = BASE64_STANDARD.encode(&serialized_tx);
+    };
 
-        let needs_init = ixs.len() > 1;
+    let mut migrations = vec![];
+    for ((mint, label, name), v1_boost_amount) in token_mints.iter().zip(v1_boost_amounts) {
+        let mut migration = Migration {
+            mint: mint.to_string(),
+            label,
+            amount: v1_boost_amount,
+            status: "nothing_to_migrate",
+            error: None,
+        };
+        if v1_boost_amount <= 0.0 {
+            textln!("No boost v1 {} to migrate", label);
+            migrations.push(migration);
+            continue;
+        }
 
-        let resp = client
-            .post(format!(
-                "{}://{}/v2/migrate-boost?pubkey={}&mint={}&init={}",
-                url_prefix,
-                base_url,
-                key.pubkey().to_string(),
-                token_mints[1].0.to_string(),
-                needs_init
-            ))
-            .body(encoded_tx)
-            .send()
-            .await;
-        if let Ok(res) = resp {
-            if let Ok(txt) = res.text().await {
-                match txt.as_str() {
-                    "SUCCESS" => {
-                        println!("  Successfully migrated ore-sol boost!");
-                    }
-                    other => {
-                        println!("  Boost Migration Transaction failed: {}", other);
-                    }
+        textln!("Migrating {} {}", v1_boost_amount, label);
+        match migrate_boost(&api, &key, mint, pool_pubkey, fee_pubkey).await {
+            Ok(()) => {
+                textln!("  Successfully migrated {} boost!", name);
+                migration.status = "migrated";
+            }
+            Err(e) => {
+                textln!("  Boost Migration Transaction failed: {}", e);
+                if !matches!(e, ApiError::Rejected(_)) {
+                    textln!("  Please wait and try again.");
                 }
-            } else {
-                println!("  Boost Migration Transaction failed, please wait and try again.");
+                migration.status = "failed";
+                migration.error = Some(e.to_string());
             }
-        } else {
-            println!("  Boost Migration Transaction failed, please wait and try again.");
         }
-    } else {
-        println!("No boost v1 ORE-SOL to migrate");
+        migrations.push(migration);
     }
 
-    if ore_isc_v1_boost_amount > 0.0 {
-        println!("Migrating {} ORE-ISC", ore_isc_v1_boost_amount);
-        // migrate ore boost
-        let ore_v2_boost_amount = balance::get_boosted_stake_balance_v2(&key, base_url.clone(), unsecure, token_mints[2].0.to_string()).await;
-        let mut ixs = vec![];
-        // init boost account
-        if ore_v2_boost_amount < 0.0 {
-            // add init ix
-            let ix = ore_miner_delegation::instruction::init_delegate_boost_v2(key.pubkey(), pool_pubkey, fee_pubkey, token_mints[2].0);
-            ixs.push(ix);
-        }
-        // migrate balance
-        let ix = ore_miner_delegation::instruction::migrate_boost_to_v2(key.pubkey(), pool_pubkey, token_mints[2].0);
-        ixs.push(ix);
-        let mut tx = solana_sdk::transaction::Transaction::new_with_payer(&ixs, Some(&fee_pubkey));
-        let resp = client
-            .get(format!("{}://{}/latest-blockhash", url_prefix, base_url))
-            .send()
-            .await
-            .unwrap()
-            .text()
-            .await
-            .unwrap();
-        let decoded_blockhash = BASE64_STANDARD.decode(resp).unwrap();
-        let deserialized_blockhash = bincode::deserialize(&decoded_blockhash).unwrap();
-        tx.partial_sign(&[&key], deserialized_blockhash);
-        let serialized_tx = bincode::serialize(&tx).unwrap();
-        let encoded_tx = BASE64_STANDARD.encode(&serialized_tx);
+    textln!("Boost Migrations Complete");
 
-        let needs_init = ixs.len() > 1;
+    let report = MigrationReport { migrations };
+    let failed = report.migrations.iter().filter(|m| m.status == "failed").count();
+    if failed > 0 {
+        output::emit_unsuccessful("migrate-boosts", &report);
+        return Err(Error::MigrationsFailed { failed });
+    }
+    output::emit("migrate-boosts", &report);
+    Ok(())
+}
 
-        let resp = client
-            .post(format!(
-                "{}://{}/v2/migrate-boost?pubkey={}&mint={}&init={}",
-                url_prefix,
-                base_url,
-                key.pubkey().to_string(),
-                token_mints[2].0.to_string(),
-                needs_init
-            ))
-            .body(encoded_tx)
-            .send()
-            .await;
-        if let Ok(res) = resp {
-            if let Ok(txt) = res.text().await {
-                match txt.as_str() {
-                    "SUCCESS" => {
-                        println!("  Successfully migrated ore-isc boost!");
-                    }
-                    other => {
-                        println!("  Boost Migration Transaction failed: {}", other);
-                    }
-                }
-            } else {
-                println!("  Boost Migration Transaction failed, please wait and try again.");
-            }
-        } else {
-            println!("  Boost Migration Transaction failed, please wait and try again.");
+async fn migrate_boost(
+    api: &PoolApi,
+    key: &Wallet,
+    mint: &Pubkey,
+    pool_pubkey: Pubkey,
+    fee_pubkey: Pubkey,
+) -> Result<(), ApiError> {
+    let mut ixs = vec![];
+    // init boost account
+    match api.boost_stake_v2(&key.pubkey(), mint).await {
+        Ok(_) => {}
+        Err(ApiError::NoStakeAccount) => {
+            // add init ix
+            let ix = ore_miner_delegation::instruction::init_delegate_boost_v2(key.pubkey(), pool_pubkey, fee_pubkey, *mint);
+            ixs.push(ix);
         }
-    } else {
-        println!("No boost v1 ORE-ISC to migrate");
+        Err(e) => return Err(e),
     }
-
-    println!("Boost Migrations Complete");
+    // migrate balance
+    let ix = ore_miner_delegation::instruction::migrate_boost_to_v2(key.pubkey(), pool_pubkey, *mint);
+    ixs.push(ix);
+    let mut tx = solana_sdk::transaction::Transaction::new_with_payer(&ixs, Some(&fee_pubkey));
+    let blockhash = api.latest_blockhash().await?;
+    key.sign_transaction(&mut tx, blockhash).await?;
+
+    let needs_init = ixs.len() > 1;
+    api.migrate_boost(&key.pubkey(), mint, needs_init, &tx).await
 }
@@ -0,0 +1,316 @@
+//! Versioned schema for the app database, tracked with `PRAGMA user_version`.
+//!
+//! Migrations are applied in order, each in its own transaction together with the
+//! version bump. Append new ones to `MIGRATIONS`; never edit one that has shipped.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use rusqlite::{Connection, OptionalExtension};
+
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    /// Drops or rewrites existing data, so the database file is backed up first
+    pub destructive: bool,
+    apply: fn(&Connection) -> rusqlite::Result<()>,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Create pool_submission_results",
+        destructive: false,
+        // Databases from before versioning already have this table at version 0
+        apply: |conn| {
+            conn.execute(
+                r#"CREATE TABLE IF NOT EXISTS pool_submission_results (
+                    id INTEGER PRIMARY KEY,
+                    pool_difficulty INTEGER NOT NULL,
+                    pool_earned INTEGER NOT NULL,
+                    miner_percentage NUMERIC NOT NULL,
+                    miner_difficulty INTEGER NOT NULL,
+                    miner_earned INTEGER NOT NULL,
+                    created_at  INTEGER DEFAULT CURRENT_TIMESTAMP NOT NULL
+                )"#,
+                (),
+            )?;
+            Ok(())
+        },
+    },
+    Migration {
+        version: 2,
+        description: "Add challenge to pool_submission_results",
+        destructive: false,
+        apply: |conn| add_column_if_missing(conn, "pool_submission_results", "challenge", "TEXT"),
+    },
+    Migration {
+        version: 3,
+        description: "Create mining_passes",
+        destructive: false,
+        apply: |conn| {
+            conn.execute(
+                r#"CREATE TABLE IF NOT EXISTS mining_passes (
+                    id INTEGER PRIMARY KEY,
+                    challenge TEXT NOT NULL,
+                    nonce_start INTEGER NOT NULL,
+                    nonce_end INTEGER NOT NULL,
+                    cutoff INTEGER NOT NULL,
+                    hashes INTEGER NOT NULL,
+                    hash_time_ms INTEGER NOT NULL,
+                    hashrate NUMERIC NOT NULL,
+                    best_difficulty INTEGER NOT NULL,
+                    overmined_by_ms NUMERIC,
+                    created_at  INTEGER DEFAULT CURRENT_TIMESTAMP NOT NULL
+                )"#,
+                (),
+            )?;
+            Ok(())
+        },
+    },
+    Migration {
+        version: 4,
+        description: "Record the miner pubkey and pool on results and passes",
+        destructive: false,
+        apply: |conn| {
+            for table in ["pool_submission_results", "mining_passes"] {
+                add_column_if_missing(conn, table, "pubkey", "TEXT")?;
+                add_column_if_missing(conn, table, "pool", "TEXT")?;
+            }
+            Ok(())
+        },
+    },
+];
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Sqlite(rusqlite::Error),
+    Backup(std::io::Error),
+    /// The database was written by a newer client
+    TooNew {
+        found: u32,
+        supported: u32,
+    },
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Sqlite(e) => write!(f, "database error: {}", e),
+            MigrationError::Backup(e) => write!(f, "failed to back up the database: {}", e),
+            MigrationError::TooNew { found, supported } => write!(
+                f,
+                "database schema version {} is newer than this client supports ({}), please update",
+                found, supported
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<rusqlite::Error> for MigrationError {
+    fn from(e: rusqlite::Error) -> Self {
+        MigrationError::Sqlite(e)
+    }
+}
+
+#[derive(Default)]
+pub struct MigrationReport {
+    pub applied: Vec<&'static Migration>,
+    pub backup: Option<PathBuf>,
+}
+
+pub fn latest_version() -> u32 {
+    latest_in(MIGRATIONS)
+}
+
+pub fn current_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Migrations not yet applied to this database.
+pub fn pending(conn: &Connection) -> Result<Vec<&'static Migration>, MigrationError> {
+    pending_in(conn, MIGRATIONS)
+}
+
+/// Brings the database at `path` up to the latest schema.
+pub fn migrate(conn: &mut Connection, path: &Path) -> Result<MigrationReport, MigrationError> {
+    migrate_with(conn, path, MIGRATIONS)
+}
+
+fn latest_in(migrations: &[Migration]) -> u32 {
+    migrations.last().map_or(0, |m| m.version)
+}
+
+fn pending_in(
+    conn: &Connection,
+    migrations: &'static [Migration],
+) -> Result<Vec<&'static Migration>, MigrationError> {
+    let current = current_version(conn)?;
+    let supported = latest_in(migrations);
+    if current > supported {
+        return Err(MigrationError::TooNew {
+            found: current,
+            supported,
+        });
+    }
+    Ok(migrations.iter().filter(|m| m.version > current).collect())
+}
+
+fn migrate_with(
+    conn: &mut Connection,
+    path: &Path,
+    migrations: &'static [Migration],
+) -> Result<MigrationReport, MigrationError> {
+    let pending = pending_in(conn, migrations)?;
+    let mut report = MigrationReport::default();
+
+    if pending.iter().any(|m| m.destructive) && path.exists() {
+        let backup = backup_path(path, current_version(conn)?);
+        std::fs::copy(path, &backup).map_err(MigrationError::Backup)?;
+        report.backup = Some(backup);
+    }
+
+    for migration in pending {
+        let tx = conn.transaction()?;
+        (migration.apply)(&tx)?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+        report.applied.push(migration);
+    }
+    Ok(report)
+}
+
+fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(
+        ".v{}-{}.bak",
+        version,
+        chrono::Local::now().format("%Y%m%d%H%M%S")
+    ));
+    PathBuf::from(name)
+}
+
+fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    let exists = conn
+        .query_row(
+            &format!("SELECT 1 FROM pragma_table_info('{table}') WHERE name = ?1"),
+            [column],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some();
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"),
+            (),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_db::TempDb;
+
+    #[test]
+    fn fresh_database_reaches_latest_version() {
+        let path = TempDb::new("migrations-fresh");
+        let mut conn = Connection::open(&path).unwrap();
+
+        let report = migrate(&mut conn, &path).unwrap();
+
+        assert_eq!(report.applied.len(), MIGRATIONS.len());
+        assert!(report.backup.is_none());
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+        assert!(pending(&conn).unwrap().is_empty());
+        assert!(migrate(&mut conn, &path).unwrap().applied.is_empty());
+    }
+
+    #[test]
+    fn unversioned_database_keeps_its_earnings() {
+        let path = TempDb::new("migrations-legacy");
+        let mut conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            r#"CREATE TABLE pool_submission_results (
+                id INTEGER PRIMARY KEY,
+                pool_difficulty INTEGER NOT NULL,
+                pool_earned INTEGER NOT NULL,
+                miner_percentage NUMERIC NOT NULL,
+                miner_difficulty INTEGER NOT NULL,
+                miner_earned INTEGER NOT NULL,
+                created_at  INTEGER DEFAULT CURRENT_TIMESTAMP NOT NULL
+            );
+            INSERT INTO pool_submission_results
+                (pool_difficulty, pool_earned, miner_percentage, miner_difficulty, miner_earned)
+                VALUES (20, 500, 1.5, 15, 42);"#,
+        )
+        .unwrap();
+
+        migrate(&mut conn, &path).unwrap();
+
+        let (earned, challenge): (u64, Option<String>) = conn
+            .query_row(
+                "SELECT miner_earned, challenge FROM pool_submission_results",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((earned, challenge), (42, None));
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn backs_up_before_destructive_migrations() {
+        static DESTRUCTIVE: &[Migration] = &[
+            Migration {
+                version: 1,
+                description: "Create t",
+                destructive: false,
+                apply: |conn| {
+                    conn.execute_batch("CREATE TABLE t (x INTEGER); INSERT INTO t VALUES (7);")
+                },
+            },
+            Migration {
+                version: 2,
+                description: "Drop t",
+                destructive: true,
+                apply: |conn| conn.execute_batch("DROP TABLE t;"),
+            },
+        ];
+        let path = TempDb::new("migrations-backup");
+        let mut conn = Connection::open(&path).unwrap();
+        migrate_with(&mut conn, &path, &DESTRUCTIVE[..1]).unwrap();
+
+        let report = migrate_with(&mut conn, &path, DESTRUCTIVE).unwrap();
+
+        let backup = report.backup.unwrap();
+        let saved: u32 = Connection::open(&backup)
+            .unwrap()
+            .query_row("SELECT x FROM t", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(saved, 7);
+        assert_eq!(report.applied.len(), 1);
+        let _ = std::fs::remove_file(backup);
+    }
+
+    #[test]
+    fn refuses_databases_from_newer_clients() {
+        let path = TempDb::new("migrations-newer");
+        let mut conn = Connection::open(&path).unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1)
+            .unwrap();
+
+        assert!(matches!(
+            migrate(&mut conn, &path),
+            Err(MigrationError::TooNew { .. })
+        ));
+    }
+}
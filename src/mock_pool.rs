@@ -0,0 +1,385 @@
+//! A scriptable stand-in for the pool server, used by the tests.
+//!
+//! Speaks just enough HTTP/1.1 for reqwest (one request per connection) and upgrades
+//! `/v2/ws` to a websocket that plays a per-connection script of frames and failures.
+
+use base64::prelude::*;
+use futures_util::{SinkExt, StreamExt};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::{handshake::derive_accept_key, protocol::Role, Message};
+use tokio_tungstenite::WebSocketStream;
+
+use crate::protocol::{ClientMessage, ServerMessage};
+
+#[derive(Debug, Clone)]
+pub enum Step {
+    Send(ServerMessage),
+    Text(String),
+    /// Read client frames until a valid Ready arrives
+    ExpectReady,
+    /// Read client frames until a valid BestSolution arrives
+    ExpectSolution,
+    Sleep(Duration),
+    /// Drop the TCP connection without a close frame
+    Drop,
+    Close,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+impl RecordedRequest {
+    /// The pubkey and signature from a `Basic pubkey:signature` authorization header.
+    pub fn basic_auth(&self) -> Option<(Pubkey, Signature)> {
+        parse_basic_auth(self.headers.get("authorization")?)
+    }
+}
+
+#[derive(Debug)]
+pub struct WsConnection {
+    pub pubkey: Option<Pubkey>,
+    /// The Basic auth signature covered the timestamp in the query string
+    pub auth_valid: bool,
+    /// The client sent a Close frame
+    pub closed_by_client: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct MockState {
+    pub requests: Vec<RecordedRequest>,
+    pub connections: Vec<WsConnection>,
+    /// Verified client frames, tagged with the index of their connection
+    pub client_messages: Vec<(usize, ClientMessage)>,
+    pub bad_client_messages: usize,
+    timestamp: Option<u64>,
+    responses: HashMap<String, (u16, String)>,
+    failures: HashMap<String, usize>,
+    scripts: VecDeque<Vec<Step>>,
+}
+
+impl MockState {
+    pub fn requests_to(&self, path: &str) -> Vec<&RecordedRequest> {
+        self.requests.iter().filter(|r| r.path == path).collect()
+    }
+
+    pub fn solutions(&self) -> usize {
+        self.client_messages
+            .iter()
+            .filter(|(_, m)| matches!(m, ClientMessage::BestSolution { .. }))
+            .count()
+    }
+}
+
+pub struct MockPool {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    task: JoinHandle<()>,
+}
+
+impl MockPool {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let accept_state = state.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle_connection(stream, accept_state.clone()));
+            }
+        });
+        MockPool { addr, state, task }
+    }
+
+    /// Host and port to pass as the client's `--url`, used with `--use-http`.
+    pub fn url(&self) -> String {
+        self.addr.to_string()
+    }
+
+    pub fn state(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Serves `/timestamp` as this value instead of the current time.
+    pub fn set_timestamp(&self, timestamp: u64) {
+        self.state().timestamp = Some(timestamp);
+    }
+
+    pub fn respond(&self, path: &str, status: u16, body: impl Into<String>) {
+        self.state()
+            .responses
+            .insert(path.to_string(), (status, body.into()));
+    }
+
+    /// Answers the next `times` requests to `path` with a 503.
+    pub fn fail(&self, path: &str, times: usize) {
+        self.state().failures.insert(path.to_string(), times);
+    }
+
+    /// Queues the script played on the next websocket connection. Connections without a
+    /// script only record what the client sends.
+    pub fn script(&self, steps: Vec<Step>) {
+        self.state().scripts.push_back(steps);
+    }
+
+    /// Polls the state until `condition` holds or `timeout` passes.
+    pub async fn wait_for(
+        &self,
+        timeout: Duration,
+        condition: impl Fn(&MockState) -> bool,
+    ) -> bool {
+        let started = Instant::now();
+        while started.elapsed() < timeout {
+            if condition(&self.state()) {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        false
+    }
+}
+
+impl Drop for MockPool {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn parse_basic_auth(header: &str) -> Option<(Pubkey, Signature)> {
+    let decoded = BASE64_STANDARD
+        .decode(header.strip_prefix("Basic ")?)
+        .ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (pubkey, signature) = decoded.split_once(':')?;
+    Some((
+        Pubkey::from_str(pubkey).ok()?,
+        Signature::from_str(signature).ok()?,
+    ))
+}
+
+async fn read_request(stream: &mut TcpStream) -> Option<RecordedRequest> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?.to_string();
+    let target = request_line.next()?.to_string();
+    let headers: HashMap<String, String> = lines
+        .filter_map(|l| l.split_once(':'))
+        .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+        .collect();
+
+    let content_length = headers
+        .get("content-length")
+        .and_then(|l| l.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = buf[header_end..].to_vec();
+    while body.len() < content_length {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let query = query
+        .split('&')
+        .filter_map(|p| p.split_once('='))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+    Some(RecordedRequest {
+        method,
+        path: path.to_string(),
+        query,
+        headers,
+        body: String::from_utf8_lossy(&body).to_string(),
+    })
+}
+
+async fn write_response(stream: &mut TcpStream, status: u16, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nContent-Type: text/plain\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+async fn handle_connection(mut stream: TcpStream, state: Arc<Mutex<MockState>>) {
+    let Some(request) = read_request(&mut stream).await else {
+        return;
+    };
+
+    let (status, body, upgrade) = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(request.clone());
+
+        let failing = state.failures.get_mut(&request.path).filter(|n| **n > 0);
+        if let Some(remaining) = failing {
+            *remaining -= 1;
+            (503, "Service Unavailable".to_string(), false)
+        } else if request.path == "/timestamp" {
+            let timestamp = state.timestamp.unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs()
+            });
+            (200, timestamp.to_string(), false)
+        } else if request.path == "/v2/ws" {
+            (101, String::new(), true)
+        } else if let Some((status, body)) = state.responses.get(&request.path) {
+            (*status, body.clone(), false)
+        } else {
+            (404, "Not Found".to_string(), false)
+        }
+    };
+
+    if upgrade {
+        websocket(stream, request, state).await;
+    } else {
+        write_response(&mut stream, status, &body).await;
+    }
+}
+
+async fn websocket(mut stream: TcpStream, request: RecordedRequest, state: Arc<Mutex<MockState>>) {
+    let Some(key) = request.headers.get("sec-websocket-key") else {
+        write_response(&mut stream, 400, "Missing Sec-WebSocket-Key").await;
+        return;
+    };
+
+    let auth = request.basic_auth();
+    let timestamp = request
+        .query
+        .get("timestamp")
+        .and_then(|t| t.parse::<u64>().ok());
+    let auth_valid = match (auth, timestamp) {
+        (Some((pubkey, signature)), Some(timestamp)) => {
+            signature.verify(&pubkey.to_bytes(), &timestamp.to_le_bytes())
+        }
+        _ => false,
+    };
+
+    let (index, script) = {
+        let mut state = state.lock().unwrap();
+        state.connections.push(WsConnection {
+            pubkey: auth.map(|(pubkey, _)| pubkey),
+            auth_valid,
+            closed_by_client: false,
+        });
+        (
+            state.connections.len() - 1,
+            state.scripts.pop_front().unwrap_or_default(),
+        )
+    };
+
+    if !auth_valid {
+        write_response(&mut stream, 401, "Invalid authorization").await;
+        return;
+    }
+
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        derive_accept_key(key.as_bytes())
+    );
+    if stream.write_all(response.as_bytes()).await.is_err() {
+        return;
+    }
+    let mut ws = WebSocketStream::from_raw_socket(stream, Role::Server, None).await;
+
+    for step in script {
+        match step {
+            Step::Send(msg) => {
+                if ws.send(Message::Binary(msg.encode())).await.is_err() {
+                    return;
+                }
+            }
+            Step::Text(t) => {
+                if ws.send(Message::Text(t)).await.is_err() {
+                    return;
+                }
+            }
+            Step::ExpectReady => {
+                let ready = |m: &ClientMessage| matches!(m, ClientMessage::Ready { .. });
+                if !read_until(&mut ws, index, &state, ready).await {
+                    return;
+                }
+            }
+            Step::ExpectSolution => {
+                let solution = |m: &ClientMessage| matches!(m, ClientMessage::BestSolution { .. });
+                if !read_until(&mut ws, index, &state, solution).await {
+                    return;
+                }
+            }
+            Step::Sleep(duration) => tokio::time::sleep(duration).await,
+            Step::Drop => return,
+            Step::Close => {
+                let _ = ws.close(None).await;
+                return;
+            }
+        }
+    }
+
+    // Script finished, keep recording until the client goes away
+    read_until(&mut ws, index, &state, |_| false).await;
+}
+
+/// Reads and records client frames until one matches. Returns false if the socket closed.
+async fn read_until(
+    ws: &mut WebSocketStream<TcpStream>,
+    index: usize,
+    state: &Arc<Mutex<MockState>>,
+    wanted: impl Fn(&ClientMessage) -> bool,
+) -> bool {
+    while let Some(Ok(frame)) = ws.next().await {
+        let b = match frame {
+            Message::Binary(b) => b,
+            Message::Close(_) => {
+                state.lock().unwrap().connections[index].closed_by_client = true;
+                continue;
+            }
+            _ => continue,
+        };
+        let mut state = state.lock().unwrap();
+        match ClientMessage::decode(&b) {
+            Ok(msg) if msg.verify() => {
+                let done = wanted(&msg);
+                state.client_messages.push((index, msg));
+                if done {
+                    return true;
+                }
+            }
+            _ => state.bad_client_messages += 1,
+        }
+    }
+    false
+}
@@ -0,0 +1,115 @@
+//! `--output json` for the account commands, so scripts don't have to scrape text.
+//!
+//! In text mode commands print for people as they always have. In JSON mode they print
+//! nothing along the way, never prompt, and finish with exactly one document on stdout:
+//! the command's report with `"command"` and `"ok"` added, or
+//! `{"command", "ok": false, "error": {"code", "message"}}` for an `Error`.
+
+use clap::ValueEnum;
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::error::Error;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+static FORMAT: OnceCell<OutputFormat> = OnceCell::new();
+
+/// Set once a command has printed its document, so an error after it doesn't add a second.
+static EMITTED: AtomicBool = AtomicBool::new(false);
+
+pub fn init(format: OutputFormat) {
+    let _ = FORMAT.set(format);
+}
+
+pub fn json() -> bool {
+    FORMAT.get() == Some(&OutputFormat::Json)
+}
+
+/// `println!` for human-readable output; prints nothing under `--output json`.
+#[macro_export]
+macro_rules! textln {
+    ($($arg:tt)*) => {
+        if !$crate::output::json() {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Prints `report` as the command's document. Text mode prints nothing; the command
+/// has already said what happened.
+pub fn emit<T: Serialize>(command: &str, report: &T) {
+    print_document(document(command, report, true));
+}
+
+/// Like `emit` with `"ok": false`, for results worth reporting in full that the command
+/// still fails with, e.g. a claim that is cooling down.
+pub fn emit_unsuccessful<T: Serialize>(command: &str, report: &T) {
+    print_document(document(command, report, false));
+}
+
+/// Reports the error a command failed with: on stderr for people, or as the error
+/// document unless the command's own document is already out.
+pub fn report_error(command: &str, e: &Error) {
+    if !json() {
+        eprintln!("Error: {}", e);
+        return;
+    }
+    print_document(json!({
+        "command": command,
+        "ok": false,
+        "error": { "code": e.code(), "message": e.to_string() },
+    }));
+}
+
+fn print_document(doc: Value) {
+    if json() && !EMITTED.swap(true, Ordering::SeqCst) {
+        println!("{}", doc);
+    }
+}
+
+fn document<T: Serialize>(command: &str, report: &T, ok: bool) -> Value {
+    let mut doc = json!({ "command": command, "ok": ok });
+    match serde_json::to_value(report).expect("reports always serialize") {
+        Value::Object(fields) => doc.as_object_mut().unwrap().extend(fields),
+        Value::Null => {}
+        other => {
+            doc["result"] = other;
+        }
+    }
+    doc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Report {
+        wallet: &'static str,
+        staked: Option<f64>,
+    }
+
+    #[test]
+    fn documents_carry_the_command_and_outcome() {
+        let doc = document(
+            "stake-balance",
+            &Report {
+                wallet: "abc",
+                staked: None,
+            },
+            true,
+        );
+        assert_eq!(
+            doc,
+            json!({ "command": "stake-balance", "ok": true, "wallet": "abc", "staked": null })
+        );
+    }
+}
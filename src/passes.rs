@@ -0,0 +1,47 @@
+use clap::{arg, Parser};
+use spl_token::amount_to_ui_amount;
+
+use crate::database::AppDatabase;
+use crate::error::Error;
+
+#[derive(Debug, Parser)]
+pub struct PassesArgs {
+    #[arg(
+        long,
+        value_name = "COUNT",
+        default_value = "20",
+        help = "Number of recent mining passes to show"
+    )]
+    pub limit: u32,
+}
+
+pub fn passes(args: &PassesArgs) -> Result<(), Error> {
+    let app_db = AppDatabase::new()?;
+
+    for p in app_db.get_recent_passes(args.limit) {
+        let earned = match p.miner_earned {
+            Some(earned) => format!(
+                "{} ORE",
+                amount_to_ui_amount(earned, ore_api::consts::TOKEN_DECIMALS)
+            ),
+            None => "-".to_string(),
+        };
+        let overmined = match p.pass.overmined_by_ms {
+            Some(ms) => format!(", Accuracy: {:.0}ms", ms),
+            None => String::new(),
+        };
+        println!(
+            "{} Challenge: {}, Hashes: {}, Hashpower: {:.0} H/s, Best Diff: {}, Submitted Diff: {}, Pool Diff: {}, Earned: {}{}",
+            p.created_at,
+            p.pass.challenge,
+            p.pass.hashes,
+            p.pass.hashrate(),
+            p.pass.best_difficulty,
+            p.miner_difficulty.map_or("-".to_string(), |d| d.to_string()),
+            p.pool_difficulty.map_or("-".to_string(), |d| d.to_string()),
+            earned,
+            overmined,
+        );
+    }
+    Ok(())
+}
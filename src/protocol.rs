@@ -0,0 +1,445 @@
+use std::{fmt, mem::size_of, ops::Range, str::FromStr};
+
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::Signature,
+    signer::{Signer, SignerError},
+};
+
+use crate::signer::Wallet;
+
+// Message type tags, shared by both directions of the pool websocket.
+pub const START_MINING: u8 = 0;
+pub const POOL_SUBMISSION_RESULT: u8 = 1;
+pub const READY: u8 = 0;
+pub const BEST_SOLUTION: u8 = 2;
+
+const START_MINING_LEN: usize = 1 + 32 + 8 + 8 + 8;
+const POOL_SUBMISSION_RESULT_LEN: usize = 1 + 4 + 8 + 8 + 8 + 8 + 4 + 32 + 8 + 4 + 8 + 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtocolError {
+    Empty,
+    UnknownMessageType(u8),
+    Truncated {
+        message: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    #[allow(dead_code)] // only produced when decoding client frames
+    InvalidSignature,
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Empty => write!(f, "empty message"),
+            ProtocolError::UnknownMessageType(t) => write!(f, "unknown message type {}", t),
+            ProtocolError::Truncated {
+                message,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} message too short: expected at least {} bytes, got {}",
+                message, expected, actual
+            ),
+            ProtocolError::InvalidSignature => write!(f, "invalid signature"),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerMessagePoolSubmissionResult {
+    pub difficulty: u32,
+    pub total_balance: f64,
+    pub total_rewards: f64,
+    pub top_stake: f64,
+    pub multiplier: f64,
+    pub active_miners: u32,
+    pub challenge: [u8; 32],
+    pub best_nonce: u64,
+    pub miner_supplied_difficulty: u32,
+    pub miner_earned_rewards: f64,
+    pub miner_percentage: f64,
+}
+
+/// Binary frames sent by the pool server.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerMessage {
+    /// Challenge, nonce range and cutoff in seconds.
+    StartMining([u8; 32], Range<u64>, u64),
+    PoolSubmissionResult(ServerMessagePoolSubmissionResult),
+}
+
+impl ServerMessage {
+    #[allow(dead_code)] // the client only decodes server frames
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            ServerMessage::StartMining(challenge, nonce_range, cutoff) => {
+                let mut bin_data = Vec::with_capacity(START_MINING_LEN);
+                bin_data.push(START_MINING);
+                bin_data.extend_from_slice(challenge);
+                bin_data.extend_from_slice(&cutoff.to_le_bytes());
+                bin_data.extend_from_slice(&nonce_range.start.to_le_bytes());
+                bin_data.extend_from_slice(&nonce_range.end.to_le_bytes());
+                bin_data
+            }
+            ServerMessage::PoolSubmissionResult(data) => {
+                let mut bin_data = Vec::with_capacity(POOL_SUBMISSION_RESULT_LEN);
+                bin_data.push(POOL_SUBMISSION_RESULT);
+                bin_data.extend_from_slice(&data.difficulty.to_le_bytes());
+                bin_data.extend_from_slice(&data.total_balance.to_le_bytes());
+                bin_data.extend_from_slice(&data.total_rewards.to_le_bytes());
+                bin_data.extend_from_slice(&data.top_stake.to_le_bytes());
+                bin_data.extend_from_slice(&data.multiplier.to_le_bytes());
+                bin_data.extend_from_slice(&data.active_miners.to_le_bytes());
+                bin_data.extend_from_slice(&data.challenge);
+                bin_data.extend_from_slice(&data.best_nonce.to_le_bytes());
+                bin_data.extend_from_slice(&data.miner_supplied_difficulty.to_le_bytes());
+                bin_data.extend_from_slice(&data.miner_earned_rewards.to_le_bytes());
+                bin_data.extend_from_slice(&data.miner_percentage.to_le_bytes());
+                bin_data
+            }
+        }
+    }
+
+    pub fn decode(b: &[u8]) -> Result<Self, ProtocolError> {
+        let message_type = *b.first().ok_or(ProtocolError::Empty)?;
+        match message_type {
+            START_MINING => {
+                let mut r = Reader::new(b, "StartMining", START_MINING_LEN)?;
+                let challenge = r.array::<32>();
+                let cutoff = r.u64();
+                let nonce_start = r.u64();
+                let nonce_end = r.u64();
+                Ok(ServerMessage::StartMining(
+                    challenge,
+                    nonce_start..nonce_end,
+                    cutoff,
+                ))
+            }
+            POOL_SUBMISSION_RESULT => {
+                let mut r = Reader::new(b, "PoolSubmissionResult", POOL_SUBMISSION_RESULT_LEN)?;
+                Ok(ServerMessage::PoolSubmissionResult(
+                    ServerMessagePoolSubmissionResult {
+                        difficulty: r.u32(),
+                        total_balance: r.f64(),
+                        total_rewards: r.f64(),
+                        top_stake: r.f64(),
+                        multiplier: r.f64(),
+                        active_miners: r.u32(),
+                        challenge: r.array::<32>(),
+                        best_nonce: r.u64(),
+                        miner_supplied_difficulty: r.u32(),
+                        miner_earned_rewards: r.f64(),
+                        miner_percentage: r.f64(),
+                    },
+                ))
+            }
+            other => Err(ProtocolError::UnknownMessageType(other)),
+        }
+    }
+}
+
+/// Binary frames sent by the miner.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientMessage {
+    Ready {
+        pubkey: Pubkey,
+        timestamp: u64,
+        signature: Signature,
+    },
+    BestSolution {
+        digest: [u8; 16],
+        nonce: u64,
+        pubkey: Pubkey,
+        signature: Signature,
+    },
+}
+
+impl ClientMessage {
+    /// Signs the timestamp, announcing the miner is ready for the next challenge.
+    pub async fn ready(wallet: &Wallet, timestamp: u64) -> Result<Self, SignerError> {
+        Ok(ClientMessage::Ready {
+            pubkey: wallet.try_pubkey()?,
+            timestamp,
+            signature: wallet.sign(&timestamp.to_le_bytes()).await?,
+        })
+    }
+
+    /// Signs the digest and nonce of a solution.
+    pub async fn best_solution(
+        wallet: &Wallet,
+        digest: [u8; 16],
+        nonce: u64,
+    ) -> Result<Self, SignerError> {
+        Ok(ClientMessage::BestSolution {
+            digest,
+            nonce,
+            pubkey: wallet.try_pubkey()?,
+            signature: wallet.sign(&Self::solution_message(&digest, nonce)).await?,
+        })
+    }
+
+    /// The bytes a BestSolution signature covers.
+    pub fn solution_message(digest: &[u8; 16], nonce: u64) -> [u8; 24] {
+        let mut hash_nonce_message = [0; 24];
+        hash_nonce_message[0..16].copy_from_slice(digest);
+        hash_nonce_message[16..24].copy_from_slice(&nonce.to_le_bytes());
+        hash_nonce_message
+    }
+
+    /// Checks the signature against the embedded pubkey.
+    #[allow(dead_code)] // the client only encodes its own frames
+    pub fn verify(&self) -> bool {
+        match self {
+            ClientMessage::Ready {
+                pubkey,
+                timestamp,
+                signature,
+            } => signature.verify(&pubkey.to_bytes(), &timestamp.to_le_bytes()),
+            ClientMessage::BestSolution {
+                digest,
+                nonce,
+                pubkey,
+                signature,
+            } => signature.verify(&pubkey.to_bytes(), &Self::solution_message(digest, *nonce)),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        // Signatures are sent as their base58 string, not the raw 64 bytes.
+        match self {
+            ClientMessage::Ready {
+                pubkey,
+                timestamp,
+                signature,
+            } => {
+                let sig = signature.to_string();
+                let mut bin_data = Vec::with_capacity(1 + 32 + 8 + sig.len());
+                bin_data.push(READY);
+                bin_data.extend_from_slice(&pubkey.to_bytes());
+                bin_data.extend_from_slice(&timestamp.to_le_bytes());
+                bin_data.extend_from_slice(sig.as_bytes());
+                bin_data
+            }
+            ClientMessage::BestSolution {
+                digest,
+                nonce,
+                pubkey,
+                signature,
+            } => {
+                let sig = signature.to_string();
+                let mut bin_data = Vec::with_capacity(1 + 16 + 8 + 32 + sig.len());
+                bin_data.push(BEST_SOLUTION);
+                bin_data.extend_from_slice(digest);
+                bin_data.extend_from_slice(&nonce.to_le_bytes());
+                bin_data.extend_from_slice(&pubkey.to_bytes());
+                bin_data.extend_from_slice(sig.as_bytes());
+                bin_data
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn decode(b: &[u8]) -> Result<Self, ProtocolError> {
+        let message_type = *b.first().ok_or(ProtocolError::Empty)?;
+        match message_type {
+            READY => {
+                let mut r = Reader::new(b, "Ready", 1 + 32 + 8 + 1)?;
+                let pubkey = Pubkey::new_from_array(r.array::<32>());
+                let timestamp = r.u64();
+                let signature = parse_signature(r.rest())?;
+                Ok(ClientMessage::Ready {
+                    pubkey,
+                    timestamp,
+                    signature,
+                })
+            }
+            BEST_SOLUTION => {
+                let mut r = Reader::new(b, "BestSolution", 1 + 16 + 8 + 32 + 1)?;
+                let digest = r.array::<16>();
+                let nonce = r.u64();
+                let pubkey = Pubkey::new_from_array(r.array::<32>());
+                let signature = parse_signature(r.rest())?;
+                Ok(ClientMessage::BestSolution {
+                    digest,
+                    nonce,
+                    pubkey,
+                    signature,
+                })
+            }
+            other => Err(ProtocolError::UnknownMessageType(other)),
+        }
+    }
+}
+
+#[allow(dead_code)]
+fn parse_signature(b: &[u8]) -> Result<Signature, ProtocolError> {
+    let s = std::str::from_utf8(b).map_err(|_| ProtocolError::InvalidSignature)?;
+    Signature::from_str(s).map_err(|_| ProtocolError::InvalidSignature)
+}
+
+/// Little-endian cursor over a frame whose minimum length has already been checked.
+struct Reader<'a> {
+    b: &'a [u8],
+    index: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(b: &'a [u8], message: &'static str, min_len: usize) -> Result<Self, ProtocolError> {
+        if b.len() < min_len {
+            return Err(ProtocolError::Truncated {
+                message,
+                expected: min_len,
+                actual: b.len(),
+            });
+        }
+        // Skip the message type byte
+        Ok(Reader { b, index: 1 })
+    }
+
+    fn array<const N: usize>(&mut self) -> [u8; N] {
+        let mut data_bytes = [0u8; N];
+        data_bytes.copy_from_slice(&self.b[self.index..self.index + N]);
+        self.index += N;
+        data_bytes
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.array::<{ size_of::<u32>() }>())
+    }
+
+    fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.array::<{ size_of::<u64>() }>())
+    }
+
+    fn f64(&mut self) -> f64 {
+        f64::from_le_bytes(self.array::<{ size_of::<f64>() }>())
+    }
+
+    #[allow(dead_code)]
+    fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.b[self.index..];
+        self.index = self.b.len();
+        rest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Keypair;
+
+    fn pool_submission_result() -> ServerMessagePoolSubmissionResult {
+        ServerMessagePoolSubmissionResult {
+            difficulty: 21,
+            total_balance: 12.5,
+            total_rewards: 0.000_123,
+            top_stake: 1_000.25,
+            multiplier: 1.75,
+            active_miners: 42,
+            challenge: [7u8; 32],
+            best_nonce: 123_456_789,
+            miner_supplied_difficulty: 18,
+            miner_earned_rewards: 0.000_004,
+            miner_percentage: 3.25,
+        }
+    }
+
+    #[test]
+    fn start_mining_round_trip() {
+        let msg = ServerMessage::StartMining([3u8; 32], 1_000..2_000_000, 55);
+        let bytes = msg.encode();
+        assert_eq!(bytes.len(), START_MINING_LEN);
+        assert_eq!(ServerMessage::decode(&bytes), Ok(msg));
+    }
+
+    #[test]
+    fn pool_submission_result_round_trip() {
+        let msg = ServerMessage::PoolSubmissionResult(pool_submission_result());
+        let bytes = msg.encode();
+        assert_eq!(bytes.len(), POOL_SUBMISSION_RESULT_LEN);
+        assert_eq!(ServerMessage::decode(&bytes), Ok(msg));
+    }
+
+    #[tokio::test]
+    async fn ready_round_trip() {
+        let key = Wallet::from(Keypair::new());
+        let msg = ClientMessage::ready(&key, 1_700_000_000).await.unwrap();
+        let decoded = ClientMessage::decode(&msg.encode()).unwrap();
+        assert!(decoded.verify());
+        assert_eq!(decoded, msg);
+    }
+
+    #[tokio::test]
+    async fn best_solution_round_trip() {
+        let key = Wallet::from(Keypair::new());
+        let msg = ClientMessage::best_solution(&key, [9u8; 16], 77)
+            .await
+            .unwrap();
+        let decoded = ClientMessage::decode(&msg.encode()).unwrap();
+        assert!(decoded.verify());
+        assert_eq!(decoded, msg);
+    }
+
+    #[tokio::test]
+    async fn tampered_solution_fails_verification() {
+        let key = Wallet::from(Keypair::new());
+        let mut bytes = ClientMessage::best_solution(&key, [9u8; 16], 77)
+            .await
+            .unwrap()
+            .encode();
+        // flip a bit in the nonce
+        bytes[17] ^= 1;
+        assert!(!ClientMessage::decode(&bytes).unwrap().verify());
+    }
+
+    #[test]
+    fn short_frames_are_rejected() {
+        assert_eq!(ServerMessage::decode(&[]), Err(ProtocolError::Empty));
+
+        let start = ServerMessage::StartMining([1u8; 32], 0..10, 5).encode();
+        for len in 1..start.len() {
+            assert!(matches!(
+                ServerMessage::decode(&start[..len]),
+                Err(ProtocolError::Truncated { .. })
+            ));
+        }
+
+        let result = ServerMessage::PoolSubmissionResult(pool_submission_result()).encode();
+        for len in 1..result.len() {
+            assert!(matches!(
+                ServerMessage::decode(&result[..len]),
+                Err(ProtocolError::Truncated { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn unknown_message_type_is_rejected() {
+        assert_eq!(
+            ServerMessage::decode(&[9, 0, 0]),
+            Err(ProtocolError::UnknownMessageType(9))
+        );
+        assert_eq!(
+            ClientMessage::decode(&[5]),
+            Err(ProtocolError::UnknownMessageType(5))
+        );
+    }
+
+    #[tokio::test]
+    async fn garbage_signature_is_rejected() {
+        let key = Wallet::from(Keypair::new());
+        let mut bytes = ClientMessage::ready(&key, 1).await.unwrap().encode();
+        bytes.truncate(1 + 32 + 8);
+        bytes.extend_from_slice(b"not-a-signature");
+        assert_eq!(
+            ClientMessage::decode(&bytes),
+            Err(ProtocolError::InvalidSignature)
+        );
+    }
+}
@@ -0,0 +1,1498 @@
+use base64::prelude::*;
+use colored::*;
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use http::header::{
+    AUTHORIZATION, CONNECTION, HOST, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_VERSION, UPGRADE,
+};
+use http::Method;
+use log::{error, info, warn};
+use solana_sdk::signer::Signer;
+use spl_token::amount_to_ui_amount;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::{
+    ops::Range,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::Mutex;
+use tokio::time::timeout;
+use tokio_tungstenite::{
+    connect_async_with_config,
+    tungstenite::{
+        handshake::client::{generate_key, Request},
+        protocol::{frame::coding::CloseCode, CloseFrame},
+        Message,
+    },
+    MaybeTlsStream, WebSocketStream,
+};
+
+use crate::backend::HashStats;
+use crate::database::{
+    AppDatabase, EarningsFilter, MiningPassRecord, PoolSubmissionResult, DATABASE_PATH,
+};
+use crate::error::Error;
+use crate::failover::PoolList;
+use crate::http::{Backoff, EndpointClass};
+use crate::logging;
+use crate::metrics::{self, METRICS};
+use crate::protocol::{ClientMessage, ServerMessage, ServerMessagePoolSubmissionResult};
+use crate::shutdown::{self, Shutdown};
+use crate::signer::Wallet;
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+type WsSender = Arc<Mutex<SplitSink<WsStream, Message>>>;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ThreadSubmission {
+    pub nonce: u64,
+    pub difficulty: u32,
+    pub d: [u8; 16], // digest
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum MessageSubmissionSystem {
+    /// The challenge of the pass about to start, used to verify its submissions
+    Challenge([u8; 32]),
+    Submission(ThreadSubmission),
+    Reset,
+    Finish,
+}
+
+/// Hands solutions found by the hashing threads to the submission system.
+#[derive(Clone)]
+pub struct SolutionSender(UnboundedSender<MessageSubmissionSystem>);
+
+impl SolutionSender {
+    /// Returns false once the connection this pass belongs to has gone away.
+    pub fn submit(&self, submission: ThreadSubmission) -> bool {
+        self.0
+            .send(MessageSubmissionSystem::Submission(submission))
+            .is_ok()
+    }
+}
+
+/// One StartMining message from the pool.
+pub struct MiningPass {
+    pub challenge: [u8; 32],
+    pub nonce_range: Range<u64>,
+    pub cutoff: u64,
+    pub submissions: SolutionSender,
+    pub running: Arc<AtomicBool>,
+}
+
+impl MiningPass {
+    /// The pool cutoff less the user's buffer, capped to 55s.
+    pub fn cutoff_with_buffer(&self, buffer: u32) -> u64 {
+        let cutoff = self.cutoff.saturating_sub(buffer as u64);
+        if cutoff > 60 {
+            55
+        } else {
+            cutoff
+        }
+    }
+}
+
+/// What a strategy measured while hashing one pass, stored in the `mining_passes` table.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PassReport {
+    pub hashes: u64,
+    pub hash_time: Duration,
+    pub best_difficulty: u32,
+    /// How far past its target end time hashing ran, for strategies that aim for one
+    pub overmined_by_ms: Option<f64>,
+}
+
+impl From<&HashStats> for PassReport {
+    fn from(stats: &HashStats) -> Self {
+        PassReport {
+            hashes: stats.hashes,
+            hash_time: stats.elapsed,
+            best_difficulty: stats.best.difficulty,
+            overmined_by_ms: None,
+        }
+    }
+}
+
+enum DatabaseMessage {
+    Submission(PoolSubmissionResult),
+    Pass(MiningPassRecord),
+}
+
+#[derive(Debug)]
+pub enum SessionEvent {
+    ServerTimestamp(u64),
+    TimestampFailed(String),
+    Connecting,
+    Connected {
+        elapsed: Duration,
+    },
+    ConnectFailed(String),
+    ServerText(String),
+    PoolSubmissionResult(ServerMessagePoolSubmissionResult),
+    TodaysEarnings(u64),
+    Disconnected(String),
+    Reconnecting,
+    /// The active pool kept failing and the session moved to the next one
+    FailingOver {
+        from: String,
+        to: String,
+    },
+    /// The primary pool answered again while mining on a backup
+    ReturningToPrimary(String),
+    /// A submission failed local verification and was not sent.
+    /// `actual_difficulty` is None when the digest is not a valid solution at all.
+    InvalidSolution {
+        nonce: u64,
+        claimed_difficulty: u32,
+        actual_difficulty: Option<u32>,
+        total: u64,
+    },
+    /// A signal asked the session to stop
+    ShuttingDown {
+        signal: &'static str,
+        finish_pass: bool,
+    },
+    /// The websocket is closed and pending database writes are flushed
+    Stopped,
+}
+
+/// A way of mining the passes handed out by a `PoolSession`.
+pub trait MiningStrategy: Send + Sync + 'static {
+    /// Hashes one pass, submitting improving solutions through `pass.submissions`.
+    /// Runs on a blocking thread and returns once the pass is over.
+    fn run_pass(&self, pass: MiningPass) -> PassReport;
+
+    fn on_event(&self, event: &SessionEvent) {
+        print_event(event);
+    }
+}
+
+pub fn print_event(event: &SessionEvent) {
+    match event {
+        SessionEvent::ServerTimestamp(timestamp) => {
+            info!("Server Timestamp: {}", timestamp);
+        }
+        SessionEvent::TimestampFailed(e) => {
+            warn!("{}", e);
+        }
+        SessionEvent::Connecting => {
+            info!("Connecting to server...");
+        }
+        SessionEvent::Connected { elapsed } => {
+            info!(
+                "{}{}{}",
+                "Server: ".dimmed(),
+                "Connected to network!".blue(),
+                format!(" [{}ms]", elapsed.as_millis()).dimmed(),
+            );
+        }
+        SessionEvent::ConnectFailed(e) => {
+            warn!("Error: {}", e);
+        }
+        SessionEvent::ServerText(t) => {
+            info!("{}", t);
+        }
+        SessionEvent::PoolSubmissionResult(data) => {
+            // One record per line, with the separators only on a terminal
+            let separator = || {
+                if logging::is_interactive() {
+                    println!("----------------------");
+                }
+            };
+            if logging::is_interactive() {
+                println!("\n");
+            }
+            info!("Challenge: {}", BASE64_STANDARD.encode(data.challenge));
+            info!("Pool Submitted Difficulty: {}", data.difficulty);
+            info!("Pool Earned:  {:.11} ORE", data.total_rewards);
+            info!("Pool Balance: {:.11} ORE", data.total_balance);
+            info!("Pool Boosts Multiplier: {:.2}x", data.multiplier);
+            separator();
+            info!("Active Miners: {}", data.active_miners);
+            separator();
+            info!(
+                "Miner Submitted Difficulty: {}",
+                data.miner_supplied_difficulty
+            );
+            info!("Miner Earned: {:.11} ORE", data.miner_earned_rewards);
+            info!("{:.4}% of total pool reward", data.miner_percentage);
+            if logging::is_interactive() {
+                println!();
+            }
+        }
+        SessionEvent::TodaysEarnings(earned) => {
+            info!(
+                "Todays Earnings: {} ORE",
+                amount_to_ui_amount(*earned, ore_api::consts::TOKEN_DECIMALS)
+            );
+            if logging::is_interactive() {
+                println!();
+            }
+        }
+        SessionEvent::Disconnected(reason) => {
+            warn!("{}", reason);
+        }
+        SessionEvent::Reconnecting => {
+            info!("Channels cleaned up, reconnecting...");
+            if logging::is_interactive() {
+                println!();
+            }
+        }
+        SessionEvent::FailingOver { from, to } => {
+            warn!(
+                "{}",
+                format!("Pool {} keeps failing, switching to {}", from, to).yellow()
+            );
+        }
+        SessionEvent::ReturningToPrimary(url) => {
+            info!(
+                "{}",
+                format!("Primary pool {} is back, switching to it", url).green()
+            );
+        }
+        SessionEvent::InvalidSolution {
+            nonce,
+            claimed_difficulty,
+            actual_difficulty,
+            total,
+        } => {
+            let actual = match actual_difficulty {
+                Some(difficulty) => format!("actual diff {}", difficulty),
+                None => "invalid digest".to_string(),
+            };
+            warn!(
+                "{}",
+                format!(
+                    "Dropped solution for nonce {}: claimed diff {}, {} ({} dropped so far)",
+                    nonce, claimed_difficulty, actual, total
+                )
+                .red()
+            );
+        }
+        SessionEvent::ShuttingDown {
+            signal,
+            finish_pass,
+        } => {
+            let action = if *finish_pass {
+                "finishing the current pass first"
+            } else {
+                "stopping the current pass"
+            };
+            warn!(
+                "{}",
+                format!(
+                    "Received {}, shutting down ({}). Send it again to exit now.",
+                    signal, action
+                )
+                .yellow()
+            );
+        }
+        SessionEvent::Stopped => {
+            info!("Connection closed and results saved, exiting.");
+        }
+    }
+}
+
+/// Consecutive timestamp or handshake failures before trying the next pool
+const FAILOVER_AFTER: u32 = 3;
+const TIMESTAMP_TIMEOUT: Duration = Duration::from_secs(2);
+/// Reconnect delays back off from `RETRY_BASE` up to `RETRY_MAX`, with jitter
+const RETRY_BASE: Duration = Duration::from_secs(3);
+const RETRY_MAX: Duration = Duration::from_secs(60);
+/// How long to wait for the pool to answer our Close frame
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long pending database writes get to flush on shutdown
+const DB_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How a websocket connection ended
+#[derive(Debug, PartialEq)]
+enum ConnectionEnd {
+    Closed,
+    /// Nothing at all arrived within the receive timeout
+    TimedOut,
+    /// No StartMining arrived within the watchdog window
+    Stalled,
+    /// Left a backup because the primary answered again
+    PrimaryRecovered,
+    /// Closed by us after a shutdown request
+    Shutdown,
+}
+
+/// Owns the connection to the pool: timestamp auth, websocket handshake, Ready messages,
+/// solution submission, reconnecting and failing over between pools. Passes are handed
+/// to a `MiningStrategy`.
+pub struct PoolSession {
+    pools: std::sync::Mutex<PoolList>,
+    unsecure: bool,
+    key: Arc<Wallet>,
+    running: Arc<AtomicBool>,
+    receive_timeout: Duration,
+    start_mining_timeout: Duration,
+    /// Delay between failed timestamp or connect attempts
+    retry: std::sync::Mutex<Backoff>,
+    /// How often to probe the primary while mining on a backup
+    primary_check: Duration,
+    db_path: PathBuf,
+    verify_solutions: bool,
+    invalid_solutions: Arc<AtomicU64>,
+    metrics_addr: Option<SocketAddr>,
+    shutdown: Shutdown,
+    /// Install SIGINT and SIGTERM handlers while running
+    handle_signals: bool,
+}
+
+impl PoolSession {
+    pub fn new(url: String, unsecure: bool, key: Wallet) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+        PoolSession {
+            pools: std::sync::Mutex::new(PoolList::new(vec![url], FAILOVER_AFTER)),
+            unsecure,
+            key: Arc::new(key),
+            running: running.clone(),
+            receive_timeout: Duration::from_secs(45),
+            start_mining_timeout: Duration::from_secs(120),
+            retry: std::sync::Mutex::new(Backoff::new(RETRY_BASE, RETRY_MAX)),
+            primary_check: Duration::from_secs(60),
+            db_path: PathBuf::from(DATABASE_PATH),
+            verify_solutions: false,
+            invalid_solutions: Arc::new(AtomicU64::new(0)),
+            metrics_addr: None,
+            shutdown: Shutdown::new(running, false),
+            // Tests request shutdowns directly rather than taking over the process' signals
+            handle_signals: !cfg!(test),
+        }
+    }
+
+    /// Pools to fall back to, in order, when the primary keeps failing.
+    pub fn failover(mut self, urls: Vec<String>) -> Self {
+        let pools = self.pools.get_mut().unwrap();
+        let mut all = vec![pools.primary().url.clone()];
+        all.extend(urls);
+        *pools = PoolList::new(all, FAILOVER_AFTER);
+        self
+    }
+
+    /// Serve Prometheus metrics for this session on `addr`.
+    pub fn metrics_addr(mut self, addr: Option<SocketAddr>) -> Self {
+        self.metrics_addr = addr;
+        self
+    }
+
+    /// Re-derive every solution with drillx before submitting it, dropping any whose
+    /// digest or difficulty doesn't check out.
+    pub fn verify_solutions(mut self, verify: bool) -> Self {
+        self.verify_solutions = verify;
+        self
+    }
+
+    /// On SIGINT or SIGTERM, let the pass in flight run to its cutoff and submit before
+    /// closing the connection, instead of stopping the hashing threads straight away.
+    pub fn finish_pass_on_shutdown(mut self, finish_pass: bool) -> Self {
+        self.shutdown = Shutdown::new(self.running.clone(), finish_pass);
+        self
+    }
+
+    #[cfg(test)]
+    pub fn shutdown(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    #[cfg(test)]
+    pub fn with_timeouts(
+        mut self,
+        receive: Duration,
+        start_mining: Duration,
+        retry: Duration,
+    ) -> Self {
+        self.receive_timeout = receive;
+        self.start_mining_timeout = start_mining;
+        self.retry = std::sync::Mutex::new(Backoff::new(retry, retry * 12));
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_primary_check(mut self, interval: Duration) -> Self {
+        self.primary_check = interval;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_database(mut self, db_path: PathBuf) -> Self {
+        self.db_path = db_path;
+        self
+    }
+
+    /// Today's earnings only count this wallet, and this pool unless failing over.
+    fn earnings_filter(&self) -> EarningsFilter {
+        let pools = self.pools.lock().unwrap();
+        EarningsFilter {
+            pubkey: Some(self.key.pubkey().to_string()),
+            pool: (pools.len() == 1).then(|| pools.primary().url.clone()),
+            ..Default::default()
+        }
+    }
+
+    fn active_url(&self) -> String {
+        self.pools.lock().unwrap().active().url.clone()
+    }
+
+    fn record_failure<S: MiningStrategy>(&self, strategy: &S) {
+        let mut pools = self.pools.lock().unwrap();
+        let from = pools.active().url.clone();
+        if let Some(to) = pools.record_failure().map(str::to_string) {
+            drop(pools);
+            // Give the next pool a quick first try
+            self.retry.lock().unwrap().reset();
+            METRICS.failovers.inc();
+            strategy.on_event(&SessionEvent::FailingOver { from, to });
+        }
+    }
+
+    /// Resolves once the primary answers `/timestamp`, never while mining on it.
+    async fn primary_recovered(&self) {
+        let (on_primary, primary) = {
+            let pools = self.pools.lock().unwrap();
+            (pools.on_primary(), pools.primary().url.clone())
+        };
+        if on_primary {
+            return std::future::pending().await;
+        }
+        loop {
+            tokio::time::sleep(self.primary_check).await;
+            if self.fetch_timestamp(&primary).await.is_ok() {
+                return;
+            }
+        }
+    }
+
+    pub async fn run<S: MiningStrategy>(&self, strategy: S) -> Result<(), Error> {
+        let strategy = Arc::new(strategy);
+
+        if let Some(addr) = self.metrics_addr {
+            let listener = metrics::bind(addr).await.map_err(|e| {
+                Error::Other(format!("Failed to start metrics server on {}: {}", addr, e))
+            })?;
+            tokio::spawn(metrics::serve(listener));
+        }
+
+        let signals = self
+            .handle_signals
+            .then(|| tokio::spawn(watch_signals(self.shutdown.clone(), strategy.clone())));
+
+        let (db_sender, db_receiver) = tokio::sync::mpsc::unbounded_channel::<DatabaseMessage>();
+        let database = tokio::spawn(database_system(
+            self.db_path.clone(),
+            self.earnings_filter(),
+            db_receiver,
+            strategy.clone(),
+        ));
+
+        loop {
+            let connection_started = Instant::now();
+
+            if !self.running.load(Ordering::SeqCst) || self.shutdown.is_requested() {
+                break;
+            }
+
+            let url = self.active_url();
+            let timestamp = match self.fetch_timestamp(&url).await {
+                Ok(ts) => ts,
+                Err(e) => {
+                    strategy.on_event(&SessionEvent::TimestampFailed(e));
+                    self.record_failure(&*strategy);
+                    self.wait_to_retry().await;
+                    continue;
+                }
+            };
+            strategy.on_event(&SessionEvent::ServerTimestamp(timestamp));
+
+            let request = match self.websocket_request(&url, timestamp).await {
+                Ok(request) => request,
+                Err(e) => {
+                    strategy.on_event(&SessionEvent::ConnectFailed(e));
+                    self.record_failure(&*strategy);
+                    self.wait_to_retry().await;
+                    continue;
+                }
+            };
+            strategy.on_event(&SessionEvent::Connecting);
+
+            match connect_async_with_config(request, None, true).await {
+                Ok((ws_stream, _)) => {
+                    strategy.on_event(&SessionEvent::Connected {
+                        elapsed: connection_started.elapsed(),
+                    });
+                    let end = self
+                        .run_connection(ws_stream, &url, strategy.clone(), db_sender.clone())
+                        .await;
+                    match end {
+                        ConnectionEnd::Shutdown => break,
+                        ConnectionEnd::Closed => {}
+                        // One quiet spell counts as a failure, failing over only if it repeats
+                        ConnectionEnd::TimedOut => self.record_failure(&*strategy),
+                        ConnectionEnd::Stalled => {
+                            let to = self.pools.lock().unwrap().fail_over().map(str::to_string);
+                            if let Some(to) = to {
+                                METRICS.failovers.inc();
+                                strategy.on_event(&SessionEvent::FailingOver { from: url, to });
+                            }
+                        }
+                        ConnectionEnd::PrimaryRecovered => {
+                            let mut pools = self.pools.lock().unwrap();
+                            pools.return_to_primary();
+                            let primary = pools.primary().url.clone();
+                            drop(pools);
+                            strategy.on_event(&SessionEvent::ReturningToPrimary(primary));
+                        }
+                    }
+                    METRICS.reconnects.inc();
+                    strategy.on_event(&SessionEvent::Reconnecting);
+                }
+                Err(e) => {
+                    let e = match e {
+                        tokio_tungstenite::tungstenite::Error::Http(e) => {
+                            if let Some(body) = e.body() {
+                                format!("{:?}", String::from_utf8_lossy(body))
+                            } else {
+                                format!("Http Error: {:?}", e)
+                            }
+                        }
+                        _ => format!("{:?}", e),
+                    };
+                    METRICS.connect_failures.inc();
+                    strategy.on_event(&SessionEvent::ConnectFailed(e));
+                    self.record_failure(&*strategy);
+                    self.wait_to_retry().await;
+                }
+            }
+        }
+
+        // The database system stops once every pass has dropped its sender
+        drop(db_sender);
+        let _ = timeout(DB_FLUSH_TIMEOUT, database).await;
+        strategy.on_event(&SessionEvent::Stopped);
+        if let Some(signals) = signals {
+            signals.abort();
+        }
+        Ok(())
+    }
+
+    fn http_prefix(&self) -> &'static str {
+        if self.unsecure {
+            "http"
+        } else {
+            "https"
+        }
+    }
+
+    async fn wait_to_retry(&self) {
+        let delay = self.retry.lock().unwrap().next_delay();
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = self.shutdown.requested() => {}
+        }
+    }
+
+    /// One attempt with a short timeout; the session does its own retrying and failover.
+    async fn fetch_timestamp(&self, url: &str) -> Result<u64, String> {
+        let client = crate::http::client();
+        let url = format!("{}://{}/timestamp", self.http_prefix(), url);
+        let request = client.get(url.as_str()).class(EndpointClass::Auth);
+        match client
+            .send_once(&request, TIMESTAMP_TIMEOUT)
+            .await
+            .and_then(|res| res.text(&url))
+        {
+            Ok(ts) => ts.parse::<u64>().map_err(|_| {
+                "Server response body for /timestamp failed to parse, contact admin.".to_string()
+            }),
+            Err(e) => Err(format!("Failed to get timestamp from server: {}", e)),
+        }
+    }
+
+    async fn websocket_request(&self, url: &str, timestamp: u64) -> Result<Request, String> {
+        let ws_prefix = if self.unsecure { "ws" } else { "wss" };
+        let ws_url_str = format!("{}://{}/v2/ws?timestamp={}", ws_prefix, url, timestamp);
+        let url = url::Url::parse(&ws_url_str)
+            .map_err(|e| format!("Invalid server url {}: {}", ws_url_str, e))?;
+        let host = url
+            .host_str()
+            .ok_or_else(|| format!("Invalid host in server url {}", ws_url_str))?;
+
+        let sig = self
+            .key
+            .sign(&timestamp.to_le_bytes())
+            .await
+            .map_err(|e| format!("Failed to sign in to the pool: {}", e))?;
+        let auth = BASE64_STANDARD.encode(format!("{}:{}", self.key.pubkey(), sig));
+
+        Request::builder()
+            .method(Method::GET)
+            .uri(url.to_string())
+            .header(UPGRADE, "websocket")
+            .header(CONNECTION, "Upgrade")
+            .header(SEC_WEBSOCKET_KEY, generate_key())
+            .header(HOST, host)
+            .header(SEC_WEBSOCKET_VERSION, "13")
+            .header(AUTHORIZATION, format!("Basic {}", auth))
+            .body(())
+            .map_err(|e| format!("Failed to build websocket request: {}", e))
+    }
+
+    async fn run_connection<S: MiningStrategy>(
+        &self,
+        ws_stream: WsStream,
+        url: &str,
+        strategy: Arc<S>,
+        db_sender: UnboundedSender<DatabaseMessage>,
+    ) -> ConnectionEnd {
+        let (sender, receiver) = ws_stream.split();
+        let sender = Arc::new(Mutex::new(sender));
+        let (message_sender, mut message_receiver) =
+            tokio::sync::mpsc::unbounded_channel::<ServerMessage>();
+        let (submission_sender, submission_receiver) =
+            tokio::sync::mpsc::unbounded_channel::<MessageSubmissionSystem>();
+
+        let verifier = self.verify_solutions.then(|| SolutionVerifier {
+            invalid_solutions: self.invalid_solutions.clone(),
+            strategy: strategy.clone(),
+        });
+        let submissions = tokio::spawn(submission_system(
+            self.key.clone(),
+            submission_receiver,
+            sender.clone(),
+            verifier,
+        ));
+
+        let mut receiver_thread = tokio::spawn(receiver_system(
+            receiver,
+            message_sender,
+            submission_sender.clone(),
+            strategy.clone(),
+            self.receive_timeout,
+            self.start_mining_timeout,
+        ));
+
+        if send_ready(&self.key, &sender).await.is_err() {
+            strategy.on_event(&SessionEvent::Disconnected(
+                "Failed to send Ready message.".to_string(),
+            ));
+        }
+
+        let primary_recovered = self.primary_recovered();
+        tokio::pin!(primary_recovered);
+        let mut recovered = false;
+        let mut shutting_down = false;
+        let mut current_pass = None;
+        loop {
+            let msg = tokio::select! {
+                msg = message_receiver.recv() => match msg {
+                    Some(msg) => msg,
+                    None => break,
+                },
+                _ = &mut primary_recovered => {
+                    recovered = true;
+                    break;
+                }
+                _ = self.shutdown.requested() => {
+                    shutting_down = true;
+                    break;
+                }
+            };
+            match msg {
+                ServerMessage::StartMining(challenge, nonce_range, cutoff) => {
+                    self.pools.lock().unwrap().record_success();
+                    self.retry.lock().unwrap().reset();
+                    if !self.running.load(Ordering::SeqCst) {
+                        continue;
+                    }
+                    let pass = MiningPass {
+                        challenge,
+                        nonce_range,
+                        cutoff,
+                        submissions: SolutionSender(submission_sender.clone()),
+                        running: self.running.clone(),
+                    };
+                    // Queued ahead of the pass so its submissions are checked against it
+                    let _ = submission_sender.send(MessageSubmissionSystem::Challenge(challenge));
+                    let strategy = strategy.clone();
+                    let key = self.key.clone();
+                    let sender = sender.clone();
+                    let submission_sender = submission_sender.clone();
+                    let db_sender = db_sender.clone();
+                    let pubkey = self.key.pubkey().to_string();
+                    let pool = url.to_string();
+                    let shutdown = self.shutdown.clone();
+                    current_pass = Some(tokio::spawn(async move {
+                        let nonce_range = pass.nonce_range.clone();
+                        let report =
+                            tokio::task::spawn_blocking(move || strategy.run_pass(pass)).await;
+                        if let Ok(report) = report {
+                            METRICS.record_pass(&report);
+                            let _ = db_sender.send(DatabaseMessage::Pass(MiningPassRecord {
+                                challenge: BASE64_STANDARD.encode(challenge),
+                                pubkey,
+                                pool,
+                                nonce_start: nonce_range.start,
+                                nonce_end: nonce_range.end,
+                                cutoff,
+                                hashes: report.hashes,
+                                hash_time_ms: report.hash_time.as_millis() as u64,
+                                best_difficulty: report.best_difficulty,
+                                overmined_by_ms: report.overmined_by_ms,
+                            }));
+                        }
+
+                        if submission_sender.is_closed() || shutdown.is_requested() {
+                            return;
+                        }
+                        let _ = submission_sender.send(MessageSubmissionSystem::Reset);
+
+                        // Ready up again
+                        if send_ready(&key, &sender).await.is_err() {
+                            let _ = submission_sender.send(MessageSubmissionSystem::Finish);
+                            warn!("Failed to send Ready message. Returning...");
+                        }
+                    }));
+                }
+                ServerMessage::PoolSubmissionResult(data) => {
+                    let pool_earned = (data.total_rewards
+                        * 10f64.powf(ore_api::consts::TOKEN_DECIMALS as f64))
+                        as u64;
+                    let miner_earned = (data.miner_earned_rewards
+                        * 10f64.powf(ore_api::consts::TOKEN_DECIMALS as f64))
+                        as u64;
+                    let result = PoolSubmissionResult::new(
+                        BASE64_STANDARD.encode(data.challenge),
+                        data.difficulty,
+                        pool_earned,
+                        data.miner_percentage,
+                        data.miner_supplied_difficulty,
+                        miner_earned,
+                    )
+                    .for_miner(self.key.pubkey().to_string(), url.to_string());
+                    METRICS.record_pool_result(&data);
+                    let _ = db_sender.send(DatabaseMessage::Submission(result));
+                    strategy.on_event(&SessionEvent::PoolSubmissionResult(data));
+                }
+            }
+        }
+
+        if shutting_down {
+            // The pass stops with the hashing threads, or runs to its cutoff with
+            // --finish-pass, and its record goes to the database either way
+            if let Some(pass) = current_pass {
+                let _ = pass.await;
+            }
+            // Solutions queued ahead of Finish are still sent
+            let _ = submission_sender.send(MessageSubmissionSystem::Finish);
+            let _ = submissions.await;
+            let close = Message::Close(Some(CloseFrame {
+                code: CloseCode::Normal,
+                reason: "Client shutting down".into(),
+            }));
+            let _ = sender.lock().await.send(close).await;
+            // The receiver returns once the pool answers with its own Close
+            if timeout(CLOSE_TIMEOUT, &mut receiver_thread).await.is_err() {
+                receiver_thread.abort();
+            }
+            return ConnectionEnd::Shutdown;
+        }
+
+        if recovered {
+            receiver_thread.abort();
+        }
+        // If the websocket message receiver finishes, also finish the solution submission
+        // sender system
+        let end = receiver_thread.await.unwrap_or(ConnectionEnd::Closed);
+        let _ = submission_sender.send(MessageSubmissionSystem::Finish);
+        if recovered {
+            ConnectionEnd::PrimaryRecovered
+        } else {
+            end
+        }
+    }
+}
+
+/// Requests a shutdown on the first SIGINT or SIGTERM. A second one, or the shutdown
+/// taking longer than its grace period, exits the process immediately.
+async fn watch_signals<S: MiningStrategy>(shutdown: Shutdown, strategy: Arc<S>) {
+    let signal = shutdown::signal().await;
+    strategy.on_event(&SessionEvent::ShuttingDown {
+        signal,
+        finish_pass: shutdown.finish_pass(),
+    });
+    shutdown.request();
+
+    tokio::select! {
+        signal = shutdown::signal() => {
+            warn!("Received {} again, exiting now.", signal);
+        }
+        _ = tokio::time::sleep(shutdown.grace()) => {
+            warn!("Shutdown took longer than {}s, exiting now.", shutdown.grace().as_secs());
+        }
+    }
+    std::process::exit(shutdown::FORCED_EXIT);
+}
+
+async fn send_ready(key: &Wallet, sender: &WsSender) -> Result<(), ()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs();
+    let bin_data = match ClientMessage::ready(key, now).await {
+        Ok(ready) => ready.encode(),
+        Err(e) => {
+            warn!("Failed to sign Ready: {}", e);
+            return Err(());
+        }
+    };
+    let mut lock = sender.lock().await;
+    lock.send(Message::Binary(bin_data)).await.map_err(|_| ())
+}
+
+async fn receiver_system<S: MiningStrategy>(
+    mut receiver: SplitStream<WsStream>,
+    message_sender: UnboundedSender<ServerMessage>,
+    submission_sender: UnboundedSender<MessageSubmissionSystem>,
+    strategy: Arc<S>,
+    receive_timeout: Duration,
+    start_mining_timeout: Duration,
+) -> ConnectionEnd {
+    let mut last_start_mine_instant = Instant::now();
+    let mut end = ConnectionEnd::Closed;
+    let reason = loop {
+        match timeout(receive_timeout, receiver.next()).await {
+            Ok(Some(Ok(message))) => {
+                match message {
+                    Message::Text(t) => {
+                        strategy.on_event(&SessionEvent::ServerText(t));
+                    }
+                    Message::Binary(b) => match ServerMessage::decode(&b) {
+                        Ok(msg) => {
+                            if matches!(msg, ServerMessage::StartMining(..)) {
+                                last_start_mine_instant = Instant::now();
+                            }
+                            let _ = message_sender.send(msg);
+                        }
+                        Err(e) => {
+                            warn!("Failed to parse server message: {}", e);
+                        }
+                    },
+                    Message::Close(v) => {
+                        break format!("Got Close: {:?}", v);
+                    }
+                    _ => {}
+                }
+
+                if last_start_mine_instant.elapsed() >= start_mining_timeout {
+                    end = ConnectionEnd::Stalled;
+                    break "Last start mining message was over 2 minutes ago. Closing websocket for reconnection.".to_string();
+                }
+            }
+            Ok(Some(Err(e))) => {
+                METRICS.websocket_errors.inc();
+                break format!("Websocket error: {}", e);
+            }
+            Ok(None) => {
+                break "Websocket closed gracefully".to_string();
+            }
+            Err(_) => {
+                end = ConnectionEnd::TimedOut;
+                break "Websocket receiver timeout, assuming disconnection".to_string();
+            }
+        }
+    };
+
+    strategy.on_event(&SessionEvent::Disconnected(reason));
+    let _ = submission_sender.send(MessageSubmissionSystem::Finish);
+    end
+}
+
+/// Re-derives the hash of a submission. Returns its real difficulty, or None if the
+/// digest is not a valid solution for this challenge and nonce.
+pub fn verified_difficulty(challenge: &[u8; 32], submission: &ThreadSubmission) -> Option<u32> {
+    let solution = drillx_2::Solution::new(submission.d, submission.nonce.to_le_bytes());
+    if !solution.is_valid(challenge) {
+        return None;
+    }
+    Some(solution.to_hash().difficulty())
+}
+
+struct SolutionVerifier<S> {
+    invalid_solutions: Arc<AtomicU64>,
+    strategy: Arc<S>,
+}
+
+impl<S: MiningStrategy> SolutionVerifier<S> {
+    /// Returns false, counting and reporting the submission, if it doesn't verify.
+    fn check(&self, challenge: &[u8; 32], submission: &ThreadSubmission) -> bool {
+        let actual_difficulty = verified_difficulty(challenge, submission);
+        if actual_difficulty == Some(submission.difficulty) {
+            return true;
+        }
+        let total = self.invalid_solutions.fetch_add(1, Ordering::Relaxed) + 1;
+        METRICS.invalid_solutions.inc();
+        self.strategy.on_event(&SessionEvent::InvalidSolution {
+            nonce: submission.nonce,
+            claimed_difficulty: submission.difficulty,
+            actual_difficulty,
+            total,
+        });
+        false
+    }
+}
+
+async fn submission_system<S: MiningStrategy>(
+    key: Arc<Wallet>,
+    mut system_message_receiver: UnboundedReceiver<MessageSubmissionSystem>,
+    socket_sender: WsSender,
+    verifier: Option<SolutionVerifier<S>>,
+) {
+    let mut best_diff = 0;
+    let mut challenge = None;
+    while let Some(msg) = system_message_receiver.recv().await {
+        match msg {
+            MessageSubmissionSystem::Challenge(c) => {
+                challenge = Some(c);
+            }
+            MessageSubmissionSystem::Submission(thread_submission) => {
+                if thread_submission.difficulty > best_diff {
+                    if let (Some(verifier), Some(challenge)) = (&verifier, &challenge) {
+                        if !verifier.check(challenge, &thread_submission) {
+                            continue;
+                        }
+                    }
+                    best_diff = thread_submission.difficulty;
+
+                    // Send results to the server
+                    let bin_vec = match ClientMessage::best_solution(
+                        &key,
+                        thread_submission.d,
+                        thread_submission.nonce,
+                    )
+                    .await
+                    {
+                        Ok(solution) => solution.encode(),
+                        Err(e) => {
+                            warn!("Failed to sign the solution: {}", e);
+                            continue;
+                        }
+                    };
+
+                    let mut message_sender = socket_sender.lock().await;
+                    if message_sender.send(Message::Binary(bin_vec)).await.is_ok() {
+                        METRICS.record_submission(thread_submission.difficulty);
+                    }
+                    drop(message_sender);
+                }
+            }
+            MessageSubmissionSystem::Reset => {
+                best_diff = 0;
+
+                // Sleep for 2 seconds to let the submission window open again
+                tokio::time::sleep(Duration::from_secs(2)).await;
+            }
+            MessageSubmissionSystem::Finish => {
+                return;
+            }
+        }
+    }
+}
+
+async fn database_system<S: MiningStrategy>(
+    db_path: PathBuf,
+    earnings_filter: EarningsFilter,
+    mut db_receiver: UnboundedReceiver<DatabaseMessage>,
+    strategy: Arc<S>,
+) {
+    let app_db = match AppDatabase::open(&db_path) {
+        Ok(app_db) => app_db,
+        Err(e) => {
+            error!("Error: Failed to open app database, results will not be saved: {e}");
+            return;
+        }
+    };
+    while let Some(msg) = db_receiver.recv().await {
+        match msg {
+            DatabaseMessage::Submission(result) => {
+                app_db.add_new_pool_submission(result);
+                strategy.on_event(&SessionEvent::TodaysEarnings(
+                    app_db.get_todays_earnings(&earnings_filter),
+                ));
+            }
+            DatabaseMessage::Pass(pass) => {
+                app_db.add_new_mining_pass(pass);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_pool::{MockPool, MockState, Step};
+    use crate::temp_db::TempDb;
+    use solana_sdk::signature::Keypair;
+    use std::path::Path;
+
+    // Submits one made-up solution per pass, the mock only checks the signature
+    struct FixedSolution;
+
+    impl MiningStrategy for FixedSolution {
+        fn run_pass(&self, pass: MiningPass) -> PassReport {
+            pass.submissions.submit(ThreadSubmission {
+                nonce: pass.nonce_range.start,
+                difficulty: 10,
+                d: [7u8; 16],
+            });
+            PassReport {
+                hashes: 500,
+                hash_time: Duration::from_millis(250),
+                best_difficulty: 10,
+                overmined_by_ms: None,
+            }
+        }
+
+        fn on_event(&self, _event: &SessionEvent) {}
+    }
+
+    fn start_session(
+        pool: &MockPool,
+        db_path: &Path,
+    ) -> tokio::task::JoinHandle<Result<(), Error>> {
+        let session = PoolSession::new(pool.url(), true, Keypair::new().into())
+            .with_timeouts(
+                Duration::from_secs(2),
+                Duration::from_secs(1),
+                Duration::from_millis(100),
+            )
+            .with_database(db_path.to_path_buf());
+        tokio::spawn(async move { session.run(FixedSolution).await })
+    }
+
+    fn readies(state: &MockState, connection: usize) -> usize {
+        state
+            .client_messages
+            .iter()
+            .filter(|(i, m)| *i == connection && matches!(m, ClientMessage::Ready { .. }))
+            .count()
+    }
+
+    #[tokio::test]
+    async fn submits_signed_solution_and_readies_up() {
+        let pool = MockPool::start().await;
+        pool.script(vec![
+            Step::ExpectReady,
+            Step::Send(ServerMessage::StartMining([1u8; 32], 0..1_000, 5)),
+            Step::ExpectSolution,
+            Step::ExpectReady,
+        ]);
+        let db = TempDb::new("solution");
+        let session = start_session(&pool, &db);
+
+        let done = pool
+            .wait_for(Duration::from_secs(10), |s| {
+                s.solutions() == 1 && readies(s, 0) == 2
+            })
+            .await;
+        session.abort();
+
+        assert!(done);
+        let state = pool.state();
+        assert!(state.connections[0].auth_valid);
+        assert_eq!(state.bad_client_messages, 0);
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_close_and_dropped_socket() {
+        let pool = MockPool::start().await;
+        pool.script(vec![Step::ExpectReady, Step::Close]);
+        pool.script(vec![Step::ExpectReady, Step::Drop]);
+        let db = TempDb::new("dropped");
+        let session = start_session(&pool, &db);
+
+        let reconnected = pool
+            .wait_for(Duration::from_secs(10), |s| readies(s, 2) == 1)
+            .await;
+        session.abort();
+
+        assert!(reconnected);
+    }
+
+    #[tokio::test]
+    async fn reconnects_when_start_mining_stops() {
+        let pool = MockPool::start().await;
+        // Keep the socket busy with text so only the StartMining watchdog can fire
+        let mut script = vec![Step::ExpectReady];
+        for _ in 0..10 {
+            script.push(Step::Sleep(Duration::from_millis(300)));
+            script.push(Step::Text("Pool Submitted Difficulty".to_string()));
+        }
+        pool.script(script);
+        let db = TempDb::new("watchdog");
+        let session = start_session(&pool, &db);
+
+        let reconnected = pool
+            .wait_for(Duration::from_secs(10), |s| s.connections.len() == 2)
+            .await;
+        session.abort();
+
+        assert!(reconnected);
+    }
+
+    #[tokio::test]
+    async fn retries_timestamp_after_server_error() {
+        let pool = MockPool::start().await;
+        pool.fail("/timestamp", 2);
+        let db = TempDb::new("timestamp");
+        let session = start_session(&pool, &db);
+
+        let connected = pool
+            .wait_for(Duration::from_secs(10), |s| readies(s, 0) == 1)
+            .await;
+        session.abort();
+
+        assert!(connected);
+        assert_eq!(pool.state().requests_to("/timestamp").len(), 3);
+    }
+
+    #[tokio::test]
+    async fn fails_over_and_returns_to_the_primary() {
+        let primary = MockPool::start().await;
+        let backup = MockPool::start().await;
+        primary.fail("/timestamp", FAILOVER_AFTER as usize);
+        primary.script(vec![Step::ExpectReady]);
+        backup.script(vec![Step::ExpectReady]);
+        let db = TempDb::new("failover");
+        let session = PoolSession::new(primary.url(), true, Keypair::new().into())
+            .failover(vec![backup.url()])
+            .with_timeouts(
+                Duration::from_secs(30),
+                Duration::from_secs(30),
+                Duration::from_millis(50),
+            )
+            .with_primary_check(Duration::from_millis(300))
+            .with_database(db.to_path_buf());
+        let session = tokio::spawn(async move { session.run(FixedSolution).await });
+
+        let failed_over = backup
+            .wait_for(Duration::from_secs(10), |s| readies(s, 0) == 1)
+            .await;
+        let returned = primary
+            .wait_for(Duration::from_secs(10), |s| readies(s, 0) == 1)
+            .await;
+        session.abort();
+
+        assert!(failed_over);
+        assert!(returned);
+        assert_eq!(backup.state().connections.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_single_receive_timeout_stays_on_the_primary() {
+        let primary = MockPool::start().await;
+        let backup = MockPool::start().await;
+        primary.script(vec![Step::ExpectReady]);
+        primary.script(vec![Step::ExpectReady]);
+        let db = TempDb::new("receive-timeout");
+        let session = PoolSession::new(primary.url(), true, Keypair::new().into())
+            .failover(vec![backup.url()])
+            .with_timeouts(
+                Duration::from_millis(300),
+                Duration::from_secs(30),
+                Duration::from_millis(50),
+            )
+            .with_database(db.to_path_buf());
+        let session = tokio::spawn(async move { session.run(FixedSolution).await });
+
+        let reconnected = primary
+            .wait_for(Duration::from_secs(10), |s| readies(s, 1) == 1)
+            .await;
+        session.abort();
+
+        assert!(reconnected);
+        assert!(backup.state().connections.is_empty());
+    }
+
+    #[tokio::test]
+    async fn records_pool_submission_results() {
+        let pool = MockPool::start().await;
+        pool.script(vec![
+            Step::ExpectReady,
+            Step::Send(ServerMessage::PoolSubmissionResult(
+                ServerMessagePoolSubmissionResult {
+                    difficulty: 20,
+                    total_balance: 10.0,
+                    total_rewards: 0.5,
+                    top_stake: 100.0,
+                    multiplier: 1.0,
+                    active_miners: 3,
+                    challenge: [2u8; 32],
+                    best_nonce: 5,
+                    miner_supplied_difficulty: 15,
+                    miner_earned_rewards: 0.01,
+                    miner_percentage: 2.0,
+                },
+            )),
+        ]);
+        let db_path = TempDb::new("results");
+        let session = start_session(&pool, &db_path);
+
+        let started = Instant::now();
+        let mut earned = 0;
+        while earned == 0 && started.elapsed() < Duration::from_secs(10) {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            if db_path.exists() {
+                earned = AppDatabase::open(&db_path)
+                    .unwrap()
+                    .get_todays_earnings(&EarningsFilter::default());
+            }
+        }
+        session.abort();
+
+        assert_eq!(earned, 1_000_000_000);
+    }
+
+    type Dropped = Arc<std::sync::Mutex<Vec<(u64, Option<u32>)>>>;
+
+    // Submits a bogus solution and then a real one, recording dropped solutions
+    struct BogusThenValid(Dropped);
+
+    impl MiningStrategy for BogusThenValid {
+        fn run_pass(&self, pass: MiningPass) -> PassReport {
+            pass.submissions.submit(ThreadSubmission {
+                nonce: pass.nonce_range.start,
+                difficulty: 30,
+                d: [7u8; 16],
+            });
+            // Anything above difficulty 0 improves on the empty best
+            let (nonce, hash) = pass
+                .nonce_range
+                .clone()
+                .filter_map(|n| {
+                    drillx_2::hash(&pass.challenge, &n.to_le_bytes())
+                        .ok()
+                        .map(|h| (n, h))
+                })
+                .find(|(_, h)| h.difficulty() > 0)
+                .unwrap();
+            pass.submissions.submit(ThreadSubmission {
+                nonce,
+                difficulty: hash.difficulty(),
+                d: hash.d,
+            });
+            PassReport::default()
+        }
+
+        fn on_event(&self, event: &SessionEvent) {
+            if let SessionEvent::InvalidSolution {
+                nonce,
+                actual_difficulty,
+                ..
+            } = event
+            {
+                self.0.lock().unwrap().push((*nonce, *actual_difficulty));
+            }
+        }
+    }
+
+    #[test]
+    fn verified_difficulty_matches_drillx() {
+        let challenge = [3u8; 32];
+        let (nonce, hash) = (0u64..)
+            .find_map(|n| {
+                drillx_2::hash(&challenge, &n.to_le_bytes())
+                    .ok()
+                    .map(|h| (n, h))
+            })
+            .unwrap();
+        let mut submission = ThreadSubmission {
+            nonce,
+            difficulty: hash.difficulty(),
+            d: hash.d,
+        };
+        assert_eq!(
+            verified_difficulty(&challenge, &submission),
+            Some(hash.difficulty())
+        );
+
+        submission.d = [0u8; 16];
+        assert_eq!(verified_difficulty(&challenge, &submission), None);
+    }
+
+    #[tokio::test]
+    async fn drops_solutions_that_fail_verification() {
+        let pool = MockPool::start().await;
+        pool.script(vec![
+            Step::ExpectReady,
+            Step::Send(ServerMessage::StartMining([1u8; 32], 0..1_000, 5)),
+            Step::ExpectSolution,
+            Step::ExpectReady,
+        ]);
+        let dropped = Arc::new(std::sync::Mutex::new(vec![]));
+        let db = TempDb::new("verify");
+        let session = PoolSession::new(pool.url(), true, Keypair::new().into())
+            .with_timeouts(
+                Duration::from_secs(2),
+                Duration::from_secs(1),
+                Duration::from_millis(100),
+            )
+            .with_database(db.to_path_buf())
+            .verify_solutions(true);
+        let strategy = BogusThenValid(dropped.clone());
+        let session = tokio::spawn(async move { session.run(strategy).await });
+
+        let done = pool
+            .wait_for(Duration::from_secs(10), |s| readies(s, 0) == 2)
+            .await;
+        session.abort();
+
+        assert!(done);
+        assert_eq!(pool.state().solutions(), 1);
+        assert_eq!(*dropped.lock().unwrap(), vec![(0, None)]);
+    }
+
+    #[tokio::test]
+    async fn records_mining_passes_with_their_results() {
+        let pool = MockPool::start().await;
+        pool.script(vec![
+            Step::ExpectReady,
+            Step::Send(ServerMessage::StartMining([4u8; 32], 100..2_000, 5)),
+            Step::ExpectSolution,
+            Step::ExpectReady,
+            Step::Send(ServerMessage::PoolSubmissionResult(
+                ServerMessagePoolSubmissionResult {
+                    difficulty: 22,
+                    total_balance: 10.0,
+                    total_rewards: 0.5,
+                    top_stake: 100.0,
+                    multiplier: 1.0,
+                    active_miners: 3,
+                    challenge: [4u8; 32],
+                    best_nonce: 100,
+                    miner_supplied_difficulty: 10,
+                    miner_earned_rewards: 0.01,
+                    miner_percentage: 2.0,
+                },
+            )),
+        ]);
+        let db_path = TempDb::new("passes");
+        let session = start_session(&pool, &db_path);
+
+        let started = Instant::now();
+        let mut passes = vec![];
+        while started.elapsed() < Duration::from_secs(10) {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            if db_path.exists() {
+                passes = AppDatabase::open(&db_path).unwrap().get_recent_passes(10);
+                if passes.iter().any(|p| p.miner_earned.is_some()) {
+                    break;
+                }
+            }
+        }
+        session.abort();
+
+        assert_eq!(passes.len(), 1);
+        let pass = &passes[0];
+        assert_eq!(pass.pass.challenge, BASE64_STANDARD.encode([4u8; 32]));
+        assert_eq!((pass.pass.nonce_start, pass.pass.nonce_end), (100, 2_000));
+        assert_eq!(pass.pass.cutoff, 5);
+        assert_eq!(pass.pass.hashes, 500);
+        assert_eq!(pass.pass.hashrate(), 2_000.0);
+        assert_eq!(pass.pool_difficulty, Some(22));
+        assert_eq!(pass.miner_earned, Some(1_000_000_000));
+    }
+
+    // Holds each pass until `hold` passes or the session stops it, then submits
+    struct HoldsPass {
+        started: Arc<tokio::sync::Notify>,
+        hold: Duration,
+    }
+
+    impl MiningStrategy for HoldsPass {
+        fn run_pass(&self, pass: MiningPass) -> PassReport {
+            self.started.notify_one();
+            let started = Instant::now();
+            while started.elapsed() < self.hold && pass.running.load(Ordering::SeqCst) {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            pass.submissions.submit(ThreadSubmission {
+                nonce: pass.nonce_range.start,
+                difficulty: 10,
+                d: [7u8; 16],
+            });
+            PassReport {
+                hash_time: started.elapsed(),
+                ..Default::default()
+            }
+        }
+
+        fn on_event(&self, _event: &SessionEvent) {}
+    }
+
+    async fn shut_down_mid_pass(
+        name: &str,
+        hold: Duration,
+        finish_pass: bool,
+    ) -> (MockPool, TempDb) {
+        let pool = MockPool::start().await;
+        pool.script(vec![
+            Step::ExpectReady,
+            Step::Send(ServerMessage::StartMining([5u8; 32], 0..1_000, 5)),
+        ]);
+        let db_path = TempDb::new(name);
+        let session = PoolSession::new(pool.url(), true, Keypair::new().into())
+            .with_timeouts(
+                Duration::from_secs(2),
+                Duration::from_secs(1),
+                Duration::from_millis(100),
+            )
+            .with_database(db_path.to_path_buf())
+            .finish_pass_on_shutdown(finish_pass);
+        let shutdown = session.shutdown();
+        let started = Arc::new(tokio::sync::Notify::new());
+        let strategy = HoldsPass {
+            started: started.clone(),
+            hold,
+        };
+        let session = tokio::spawn(async move { session.run(strategy).await });
+
+        started.notified().await;
+        shutdown.request();
+        let stopped = timeout(Duration::from_secs(5), session).await;
+        assert!(stopped.is_ok(), "session did not stop");
+        (pool, db_path)
+    }
+
+    #[tokio::test]
+    async fn shutdown_stops_the_pass_and_closes_the_socket() {
+        let (pool, db_path) = shut_down_mid_pass("shutdown", Duration::from_secs(30), false).await;
+
+        let state = pool.state();
+        assert!(state.connections[0].closed_by_client);
+        assert_eq!(readies(&state, 0), 1);
+        assert_eq!(state.connections.len(), 1);
+        let passes = AppDatabase::open(&db_path).unwrap().get_recent_passes(10);
+        assert_eq!(passes.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn shutdown_can_let_the_pass_finish_and_submit() {
+        let hold = Duration::from_millis(500);
+        let (pool, db_path) = shut_down_mid_pass("finish-pass", hold, true).await;
+
+        let state = pool.state();
+        assert!(state.connections[0].closed_by_client);
+        assert_eq!(state.solutions(), 1);
+        assert_eq!(readies(&state, 0), 1);
+        let passes = AppDatabase::open(&db_path).unwrap().get_recent_passes(10);
+        assert_eq!(passes.len(), 1);
+        assert!(passes[0].pass.hash_time_ms >= hold.as_millis() as u64);
+    }
+}
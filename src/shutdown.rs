@@ -0,0 +1,93 @@
+//! Stopping a mining session on SIGINT or SIGTERM.
+//!
+//! The first signal stops the hashing threads, or with `--finish-pass` lets the pass in
+//! flight run to its cutoff and submit. The session then closes its websocket and flushes
+//! the database. A second signal, or the grace period running out, exits right away.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+/// Exit code when the process is stopped before shutting down cleanly, as for SIGINT
+pub const FORCED_EXIT: i32 = 130;
+/// How long closing the connection and flushing the database may take
+const GRACE: Duration = Duration::from_secs(10);
+/// Pass cutoffs are capped to a minute, so `--finish-pass` waits that much longer
+const FINISH_PASS_GRACE: Duration = Duration::from_secs(70);
+
+/// Asks a session to stop. Clones share the same request.
+#[derive(Clone)]
+pub struct Shutdown {
+    requested: Arc<watch::Sender<bool>>,
+    running: Arc<AtomicBool>,
+    finish_pass: bool,
+}
+
+impl Shutdown {
+    /// `running` is the flag the hashing threads poll between nonces.
+    pub fn new(running: Arc<AtomicBool>, finish_pass: bool) -> Self {
+        Shutdown {
+            requested: Arc::new(watch::channel(false).0),
+            running,
+            finish_pass,
+        }
+    }
+
+    /// Stops new passes from starting, and the one in flight unless it should finish.
+    pub fn request(&self) {
+        if !self.finish_pass {
+            self.running.store(false, Ordering::SeqCst);
+        }
+        self.requested.send_replace(true);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.requested.borrow()
+    }
+
+    /// Resolves once `request` has been called.
+    pub async fn requested(&self) {
+        let mut requested = self.requested.subscribe();
+        let _ = requested.wait_for(|requested| *requested).await;
+    }
+
+    pub fn finish_pass(&self) -> bool {
+        self.finish_pass
+    }
+
+    /// How long after the request the process may take to exit.
+    pub fn grace(&self) -> Duration {
+        if self.finish_pass {
+            FINISH_PASS_GRACE
+        } else {
+            GRACE
+        }
+    }
+}
+
+/// Resolves with the name of the first SIGINT or SIGTERM received.
+pub async fn signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = match signal(SignalKind::terminate()) {
+            Ok(terminate) => terminate,
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+                return "SIGINT";
+            }
+        };
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => "SIGINT",
+            _ = terminate.recv() => "SIGTERM",
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "Ctrl+C"
+    }
+}
@@ -0,0 +1,814 @@
+//! What commands sign with, and the remote signer that keeps the wallet off mining rigs.
+//!
+//! A `Wallet` is a keypair file, plaintext or encrypted, or a `RemoteSigner` reached with
+//! `--signer unix:PATH` or `--signer tcp:HOST:PORT`. The other end is `signer serve`, run
+//! where the keypair lives. Requests and answers are one JSON object per line:
+//! `{"method":"pubkey"}` is answered by `{"pubkey":"..."}`, and
+//! `{"method":"sign","message":"<base64>"}` by `{"signature":"..."}`, `{"refused":"..."}`
+//! or `{"error":"..."}`.
+//!
+//! The server decides what to sign from the message bytes alone, never from what the
+//! client says they are. Fresh Ready timestamps and BestSolution digests are signed as
+//! they come. Claims and transactions such as boosts wait for the operator to confirm
+//! them at the server's terminal, and anything else is refused.
+//!
+//! Nothing authenticates a rig, and a signed timestamp is enough to log in to a pool as the
+//! wallet. So the unix socket is only accessible to its owner, and `tcp:` listens on
+//! loopback unless `--allow-remote` is given. Rigs on other machines should reach it
+//! through an SSH tunnel or a forwarded unix socket instead.
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use clap::{Parser, Subcommand};
+use colored::*;
+use inquire::Confirm;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    hash::Hash,
+    message::Message,
+    pubkey::Pubkey,
+    sanitize::Sanitize,
+    signature::{Keypair, Signature},
+    signer::{Signer, SignerError},
+    transaction::Transaction,
+};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use crate::error::Error;
+use crate::keystore::{self, KeystoreError};
+use crate::output;
+use crate::shutdown;
+
+/// How long to wait for a signature, long enough for the operator to confirm a claim
+const SIGN_TIMEOUT: Duration = Duration::from_secs(120);
+/// How far a timestamp may be from the server's clock and still be signed unconfirmed
+const MAX_CLOCK_SKEW: u64 = 300;
+
+/// The wallet a command signs with.
+pub enum Wallet {
+    /// A plaintext keypair file
+    File(Keypair),
+    /// An encrypted keypair file, decrypted at startup
+    Keystore(Keypair),
+    /// `signer serve` on another machine, so the secret key never reaches this one
+    Remote(Arc<RemoteSigner>),
+}
+
+impl Wallet {
+    /// Loads the keypair file at `path`, getting the passphrase if it's encrypted.
+    pub fn load(path: &str) -> Result<Wallet, KeystoreError> {
+        let keypair = keystore::read_keypair(path)?;
+        if keystore::is_encrypted(Path::new(path)) {
+            Ok(Wallet::Keystore(keypair))
+        } else {
+            Ok(Wallet::File(keypair))
+        }
+    }
+
+    /// Connects to `signer serve` at `address`.
+    pub fn remote(address: &str) -> Result<Wallet, Error> {
+        let address = address.parse().map_err(Error::Usage)?;
+        RemoteSigner::connect(address)
+            .map(|remote| Wallet::Remote(Arc::new(remote)))
+            .map_err(Error::Signer)
+    }
+
+    /// Signs `message` without holding up the runtime while a remote signer answers, which
+    /// can take as long as the operator does to confirm.
+    pub async fn sign(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        match self {
+            Wallet::File(keypair) | Wallet::Keystore(keypair) => keypair.try_sign_message(message),
+            Wallet::Remote(remote) => {
+                let remote = remote.clone();
+                let message = message.to_vec();
+                tokio::task::spawn_blocking(move || remote.try_sign_message(&message))
+                    .await
+                    .map_err(|e| SignerError::Custom(e.to_string()))?
+            }
+        }
+    }
+
+    /// Adds this wallet's signature to `tx` over `blockhash`, like `try_partial_sign`.
+    pub async fn sign_transaction(
+        &self,
+        tx: &mut Transaction,
+        blockhash: Hash,
+    ) -> Result<(), SignerError> {
+        let position = tx.get_signing_keypair_positions(&[self.try_pubkey()?])?[0]
+            .ok_or(SignerError::KeypairPubkeyMismatch)?;
+        if tx.message.recent_blockhash != blockhash {
+            tx.message.recent_blockhash = blockhash;
+            tx.signatures.fill(Signature::default());
+        }
+        tx.signatures[position] = self.sign(&tx.message_data()).await?;
+        Ok(())
+    }
+}
+
+impl From<Keypair> for Wallet {
+    fn from(keypair: Keypair) -> Self {
+        Wallet::File(keypair)
+    }
+}
+
+impl Signer for Wallet {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        match self {
+            Wallet::File(keypair) | Wallet::Keystore(keypair) => keypair.try_pubkey(),
+            Wallet::Remote(remote) => remote.try_pubkey(),
+        }
+    }
+
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        match self {
+            Wallet::File(keypair) | Wallet::Keystore(keypair) => keypair.try_sign_message(message),
+            Wallet::Remote(remote) => remote.try_sign_message(message),
+        }
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+/// Where `signer serve` listens.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignerAddress {
+    #[cfg(unix)]
+    Unix(PathBuf),
+    Tcp(String),
+}
+
+impl FromStr for SignerAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        #[cfg(unix)]
+        if let Some(path) = s.strip_prefix("unix:") {
+            let path = crate::expand_tilde(path);
+            return Ok(SignerAddress::Unix(PathBuf::from(path)));
+        }
+        match s.strip_prefix("tcp:") {
+            Some(address) => Ok(SignerAddress::Tcp(address.to_string())),
+            None => Err(format!(
+                "invalid signer address {}, expected unix:PATH or tcp:HOST:PORT",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SignerAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(unix)]
+            SignerAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+            SignerAddress::Tcp(address) => write!(f, "tcp:{}", address),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+enum Request {
+    Pubkey,
+    Sign { message: String },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Response {
+    Pubkey(String),
+    Signature(String),
+    /// The policy or the operator turned the message down
+    Refused(String),
+    Error(String),
+}
+
+/// Signs through `signer serve`. Calls block until the answer, which for a claim includes
+/// the operator confirming it, so async code signs with `Wallet::sign`.
+pub struct RemoteSigner {
+    address: SignerAddress,
+    pubkey: Pubkey,
+    connection: Mutex<Option<Connection>>,
+}
+
+impl RemoteSigner {
+    /// Connects to `address` and asks for the public key it signs for.
+    pub fn connect(address: SignerAddress) -> Result<Self, SignerError> {
+        let mut signer = RemoteSigner {
+            address,
+            pubkey: Pubkey::default(),
+            connection: Mutex::new(None),
+        };
+        match signer.call(&Request::Pubkey)? {
+            Response::Pubkey(pubkey) => {
+                signer.pubkey = pubkey
+                    .parse()
+                    .map_err(|_| SignerError::Protocol(format!("invalid pubkey {}", pubkey)))?;
+                Ok(signer)
+            }
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Sends `request`, opening a new connection once if the kept one has gone away.
+    fn call(&self, request: &Request) -> Result<Response, SignerError> {
+        let mut connection = self.connection.lock().unwrap();
+        let connection_err =
+            |e: io::Error| SignerError::Connection(format!("{}: {}", self.address, e));
+
+        if let Some(open) = connection.as_mut() {
+            match open.call(request) {
+                Ok(response) => return Ok(response),
+                Err(e) => {
+                    *connection = None;
+                    // Asking again after a timeout could prompt the operator twice
+                    if e.kind() == io::ErrorKind::TimedOut || e.kind() == io::ErrorKind::WouldBlock
+                    {
+                        return Err(connection_err(e));
+                    }
+                }
+            }
+        }
+        let response = connection
+            .insert(Connection::open(&self.address).map_err(connection_err)?)
+            .call(request);
+        if response.is_err() {
+            *connection = None;
+        }
+        response.map_err(connection_err)
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        Ok(self.pubkey)
+    }
+
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        let request = Request::Sign {
+            message: BASE64_STANDARD.encode(message),
+        };
+        match self.call(&request)? {
+            Response::Signature(signature) => {
+                let signature = Signature::from_str(&signature).map_err(|_| {
+                    SignerError::Protocol(format!("invalid signature {}", signature))
+                })?;
+                if !signature.verify(self.pubkey.as_ref(), message) {
+                    return Err(SignerError::Protocol(
+                        "the signer answered with a signature that doesn't verify".to_string(),
+                    ));
+                }
+                Ok(signature)
+            }
+            Response::Refused(reason) => Err(SignerError::UserCancel(reason)),
+            Response::Error(e) => Err(SignerError::Custom(e)),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+fn unexpected(response: Response) -> SignerError {
+    SignerError::Protocol(format!("unexpected answer from the signer: {:?}", response))
+}
+
+struct Connection {
+    reader: BufReader<Box<dyn Read + Send>>,
+    writer: Box<dyn Write + Send>,
+}
+
+impl Connection {
+    fn open(address: &SignerAddress) -> io::Result<Self> {
+        let (reader, writer): (Box<dyn Read + Send>, Box<dyn Write + Send>) = match address {
+            #[cfg(unix)]
+            SignerAddress::Unix(path) => {
+                let stream = std::os::unix::net::UnixStream::connect(path)?;
+                stream.set_read_timeout(Some(SIGN_TIMEOUT))?;
+                (Box::new(stream.try_clone()?), Box::new(stream))
+            }
+            SignerAddress::Tcp(address) => {
+                let stream = TcpStream::connect(address)?;
+                stream.set_read_timeout(Some(SIGN_TIMEOUT))?;
+                stream.set_nodelay(true)?;
+                (Box::new(stream.try_clone()?), Box::new(stream))
+            }
+        };
+        Ok(Connection {
+            reader: BufReader::new(reader),
+            writer,
+        })
+    }
+
+    fn call(&mut self, request: &Request) -> io::Result<Response> {
+        let mut line = serde_json::to_string(request)?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.flush()?;
+
+        let mut answer = String::new();
+        if self.reader.read_line(&mut answer)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(serde_json::from_str(&answer)?)
+    }
+}
+
+/// What a message to sign is, told from its bytes.
+#[derive(Debug, PartialEq)]
+enum Payload {
+    /// A Ready message or a websocket login, both sign a unix timestamp
+    Timestamp(u64),
+    /// A BestSolution's digest and nonce
+    Solution {
+        nonce: u64,
+    },
+    /// A claim of mining rewards, over the server timestamp, receiver and amount
+    Claim {
+        receiver: Pubkey,
+        amount: u64,
+    },
+    /// A claim of staking rewards from a boost
+    StakeClaim {
+        mint: Pubkey,
+        receiver: Pubkey,
+        amount: u64,
+    },
+    /// A transaction such as a boost, unboost or unstake
+    Transaction(Message),
+    Unknown(usize),
+}
+
+enum Policy {
+    Sign,
+    Confirm,
+    Refuse,
+}
+
+impl Payload {
+    fn parse(bytes: &[u8]) -> Self {
+        // Only a message that encodes back to exactly these bytes is a transaction
+        if let Ok(message) = bincode::deserialize::<Message>(bytes) {
+            if message.sanitize().is_ok()
+                && bincode::serialize(&message).ok().as_deref() == Some(bytes)
+            {
+                return Payload::Transaction(message);
+            }
+        }
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let pubkey_at = |at: usize| Pubkey::try_from(&bytes[at..at + 32]).unwrap();
+        match bytes.len() {
+            8 => Payload::Timestamp(u64_at(0)),
+            24 => Payload::Solution { nonce: u64_at(16) },
+            48 => Payload::Claim {
+                receiver: pubkey_at(8),
+                amount: u64_at(40),
+            },
+            80 => Payload::StakeClaim {
+                mint: pubkey_at(8),
+                receiver: pubkey_at(40),
+                amount: u64_at(72),
+            },
+            len => Payload::Unknown(len),
+        }
+    }
+
+    fn policy(&self, now: u64) -> Policy {
+        match self {
+            Payload::Timestamp(timestamp) if timestamp.abs_diff(now) <= MAX_CLOCK_SKEW => {
+                Policy::Sign
+            }
+            Payload::Solution { .. } => Policy::Sign,
+            Payload::Unknown(_) => Policy::Refuse,
+            // Claims, transactions and timestamps that could log in later
+            _ => Policy::Confirm,
+        }
+    }
+}
+
+impl fmt::Display for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ore = |amount: u64| amount as f64 / 10f64.powf(ore_api::consts::TOKEN_DECIMALS as f64);
+        match self {
+            Payload::Timestamp(timestamp) => write!(f, "timestamp {}", timestamp),
+            Payload::Solution { nonce } => write!(f, "solution with nonce {}", nonce),
+            Payload::Claim { receiver, amount } => {
+                write!(f, "a claim of {} ORE to {}", ore(*amount), receiver)
+            }
+            Payload::StakeClaim {
+                mint,
+                receiver,
+                amount,
+            } => write!(
+                f,
+                "a claim of {} staking rewards from boost {} to {}",
+                ore(*amount),
+                mint,
+                receiver
+            ),
+            Payload::Transaction(message) => {
+                let programs: Vec<String> = message
+                    .instructions
+                    .iter()
+                    .map(|ix| {
+                        let program = message.account_keys[ix.program_id_index as usize];
+                        if program == ore_miner_delegation::id() {
+                            "ore-miner-delegation".to_string()
+                        } else {
+                            program.to_string()
+                        }
+                    })
+                    .collect();
+                write!(
+                    f,
+                    "a transaction for {}, fee payer {}",
+                    programs.join(", "),
+                    message.account_keys[0]
+                )
+            }
+            Payload::Unknown(len) => write!(f, "{} bytes of unknown data", len),
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct SignerArgs {
+    #[command(subcommand)]
+    pub command: SignerCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SignerCommand {
+    #[command(
+        about = "Sign for mining rigs with --keypair, asking here before signing claims and boosts."
+    )]
+    Serve(ServeArgs),
+}
+
+#[derive(Debug, Parser)]
+pub struct ServeArgs {
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        help = "unix:PATH or tcp:HOST:PORT to listen on"
+    )]
+    pub listen: SignerAddress,
+
+    #[arg(
+        long,
+        help = "Listen on a tcp address other than loopback. Rigs aren't authenticated, anyone who can reach it can log in to pools as the wallet."
+    )]
+    pub allow_remote: bool,
+}
+
+/// Runs a `signer` command with the keypair at `keypair`.
+pub async fn signer(args: &SignerArgs, keypair: &str) -> Result<(), Error> {
+    match &args.command {
+        SignerCommand::Serve(args) => serve(args, keypair).await,
+    }
+}
+
+struct Server {
+    keypair: Keypair,
+    /// Confirmations need someone at the terminal
+    interactive: bool,
+    /// One confirmation prompt at a time
+    prompt: tokio::sync::Mutex<()>,
+}
+
+async fn serve(args: &ServeArgs, keypair: &str) -> Result<(), Error> {
+    if output::json() {
+        return Err(Error::Usage(
+            "signer serve logs as it goes, run it without --output json".to_string(),
+        ));
+    }
+    let server = Arc::new(Server {
+        keypair: keystore::read_keypair(keypair).map_err(|source| Error::Keystore {
+            path: keypair.to_string(),
+            source,
+        })?,
+        interactive: io::stdin().is_terminal(),
+        prompt: tokio::sync::Mutex::new(()),
+    });
+    let io_err = |e: io::Error| Error::Other(format!("Failed to listen on {}: {}", args.listen, e));
+
+    println!(
+        "  Signing for {} on {}",
+        server.keypair.pubkey().to_string().bold(),
+        args.listen
+    );
+    if !server.interactive {
+        warn!("No terminal to confirm on, claims and transactions will be refused.");
+    }
+
+    let accept = match &args.listen {
+        #[cfg(unix)]
+        SignerAddress::Unix(path) => {
+            use std::os::unix::fs::FileTypeExt;
+            // A socket left by an earlier run that didn't get to remove it
+            if std::fs::metadata(path).map_or(false, |m| m.file_type().is_socket()) {
+                std::fs::remove_file(path).map_err(io_err)?;
+            }
+            tokio::spawn(accept_unix(bind_unix(path).map_err(io_err)?, server))
+        }
+        SignerAddress::Tcp(address) => {
+            let listener = bind_tcp(address, args.allow_remote).await.map_err(|e| match e {
+                BindError::Remote(ip) => Error::Usage(format!(
+                    "{} isn't a loopback address and rigs aren't authenticated, pass --allow-remote to listen on it anyway",
+                    ip
+                )),
+                BindError::Io(e) => io_err(e),
+            })?;
+            tokio::spawn(accept_tcp(listener, server))
+        }
+    };
+
+    let signal = shutdown::signal().await;
+    accept.abort();
+    #[cfg(unix)]
+    if let SignerAddress::Unix(path) = &args.listen {
+        let _ = std::fs::remove_file(path);
+    }
+    println!("  Received {}, stopped signing.", signal);
+    Ok(())
+}
+
+/// Binds the socket with no access for group or others from the start, rather than
+/// narrowing it after it's already reachable.
+#[cfg(unix)]
+fn bind_unix(path: &Path) -> io::Result<tokio::net::UnixListener> {
+    // SAFETY: umask only swaps the process file mode mask and can't fail
+    let previous = unsafe { libc::umask(0o177) };
+    let listener = tokio::net::UnixListener::bind(path);
+    unsafe { libc::umask(previous) };
+    listener
+}
+
+enum BindError {
+    /// A non-loopback address without `--allow-remote`
+    Remote(std::net::IpAddr),
+    Io(io::Error),
+}
+
+async fn bind_tcp(address: &str, allow_remote: bool) -> Result<tokio::net::TcpListener, BindError> {
+    let addrs: Vec<_> = tokio::net::lookup_host(address)
+        .await
+        .map_err(BindError::Io)?
+        .collect();
+    if !allow_remote {
+        if let Some(remote) = addrs.iter().find(|addr| !addr.ip().is_loopback()) {
+            return Err(BindError::Remote(remote.ip()));
+        }
+    }
+    tokio::net::TcpListener::bind(&addrs[..])
+        .await
+        .map_err(BindError::Io)
+}
+
+#[cfg(unix)]
+async fn accept_unix(listener: tokio::net::UnixListener, server: Arc<Server>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(handle(stream, "local socket".to_string(), server.clone()));
+            }
+            Err(e) => warn!("Failed to accept a connection: {}", e),
+        }
+    }
+}
+
+async fn accept_tcp(listener: tokio::net::TcpListener, server: Arc<Server>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                tokio::spawn(handle(stream, peer.to_string(), server.clone()));
+            }
+            Err(e) => warn!("Failed to accept a connection: {}", e),
+        }
+    }
+}
+
+async fn handle<S>(stream: S, peer: String, server: Arc<Server>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = tokio::io::BufReader::new(reader).lines();
+    info!("Rig connected from {}", peer);
+    while let Ok(Some(line)) = lines.next_line().await {
+        let response = match serde_json::from_str(&line) {
+            Ok(Request::Pubkey) => Response::Pubkey(server.keypair.pubkey().to_string()),
+            Ok(Request::Sign { message }) => server.sign(&message, &peer).await,
+            Err(e) => Response::Error(format!("invalid request: {}", e)),
+        };
+        let mut answer = serde_json::to_string(&response).expect("responses serialize");
+        answer.push('\n');
+        if writer.write_all(answer.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+    info!("Rig at {} disconnected", peer);
+}
+
+impl Server {
+    async fn sign(&self, message: &str, peer: &str) -> Response {
+        let Ok(bytes) = BASE64_STANDARD.decode(message) else {
+            return Response::Error("message is not base64".to_string());
+        };
+        let payload = Payload::parse(&bytes);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+
+        match payload.policy(now) {
+            Policy::Sign => info!("Signing {} for {}", payload, peer),
+            Policy::Refuse => {
+                warn!("Refused to sign {} for {}", payload, peer);
+                return Response::Refused(format!("the signer doesn't sign {}", payload));
+            }
+            Policy::Confirm => {
+                if !self.interactive {
+                    warn!(
+                        "Refused to sign {} for {}, nobody to confirm it",
+                        payload, peer
+                    );
+                    return Response::Refused(format!(
+                        "{} needs confirming and the signer has no terminal",
+                        payload
+                    ));
+                }
+                let _prompt = self.prompt.lock().await;
+                let question = format!("  {} asks to sign {}. Sign it?", peer, payload);
+                let confirmed = tokio::task::spawn_blocking(move || {
+                    Confirm::new(&question).with_default(false).prompt()
+                })
+                .await;
+                if !matches!(confirmed, Ok(Ok(true))) {
+                    return Response::Refused(format!("the operator declined {}", payload));
+                }
+            }
+        }
+        Response::Signature(self.keypair.sign_message(&bytes).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ClientMessage;
+    use solana_sdk::transaction::Transaction;
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn claim_message(receiver: &Pubkey, amount: u64) -> Vec<u8> {
+        let mut message = vec![];
+        message.extend(now().to_le_bytes());
+        message.extend(receiver.to_bytes());
+        message.extend(amount.to_le_bytes());
+        message
+    }
+
+    #[test]
+    fn payloads_are_told_apart_by_their_bytes() {
+        let now = now();
+        let ready = Payload::parse(&now.to_le_bytes());
+        assert_eq!(ready, Payload::Timestamp(now));
+        assert!(matches!(ready.policy(now), Policy::Sign));
+        assert!(matches!(ready.policy(now + 3600), Policy::Confirm));
+
+        let solution = Payload::parse(&ClientMessage::solution_message(&[7; 16], 42));
+        assert_eq!(solution, Payload::Solution { nonce: 42 });
+        assert!(matches!(solution.policy(now), Policy::Sign));
+
+        let receiver = Pubkey::new_unique();
+        let claim = Payload::parse(&claim_message(&receiver, 5));
+        assert_eq!(
+            claim,
+            Payload::Claim {
+                receiver,
+                amount: 5
+            }
+        );
+        assert!(matches!(claim.policy(now), Policy::Confirm));
+
+        let key = Keypair::new();
+        let ix = ore_miner_delegation::instruction::delegate_boost_v2(
+            key.pubkey(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            1,
+        );
+        let tx = Transaction::new_with_payer(&[ix], Some(&Pubkey::new_unique()));
+        let boost = Payload::parse(&tx.message_data());
+        assert!(matches!(boost, Payload::Transaction(_)));
+        assert!(matches!(boost.policy(now), Policy::Confirm));
+        assert!(boost.to_string().contains("ore-miner-delegation"));
+
+        assert!(matches!(
+            Payload::parse(&[1; 33]).policy(now),
+            Policy::Refuse
+        ));
+    }
+
+    #[tokio::test]
+    async fn listens_only_where_rigs_are_trusted() {
+        assert!(bind_tcp("127.0.0.1:0", false).await.is_ok());
+        assert!(matches!(
+            bind_tcp("0.0.0.0:0", false).await,
+            Err(BindError::Remote(_))
+        ));
+        assert!(bind_tcp("0.0.0.0:0", true).await.is_ok());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let path =
+                std::env::temp_dir().join(format!("ore-hq-signer-{}.sock", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            let _listener = bind_unix(&path).unwrap();
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+
+    #[tokio::test]
+    async fn remote_signer_signs_mining_messages_and_refuses_claims_unconfirmed() {
+        let server = Arc::new(Server {
+            keypair: Keypair::new(),
+            interactive: false,
+            prompt: tokio::sync::Mutex::new(()),
+        });
+        let pubkey = server.keypair.pubkey();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = SignerAddress::Tcp(listener.local_addr().unwrap().to_string());
+        tokio::spawn(accept_tcp(listener, server));
+
+        let remote = tokio::task::spawn_blocking(move || RemoteSigner::connect(address).unwrap())
+            .await
+            .unwrap();
+        let wallet = Wallet::Remote(Arc::new(remote));
+        assert_eq!(wallet.pubkey(), pubkey);
+
+        let ready = ClientMessage::ready(&wallet, now()).await.unwrap();
+        assert!(ready.verify());
+        let solution = ClientMessage::best_solution(&wallet, [3; 16], 9)
+            .await
+            .unwrap();
+        assert!(solution.verify());
+
+        let claim = claim_message(&Pubkey::new_unique(), 1);
+        assert!(matches!(
+            wallet.sign(&claim).await,
+            Err(SignerError::UserCancel(_))
+        ));
+        // The refusal leaves the connection usable
+        assert!(wallet.sign(&now().to_le_bytes()).await.is_ok());
+
+        let mut tx = Transaction::new_with_payer(&[], Some(&pubkey));
+        assert!(matches!(
+            wallet.sign_transaction(&mut tx, Hash::new_unique()).await,
+            Err(SignerError::UserCancel(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn wallets_sign_their_place_in_a_transaction() {
+        let payer = Keypair::new();
+        let wallet = Wallet::from(Keypair::new());
+        let ix = ore_miner_delegation::instruction::delegate_boost_v2(
+            wallet.pubkey(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            1,
+        );
+        let mut tx = Transaction::new_with_payer(&[ix], Some(&payer.pubkey()));
+        let blockhash = Hash::new_unique();
+        tx.try_partial_sign(&[&payer], blockhash).unwrap();
+        wallet.sign_transaction(&mut tx, blockhash).await.unwrap();
+        assert!(tx.verify().is_ok());
+
+        let mut other = Transaction::new_with_payer(&[], Some(&payer.pubkey()));
+        assert!(matches!(
+            wallet.sign_transaction(&mut other, blockhash).await,
+            Err(SignerError::KeypairPubkeyMismatch)
+        ));
+    }
+}
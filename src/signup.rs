@@ -1,7 +1,14 @@
 use std::str::FromStr;
 
 use clap::Parser;
-use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+use serde::Serialize;
+use solana_sdk::{pubkey::Pubkey, signer::Signer};
+
+use crate::api::{PoolApi, SignupStatus};
+use crate::error::Error;
+use crate::output;
+use crate::signer::Wallet;
+use crate::textln;
 
 #[derive(Debug, Parser)]
 pub struct SignupArgs {
@@ -14,56 +21,44 @@ pub struct SignupArgs {
     pub pubkey: Option<String>,
 }
 
-pub async fn signup(args: SignupArgs, url: String, key: Keypair, unsecure: bool) {
+#[derive(Debug, Serialize)]
+struct SignupReport {
+    miner: String,
+    /// `signed_up` or `already_signed_up`
+    status: &'static str,
+}
+
+pub async fn signup(args: SignupArgs, url: String, key: Wallet, unsecure: bool) -> Result<(), Error> {
     let miner_pubkey = if args.pubkey.is_some() {
         match Pubkey::from_str(&args.pubkey.unwrap()) {
             Ok(pk) => pk,
             Err(_e) => {
-                println!("Invalid miner pubkey arg provided.");
-                return;
+                return Err(Error::Usage("Invalid miner pubkey arg provided.".to_string()));
             }
         }
     } else {
         key.pubkey()
     };
 
-    let base_url = url;
-
-    let client = reqwest::Client::new();
-
-    let url_prefix = if unsecure {
-        "http".to_string()
-    } else {
-        "https".to_string()
+    let api = PoolApi::new(&url, unsecure);
+    let status = match api.signup(&miner_pubkey).await {
+        Ok(SignupStatus::SignedUp) => {
+            textln!("  Successfully signed up!");
+            "signed_up"
+        }
+        Ok(SignupStatus::AlreadySignedUp) => {
+            textln!("  You're already signed up!");
+            "already_signed_up"
+        }
+        Err(e) => return Err(Error::api("Sign up failed, please wait and try again", e)),
     };
 
-    let resp = client
-        .post(format!(
-            "{}://{}/v2/signup?miner={}",
-            url_prefix,
-            base_url,
-            miner_pubkey.to_string(),
-        ))
-        .body("BLANK".to_string())
-        .send()
-        .await;
-    if let Ok(res) = resp {
-        if let Ok(txt) = res.text().await {
-            match txt.as_str() {
-                "SUCCESS" => {
-                    println!("  Successfully signed up!");
-                }
-                "EXISTS" => {
-                    println!("  You're already signed up!");
-                }
-                _ => {
-                    println!("  Transaction failed, please try again.");
-                }
-            }
-        } else {
-            println!("  Transaction failed, please wait and try again.");
-        }
-    } else {
-        println!("  Transaction failed, please wait and try again.");
-    }
+    output::emit(
+        "signup",
+        &SignupReport {
+            miner: miner_pubkey.to_string(),
+            status,
+        },
+    );
+    Ok(())
 }
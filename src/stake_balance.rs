@@ -1,68 +1,55 @@
-use solana_sdk::{signature::Keypair, signer::Signer};
+use serde::Serialize;
+use solana_sdk::{signer::Signer};
 
-pub async fn stake_balance(key: &Keypair, url: String, unsecure: bool) {
-    let base_url = url;
-    let client = reqwest::Client::new();
+use crate::api::{ApiError, PoolApi};
+use crate::error::Error;
+use crate::output;
+use crate::signer::Wallet;
+use crate::textln;
 
-    let url_prefix = if unsecure {
-        "http".to_string()
-    } else {
-        "https".to_string()
-    };
+#[derive(Debug, Serialize)]
+struct StakeBalanceReport {
+    wallet: String,
+    /// None when the wallet has no stake account
+    staked: Option<f64>,
+}
+
+pub async fn stake_balance(key: &Wallet, url: String, unsecure: bool) -> Result<(), Error> {
+    let api = PoolApi::new(&url, unsecure);
 
-    match client
-        .get(format!(
-            "{}://{}/miner/stake?pubkey={}",
-            url_prefix,
-            base_url,
-            key.pubkey().to_string()
-        ))
-        .send()
-        .await
-    {
-        Ok(response) => {
-            let balance = response.text().await.unwrap();
-            // Check if the balance failed to load
-            if balance.contains("Failed to g") {
-                println!("  Staked Balance: No staked account");
-            } else {
-                println!("  Staked Balance: {:.11} ORE", balance);
-            }
+    let staked = match api.stake(&key.pubkey()).await {
+        Ok(balance) => {
+            textln!("  Staked Balance: {:.11} ORE", balance);
+            Some(balance)
+        }
+        Err(ApiError::NoStakeAccount) => {
+            textln!("  Staked Balance: No staked account");
+            None
         }
         Err(e) => {
-            println!("  Error fetching stake balance: {:?}", e);
+            return Err(Error::api("Error fetching stake balance", e))
         }
-    }
+    };
+
+    output::emit(
+        "stake-balance",
+        &StakeBalanceReport {
+            wallet: key.pubkey().to_string(),
+            staked,
+        },
+    );
+    Ok(())
 }
 
-pub async fn get_staked_balance(key: &Keypair, url: String, unsecure: bool) -> f64 {
-    let base_url = url;
-    let client = reqwest::Client::new();
-    let url_prefix = if unsecure { "http" } else { "https" };
+pub async fn get_staked_balance(key: &Wallet, url: String, unsecure: bool) -> Result<f64, ApiError> {
+    let api = PoolApi::new(&url, unsecure);
 
-    match client
-        .get(format!(
-            "{}://{}/miner/stake?pubkey={}",
-            url_prefix,
-            base_url,
-            key.pubkey().to_string()
-        ))
-        .send()
-        .await
-    {
-        Ok(response) => {
-            let balance_str = response.text().await.unwrap();
-            if balance_str.contains("Failed to g") {
-                println!("  Delegated stake balance: No staked account");
-                0.0
-            } else {
-                balance_str.parse::<f64>().unwrap_or(0.0)
-            }
-        }
-        Err(e) => {
-            println!();
-            println!("  Error fetching stake balance: {:?}", e);
-            0.0
+    match api.stake(&key.pubkey()).await {
+        Ok(balance) => Ok(balance),
+        Err(ApiError::NoStakeAccount) => {
+            textln!("  Delegated stake balance: No staked account");
+            Ok(0.0)
         }
+        Err(e) => Err(e),
     }
 }
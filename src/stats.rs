@@ -1,4 +1,5 @@
 use colored::*;
+use log::info;
 use std::time::Instant;
 use once_cell::sync::Lazy;
 use std::sync::Mutex;
@@ -11,7 +12,7 @@ pub static GLOBAL_PASS_START_TIME: Lazy<Mutex<Instant>> = Lazy::new(|| Mutex::ne
 pub static GLOBAL_PASS_NUMBER: Lazy<Mutex<u64>> = Lazy::new(|| Mutex::new(0));
 pub fn set_global_pass_start_time(i: Instant, pass_number: u64) {
 	let mut global_pass_start_time = GLOBAL_PASS_START_TIME.lock().unwrap();
-	*global_pass_start_time=i.clone();
+	*global_pass_start_time=i;
 	let mut global_pass_number = GLOBAL_PASS_NUMBER.lock().unwrap();
 	*global_pass_number=pass_number;
 }
@@ -27,18 +28,18 @@ pub fn get_miner_accuracy() -> f64 {
 }
 pub fn record_miner_accuracy(accuracy: f64) {
 	let mut miner_accuracy_buffer = MINER_ACCURACY_BUFFER.lock().unwrap();
-	if accuracy>=-1000.0 && accuracy<=5_000_000.0 {
+	if (-1000.0..=5_000_000.0).contains(&accuracy) {
 		miner_accuracy_buffer.insert(accuracy);
-		println!("        Accuracy: {} {}\t\t\t[{} -> {} -> {}]", 
+		info!("        Accuracy: {} {}\t\t\t[{:.0} -> {} -> {}]", 
 			format!("{:.0}", accuracy).green(), ("ms").dimmed(),
-			format!("{:.0}", miner_accuracy_buffer.calculate_min()), 
+			miner_accuracy_buffer.calculate_min(), 
 			format!("{:.0}", miner_accuracy_buffer.calculate_median()).cyan(), 
 			format!("{:.0}", miner_accuracy_buffer.calculate_max()).green(),
 		);
 	} else {
-		println!("        Accuracy: {}{}\t{}", 
+		info!("        Accuracy: {}{}\t{}", 
 			format!("{:.0}", accuracy).green(), ("ms").dimmed(),
-			format!("Ignored as outwith tolerance").yellow(),
+			"Ignored as outwith tolerance".yellow(),
 		);
 	}
 }
@@ -0,0 +1,36 @@
+//! Database files for the tests, removed once the test is done with them.
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// A database path in the temp directory, free when created and deleted on drop.
+pub struct TempDb(PathBuf);
+
+impl TempDb {
+    pub fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("ore-hq-client-{}-{}.db3", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        TempDb(path)
+    }
+}
+
+impl Deref for TempDb {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDb {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
@@ -1,35 +1,44 @@
-use base64::{prelude::BASE64_STANDARD, Engine};
 use clap::Parser;
 use colored::*;
 use inquire::{InquireError, Text};
-use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer, transaction::Transaction};
+use serde::Serialize;
+use solana_sdk::{signer::Signer, transaction::Transaction};
 use spl_associated_token_account::get_associated_token_address;
-use std::str::FromStr;
 
+use crate::api::{ApiError, PoolApi};
+use crate::error::Error;
+use crate::output;
+use crate::signer::Wallet;
 use crate::stake_balance;
+use crate::textln;
 
 #[derive(Debug, Parser)]
 pub struct UnstakeArgs {
     #[arg(long, value_name = "AMOUNT", help = "Amount of ore to unstake.")]
     pub amount: f64,
+    #[arg(long, short, action, help = "Auto approve confirmations.")]
+    pub y: bool,
 }
 
-pub async fn undelegate_stake(args: UnstakeArgs, key: &Keypair, url: String, unsecure: bool) {
-    let base_url = url;
-    let client = reqwest::Client::new();
-    let url_prefix = if unsecure {
-        "http".to_string()
-    } else {
-        "https".to_string()
-    };
+#[derive(Debug, Serialize)]
+struct UnstakeReport {
+    amount: f64,
+    status: &'static str,
+}
+
+pub async fn undelegate_stake(args: UnstakeArgs, key: &Wallet, url: String, unsecure: bool) -> Result<(), Error> {
+    let api = PoolApi::new(&url, unsecure);
 
     // Fetch the staked balance
-    let staked_balance = stake_balance::get_staked_balance(&key, base_url.clone(), unsecure).await;
-    println!("  Current Staked Balance: {:.11} ORE", staked_balance);
+    let staked_balance = match stake_balance::get_staked_balance(key, url.clone(), unsecure).await {
+        Ok(balance) => balance,
+        Err(e) => return Err(Error::api("Error fetching stake balance", e)),
+    };
+    textln!("  Current Staked Balance: {:.11} ORE", staked_balance);
 
     // Ensure unstake amount does not exceed staked balance
     let unstake_amount = if args.amount > staked_balance {
-        println!(
+        textln!(
             "  Unstake amount exceeds staked balance. Defaulting to maximum available: {:.11} ORE",
             staked_balance
         );
@@ -39,117 +48,75 @@ pub async fn undelegate_stake(args: UnstakeArgs, key: &Keypair, url: String, uns
     };
 
     // Add confirmation step with red text before unstaking
-    match Text::new(
-        &format!(
-            "  Are you sure you want to unstake {} ORE? (Y/n or 'esc' to cancel)",
-            unstake_amount
+    if !args.y {
+        if output::json() {
+            return Err(Error::ConfirmationRequired);
+        }
+        match Text::new(
+            &format!(
+                "  Are you sure you want to unstake {} ORE? (Y/n or 'esc' to cancel)",
+                unstake_amount
+            )
+            .red()
+            .to_string(),
         )
-        .red()
-        .to_string(),
-    )
-    .prompt()
-    {
-        Ok(confirm) => {
-            if confirm.trim().eq_ignore_ascii_case("esc") {
-                println!("  Unstaking canceled.");
-                return;
-            } else if confirm.trim().is_empty() || confirm.trim().to_lowercase() == "y" {
-                // Proceed with unstaking
-            } else {
-                println!("  Unstaking canceled.");
-                return;
+        .prompt()
+        {
+            Ok(confirm) => {
+                if confirm.trim().eq_ignore_ascii_case("esc") {
+                    println!("  Unstaking canceled.");
+                    return Ok(());
+                } else if confirm.trim().is_empty() || confirm.trim().to_lowercase() == "y" {
+                    // Proceed with unstaking
+                } else {
+                    println!("  Unstaking canceled.");
+                    return Ok(());
+                }
+            }
+            Err(InquireError::OperationCanceled) => {
+                println!("  Unstaking operation canceled.");
+                return Ok(());
+            }
+            Err(_) => {
+                println!("  Invalid input. Unstaking canceled.");
+                return Ok(());
             }
-        }
-        Err(InquireError::OperationCanceled) => {
-            println!("  Unstaking operation canceled.");
-            return;
-        }
-        Err(_) => {
-            println!("  Invalid input. Unstaking canceled.");
-            return;
         }
     }
 
     // Continue with transaction
-    let resp = client
-        .get(format!(
-            "{}://{}/pool/authority/pubkey",
-            url_prefix, base_url
-        ))
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-    let pool_pubkey = Pubkey::from_str(&resp).unwrap();
-
-    let resp = client
-        .get(format!(
-            "{}://{}/pool/fee_payer/pubkey",
-            url_prefix, base_url
-        ))
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-    let fee_pubkey = Pubkey::from_str(&resp).unwrap();
+    match send_unstake(&api, key, unstake_amount).await {
+        Ok(()) => {
+            textln!("  Successfully unstaked!");
+            output::emit(
+                "unstake",
+                &UnstakeReport {
+                    amount: unstake_amount,
+                    status: "unstaked",
+                },
+            );
+            Ok(())
+        }
+        Err(e) => Err(Error::api("Transaction failed", e)),
+    }
+}
 
-    let resp = client
-        .get(format!("{}://{}/latest-blockhash", url_prefix, base_url))
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-    let decoded_blockhash = BASE64_STANDARD.decode(resp).unwrap();
-    let deserialized_blockhash = bincode::deserialize(&decoded_blockhash).unwrap();
+async fn send_unstake(api: &PoolApi, key: &Wallet, amount: f64) -> Result<(), ApiError> {
+    let pool_pubkey = api.pool_authority().await?;
+    let fee_pubkey = api.fee_payer().await?;
+    let blockhash = api.latest_blockhash().await?;
 
     let ata_address = get_associated_token_address(&key.pubkey(), &ore_api::consts::MINT_ADDRESS);
 
-    let unstake_amount_u64 =
-        (unstake_amount * 10f64.powf(ore_api::consts::TOKEN_DECIMALS as f64)) as u64;
+    let amount_u64 = (amount * 10f64.powf(ore_api::consts::TOKEN_DECIMALS as f64)) as u64;
     let ix = ore_miner_delegation::instruction::undelegate_stake(
         key.pubkey(),
         pool_pubkey,
         ata_address,
-        unstake_amount_u64,
+        amount_u64,
     );
 
     let mut tx = Transaction::new_with_payer(&[ix], Some(&fee_pubkey));
-    tx.partial_sign(&[&key], deserialized_blockhash);
-
-    let serialized_tx = bincode::serialize(&tx).unwrap();
-    let encoded_tx = BASE64_STANDARD.encode(&serialized_tx);
-
-    let resp = client
-        .post(format!(
-            "{}://{}/unstake?pubkey={}&amount={}",
-            url_prefix,
-            base_url,
-            key.pubkey().to_string(),
-            unstake_amount_u64
-        ))
-        .body(encoded_tx)
-        .send()
-        .await;
-    if let Ok(res) = resp {
-        if let Ok(txt) = res.text().await {
-            match txt.as_str() {
-                "SUCCESS" => {
-                    println!("  Successfully unstaked!");
-                }
-                other => {
-                    println!("  Transaction failed: {}", other);
-                }
-            }
-        } else {
-            println!("  Transaction failed, please wait and try again.");
-        }
-    } else {
-        println!("  Transaction failed, please wait and try again.");
-    }
+    key.sign_transaction(&mut tx, blockhash).await?;
+    api.unstake(&key.pubkey(), amount_u64, &tx).await
 }